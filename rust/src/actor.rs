@@ -0,0 +1,389 @@
+use crate::error::GaussError;
+use crate::parallel::{combine, finish, pool_for, update_row, Candidate, CANCEL_POLL};
+use crate::{check_dense, Data};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use threadpool_crossbeam::cancel::CancelToken;
+use threadpool_crossbeam::stats::{self, Stamp};
+
+pub const DEFAULT_BLOCK: usize = 4;
+
+// Messages to an actor.
+enum ToActor {
+    // Sent to the owner of row i: swap it with row irow and broadcast it.
+    Pivot { irow: usize },
+    // Sent to the owner of irow when row i lives on another actor.
+    SwapWith { i: usize, irow: usize },
+    // One side of a row exchange between two actors.
+    Row(Vec<f64>),
+    // Row i, pivoted and normalized.
+    PivotRow(Arc<Vec<f64>>),
+    // The run is over before the last phase; send the rows back.
+    Stop,
+}
+
+// Messages to the root.
+enum ToRoot {
+    Candidate(Candidate),
+    Rows(usize, Vec<Vec<f64>>),
+}
+
+// Rows are dealt out in blocks of `block` rows, round robin over the actors.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    pub nsize: usize,
+    pub block: usize,
+    pub nactors: usize,
+}
+
+impl Layout {
+    pub fn owner(&self, r: usize) -> usize {
+        (r / self.block) % self.nactors
+    }
+
+    // Position of row r among the rows of its owner.
+    pub fn local(&self, r: usize) -> usize {
+        r / (self.block * self.nactors) * self.block + r % self.block
+    }
+
+    // The rows owned by actor w, in order.
+    pub fn rows(&self, w: usize) -> impl Iterator<Item = usize> {
+        let l = *self;
+        (0..l.nsize).filter(move |&r| l.owner(r) == w)
+    }
+}
+
+// Number of rows each actor gets, as fill_sendcounts in c/gaussMPI.c.
+pub fn fill_sendcounts(l: &Layout) -> Vec<usize> {
+    let mut sendcounts = vec![0; l.nactors];
+    for r in 0..l.nsize {
+        sendcounts[l.owner(r)] += 1;
+    }
+    sendcounts
+}
+
+// Where each actor's rows start in the packed send buffer, as
+// fill_displacement in c/gaussMPI.c.
+pub fn fill_displacement(sendcounts: &[usize]) -> Vec<usize> {
+    let mut displ = vec![0; sendcounts.len()];
+    for l in 1..sendcounts.len() {
+        displ[l] = displ[l - 1] + sendcounts[l - 1];
+    }
+    displ
+}
+
+struct Actor {
+    id: usize,
+    l: Layout,
+    // Own rows with b appended, in Layout::local order.
+    rows: Vec<Vec<f64>>,
+    inbox: Receiver<ToActor>,
+    peers: Vec<Sender<ToActor>>,
+    root: Sender<ToRoot>,
+    // The pool's; an actor that panicked will not send what it owes.
+    cancel: CancelToken,
+}
+
+impl Actor {
+    fn row(&mut self, r: usize) -> &mut Vec<f64> {
+        &mut self.rows[self.l.local(r)]
+    }
+
+    fn recv(&self) -> Option<ToActor> {
+        loop {
+            match self.inbox.recv_timeout(CANCEL_POLL) {
+                Ok(ToActor::Stop) | Err(RecvTimeoutError::Disconnected) => return None,
+                Ok(msg) => return Some(msg),
+                Err(RecvTimeoutError::Timeout) if self.cancel.is_cancelled() => return None,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    fn recv_row(&self) -> Option<Vec<f64>> {
+        match self.recv()? {
+            ToActor::Row(row) => Some(row),
+            _ => unreachable!("expected a row"),
+        }
+    }
+
+    fn local_pivot(&self, i: usize) -> Candidate {
+        self.l
+            .rows(self.id)
+            .filter(|&r| r >= i)
+            .fold(Candidate::none(i), |best, r| {
+                combine(
+                    best,
+                    Candidate {
+                        big: self.rows[self.l.local(r)][i],
+                        irow: r,
+                    },
+                )
+            })
+    }
+
+    // Swaps row i in from wherever irow lives, normalizes it and sends it to
+    // everybody.
+    fn pivot(&mut self, i: usize, irow: usize) -> Option<Arc<Vec<f64>>> {
+        if irow != i {
+            let other = self.l.owner(irow);
+            if other == self.id {
+                let (a, b) = (self.l.local(i), self.l.local(irow));
+                self.rows.swap(a, b);
+            } else {
+                let row = std::mem::take(self.row(i));
+                self.peers[other].send(ToActor::Row(row)).unwrap();
+                *self.row(i) = self.recv_row()?;
+            }
+        }
+
+        let row = self.row(i);
+        let pivot_val = row[i];
+        if (pivot_val - 1.0).abs() > 0.0000001 {
+            row[i] = 1.0;
+            for x in row[i + 1..].iter_mut() {
+                *x /= pivot_val;
+            }
+        }
+        let pivot = Arc::new(row.clone());
+        for (w, peer) in self.peers.iter().enumerate() {
+            if w != self.id {
+                peer.send(ToActor::PivotRow(Arc::clone(&pivot))).unwrap();
+            }
+        }
+        Some(pivot)
+    }
+
+    // Works through the phases, returning None if the run is stopped early.
+    fn eliminate(&mut self) -> Option<()> {
+        for i in 0..self.l.nsize {
+            let start = Stamp::now();
+            self.root
+                .send(ToRoot::Candidate(self.local_pivot(i)))
+                .unwrap();
+            let mut busy = start.elapsed();
+
+            let pivot = match self.recv()? {
+                ToActor::Pivot { irow } => self.pivot(i, irow)?,
+                ToActor::SwapWith { i: currow, irow } => {
+                    let row = std::mem::take(self.row(irow));
+                    let owner = self.l.owner(currow);
+                    self.peers[owner].send(ToActor::Row(row)).unwrap();
+                    *self.row(irow) = self.recv_row()?;
+                    match self.recv()? {
+                        ToActor::PivotRow(pivot) => pivot,
+                        _ => return None,
+                    }
+                }
+                ToActor::PivotRow(pivot) => pivot,
+                _ => return None,
+            };
+
+            let start = Stamp::now();
+            let l = self.l;
+            for r in l.rows(self.id).filter(|&r| r > i) {
+                update_row(&pivot, &mut self.rows[l.local(r)], i);
+            }
+            busy += start.elapsed();
+            stats::busy(self.id, i, busy);
+        }
+        Some(())
+    }
+
+    fn run(mut self) {
+        self.eliminate();
+        self.root.send(ToRoot::Rows(self.id, self.rows)).unwrap();
+    }
+}
+
+// The best of the actors' candidates for column i, or whatever has come in
+// once the pool is cancelled. An actor that has given up on the run already
+// sends its rows back; they are kept in `early`.
+fn reduce(
+    root_rx: &Receiver<ToRoot>,
+    nactors: usize,
+    i: usize,
+    cancel: &CancelToken,
+    early: &mut Vec<ToRoot>,
+) -> Candidate {
+    let mut best = Candidate::none(i);
+    let mut heard = 0;
+    while heard < nactors && !cancel.is_cancelled() {
+        match root_rx.recv_timeout(CANCEL_POLL) {
+            Ok(ToRoot::Candidate(c)) => {
+                best = combine(best, c);
+                heard += 1;
+            }
+            Ok(rows) => early.push(rows),
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+    best
+}
+
+// Message-passing version of compute_gauss after c/gaussMPI.c. Each actor
+// owns a block-cyclic share of the rows and nothing else; the calling thread
+// plays MPI rank 0. Every phase the actors reduce their best pivot candidates
+// to the root, the root tells the owners of the two rows to swap, and the
+// owner of the pivot row broadcasts it. The rows are scattered once at the
+// start and gathered at the end.
+pub fn compute_gauss_actor(data: &mut Data, block: usize) -> Result<(), GaussError> {
+    assert!(block > 0);
+    check_dense(data)?;
+    // The rows go to the actors; a pool cancelled before they start would
+    // drop them.
+    if data.cancel.is_cancelled() {
+        return Err(GaussError::Cancelled);
+    }
+    let nactors = data.num_threads.max(1);
+    let l = Layout {
+        nsize: data.nsize,
+        block,
+        nactors,
+    };
+    let sendcounts = fill_sendcounts(&l);
+    let displ = fill_displacement(&sendcounts);
+
+    // Pack the rows actor by actor, as MPI_Scatterv expects them.
+    let mut sendbuf: Vec<Vec<f64>> = vec![Vec::new(); l.nsize];
+    let mut next = displ.clone();
+    for (r, mut row) in data.matrix.drain(..).enumerate() {
+        row.push(data.b[r]);
+        let w = l.owner(r);
+        sendbuf[next[w]] = row;
+        next[w] += 1;
+    }
+
+    let (root_tx, root_rx) = unbounded();
+    let (peers, inboxes): (Vec<_>, Vec<_>) = (0..nactors).map(|_| unbounded()).unzip();
+    let pool = pool_for(data).build();
+    for (w, inbox) in inboxes.into_iter().enumerate().rev() {
+        let rows = sendbuf.split_off(displ[w]);
+        let actor = Actor {
+            id: w,
+            l,
+            rows,
+            inbox,
+            peers: peers.clone(),
+            root: root_tx.clone(),
+            cancel: pool.cancel_token(),
+        };
+        pool.execute(move || actor.run());
+    }
+    drop(root_tx);
+
+    // The root's phases run from one reduction to the next. An actor that
+    // panicked has hung up, so sends to it are let fail; the cancelled pool
+    // stops the run at the next reduction.
+    let cancel = pool.cancel_token();
+    let mut error = None;
+    let mut early = Vec::new();
+    for i in 0..l.nsize {
+        let phase = Stamp::now();
+        let best = reduce(&root_rx, nactors, i, &cancel, &mut early);
+        if cancel.is_cancelled() {
+            error = Some(GaussError::Cancelled);
+        } else if best.big == 0.0 {
+            error = Some(GaussError::Singular(i));
+        }
+        if error.is_some() {
+            for peer in &peers {
+                let _ = peer.send(ToActor::Stop);
+            }
+            break;
+        }
+        data.swap.swap(best.irow, i);
+        if l.owner(best.irow) != l.owner(i) {
+            let _ = peers[l.owner(best.irow)].send(ToActor::SwapWith {
+                i,
+                irow: best.irow,
+            });
+        }
+        let _ = peers[l.owner(i)].send(ToActor::Pivot { irow: best.irow });
+        stats::phase(i, phase.elapsed());
+    }
+    let joined = pool.join();
+
+    // Gather, then unpack back into row order.
+    let mut recvbuf: Vec<Vec<f64>> = vec![Vec::new(); l.nsize];
+    for msg in early.into_iter().chain(root_rx.iter()) {
+        if let ToRoot::Rows(w, rows) = msg {
+            for (k, row) in rows.into_iter().enumerate() {
+                recvbuf[displ[w] + k] = row;
+            }
+        }
+    }
+    let mut next = displ;
+    for r in 0..l.nsize {
+        let w = l.owner(r);
+        let mut row = std::mem::take(&mut recvbuf[next[w]]);
+        next[w] += 1;
+        // An actor that panicked took its rows with it.
+        if row.is_empty() {
+            row = vec![f64::NAN; l.nsize + 1];
+        }
+        data.b[r] = row.pop().unwrap();
+        data.matrix.push(row);
+    }
+    joined?;
+    finish(&pool.cancel_token(), error.map_or(Ok(()), Err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::init;
+    use crate::parallel::compute_gauss_p;
+
+    #[test]
+    fn sendcounts_test() {
+        let l = Layout {
+            nsize: 10,
+            block: 2,
+            nactors: 3,
+        };
+        let sendcounts = fill_sendcounts(&l);
+        assert_eq!(sendcounts, [4, 4, 2]);
+        assert_eq!(fill_displacement(&sendcounts), [0, 4, 8]);
+        assert_eq!(l.rows(1).collect::<Vec<_>>(), [2, 3, 8, 9]);
+        assert_eq!(l.local(9), 3);
+    }
+
+    #[test]
+    fn actor_matches_pool() {
+        for &(nsize, num_threads, block) in &[(1, 2, 1), (23, 3, 2), (64, 4, 4), (50, 5, 64)] {
+            let mut pool = new_data(nsize, num_threads);
+            init(&mut pool);
+            pool.matrix[nsize / 2][nsize / 3] = -7.0 * nsize as f64;
+            pool.matrix[nsize - 1][nsize / 5] = 9.0 * nsize as f64;
+            let mut actor = new_data(nsize, num_threads);
+            actor.matrix = pool.matrix.clone();
+            actor.b = pool.b.clone();
+            actor.swap = pool.swap.clone();
+
+            compute_gauss_p(&mut pool).unwrap();
+            compute_gauss_actor(&mut actor, block).unwrap();
+
+            assert_eq!(pool.matrix, actor.matrix);
+            assert_eq!(pool.b, actor.b);
+            assert_eq!(pool.swap, actor.swap);
+        }
+    }
+
+    #[test]
+    fn actor_singular() {
+        let mut data = new_data(3, 2);
+        data.matrix = vec![
+            vec![1.0, 2.0, 1.0],
+            vec![2.0, 4.0, 2.0],
+            vec![3.0, 6.0, 3.0],
+        ];
+        data.b = vec![1.0, 2.0, 3.0];
+        data.swap = vec![0, 1, 2];
+        assert_eq!(compute_gauss_actor(&mut data, 1), Err(GaussError::Singular(1)));
+        assert_eq!(data.matrix[0], [1.0, 2.0, 1.0]);
+        assert_eq!(data.matrix[2], [0.0, 0.0, 0.0]);
+    }
+}
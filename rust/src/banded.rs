@@ -0,0 +1,434 @@
+use crate::error::GaussError;
+use crate::parallel::new_pool;
+use crate::{check_dense, Data};
+use std::ops::Range;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+// A matrix whose entries are zero more than kl below or ku above the
+// diagonal, stored by column like LAPACK's band storage. Each column keeps kl
+// extra entries above the band for the fill that row swaps cause, so the
+// eliminated U fits in place.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Banded {
+    pub nsize: usize,
+    pub kl: usize,
+    pub ku: usize,
+    // Entries per column: kl below the diagonal, kl + ku above it.
+    ld: usize,
+    vals: Vec<f64>,
+}
+
+impl Banded {
+    pub fn new(nsize: usize, kl: usize, ku: usize) -> Banded {
+        let ld = 2 * kl + ku + 1;
+        Banded {
+            nsize,
+            kl,
+            ku,
+            ld,
+            vals: vec![0.0; nsize * ld],
+        }
+    }
+
+    // Panics if `matrix` has an entry outside the band.
+    pub fn from_dense(matrix: &[Vec<f64>], kl: usize, ku: usize) -> Banded {
+        let mut a = Banded::new(matrix.len(), kl, ku);
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                if x != 0.0 {
+                    a.set(i, j, x);
+                }
+            }
+        }
+        a
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<f64>> {
+        (0..self.nsize)
+            .map(|i| (0..self.nsize).map(|j| self.get(i, j)).collect())
+            .collect()
+    }
+
+    // Where (i, j) is stored; the caller makes sure it is inside the band.
+    fn at(&self, i: usize, j: usize) -> usize {
+        j * (self.ld - 1) + i + self.kl + self.ku
+    }
+
+    fn stored(&self, i: usize, j: usize) -> bool {
+        i < self.nsize && j < self.nsize && j <= i + self.kl + self.ku && i <= j + self.kl
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        if self.stored(i, j) {
+            self.vals[self.at(i, j)]
+        } else {
+            0.0
+        }
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, x: f64) {
+        assert!(self.stored(i, j), "({}, {}) is outside the band", i, j);
+        let k = self.at(i, j);
+        self.vals[k] = x;
+    }
+}
+
+// The lower and upper bandwidth of a dense matrix: how far below and above
+// the diagonal its farthest nonzero entries are.
+pub fn bandwidth(matrix: &[Vec<f64>]) -> (usize, usize) {
+    let (mut kl, mut ku) = (0, 0);
+    for (i, row) in matrix.iter().enumerate() {
+        if let Some(first) = row.iter().position(|&x| x != 0.0) {
+            kl = kl.max(i.saturating_sub(first));
+        }
+        if let Some(last) = row.iter().rposition(|&x| x != 0.0) {
+            ku = ku.max(last.saturating_sub(i));
+        }
+    }
+    (kl, ku)
+}
+
+// compute_gauss on band storage: the pivot is chosen the same way, and a
+// phase only touches the kl rows below it and the kl + ku columns right of
+// it. O(n * kl * (kl + ku)) instead of O(n^3).
+pub fn eliminate(a: &mut Banded, b: &mut [f64], swap: &mut [u64]) -> Result<(), GaussError> {
+    let n = a.nsize;
+    for i in 0..n {
+        let last_row = (i + a.kl).min(n - 1);
+        let last_col = (i + a.kl + a.ku).min(n - 1);
+
+        let irow = if a.get(i, i) != 0.0 {
+            i
+        } else {
+            (i + 1..=last_row)
+                .find(|&r| a.get(r, i) != 0.0)
+                .ok_or(GaussError::Singular(i))?
+        };
+        if irow != i {
+            for j in i..=last_col {
+                let (x, y) = (a.at(i, j), a.at(irow, j));
+                a.vals.swap(x, y);
+            }
+            b.swap(irow, i);
+            swap.swap(irow, i);
+        }
+
+        let pivot_val = a.get(i, i);
+        if (pivot_val - 1.0).abs() > 0.0000001 {
+            a.set(i, i, 1.0);
+            for j in i + 1..=last_col {
+                let k = a.at(i, j);
+                a.vals[k] /= pivot_val;
+            }
+            b[i] /= pivot_val;
+        }
+
+        for r in i + 1..=last_row {
+            let k = a.at(r, i);
+            let pivot_val = a.vals[k];
+            a.vals[k] = 0.0;
+            for j in i + 1..=last_col {
+                let p = a.vals[a.at(i, j)];
+                let k = a.at(r, j);
+                a.vals[k] -= pivot_val * p;
+            }
+            b[r] -= pivot_val * b[i];
+        }
+    }
+    Ok(())
+}
+
+// Back substitution on the U left by eliminate.
+pub fn solve_banded(a: &Banded, b: &[f64]) -> Vec<f64> {
+    let n = a.nsize;
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let last_col = (i + a.kl + a.ku).min(n - 1);
+        let y: f64 = (i + 1..=last_col).map(|j| a.get(i, j) * x[j]).sum();
+        x[i] = (b[i] - y) / a.get(i, i);
+    }
+    x
+}
+
+// Drop-in for compute_gauss: detects the bandwidth of data.matrix, eliminates
+// in band storage and writes the band back, leaving data as compute_gauss
+// would.
+pub fn compute_gauss_banded(data: &mut Data) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let (kl, ku) = bandwidth(&data.matrix);
+    let mut a = Banded::from_dense(&data.matrix, kl, ku);
+    let result = eliminate(&mut a, &mut data.b, &mut data.swap);
+    for (i, row) in data.matrix.iter_mut().enumerate() {
+        let last_col = (i + kl + ku).min(data.nsize - 1);
+        for (j, x) in row.iter_mut().enumerate().take(last_col + 1).skip(i.saturating_sub(kl)) {
+            *x = a.get(i, j);
+        }
+    }
+    result
+}
+
+// A tridiagonal matrix by its three diagonals: row i is
+// lower[i] x[i - 1] + diag[i] x[i] + upper[i] x[i + 1], with lower[0] and
+// upper[n - 1] zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tridiagonal {
+    pub lower: Vec<f64>,
+    pub diag: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+impl Tridiagonal {
+    // None unless every entry of `matrix` is on the three diagonals.
+    pub fn from_dense(matrix: &[Vec<f64>]) -> Option<Tridiagonal> {
+        let (kl, ku) = bandwidth(matrix);
+        if kl > 1 || ku > 1 {
+            return None;
+        }
+        let n = matrix.len();
+        let at = |i: usize, j: Option<usize>| j.filter(|&j| j < n).map_or(0.0, |j| matrix[i][j]);
+        Some(Tridiagonal {
+            lower: (0..n).map(|i| at(i, i.checked_sub(1))).collect(),
+            diag: (0..n).map(|i| matrix[i][i]).collect(),
+            upper: (0..n).map(|i| at(i, Some(i + 1))).collect(),
+        })
+    }
+}
+
+// The Thomas algorithm: elimination without pivoting, down and back up the
+// three diagonals. A zero pivot on the way is reported as Singular, so it is
+// meant for diagonally dominant or positive definite systems.
+pub fn thomas(t: &Tridiagonal, d: &[f64]) -> Result<Vec<f64>, GaussError> {
+    let n = d.len();
+    let mut upper = vec![0.0; n];
+    let mut x = vec![0.0; n];
+    let mut prev_upper = 0.0;
+    let mut prev_x = 0.0;
+    for i in 0..n {
+        let m = t.diag[i] - t.lower[i] * prev_upper;
+        if m == 0.0 {
+            return Err(GaussError::Singular(i));
+        }
+        upper[i] = t.upper[i] / m;
+        x[i] = (d[i] - t.lower[i] * prev_x) / m;
+        prev_upper = upper[i];
+        prev_x = x[i];
+    }
+    for i in (0..n.saturating_sub(1)).rev() {
+        x[i] -= upper[i] * x[i + 1];
+    }
+    Ok(x)
+}
+
+// One step of cyclic reduction for equations `rows`: equation i takes in
+// equations i - s and i + s, which removes x[i - s] and x[i + s] and couples
+// it to x[i - 2s] and x[i + 2s] instead. An equation is [lower, diag, upper,
+// rhs].
+fn reduce(eq: &[[f64; 4]], rows: Range<usize>, s: usize) -> Result<Vec<[f64; 4]>, GaussError> {
+    let n = eq.len();
+    rows.map(|i| {
+        let [lower, diag, upper, rhs] = eq[i];
+        let mut out = [0.0, diag, 0.0, rhs];
+        if i >= s {
+            let [l, d, u, r] = eq[i - s];
+            if d == 0.0 {
+                return Err(GaussError::Singular(i - s));
+            }
+            let k = lower / d;
+            out[0] = -l * k;
+            out[1] -= u * k;
+            out[3] -= r * k;
+        }
+        if i + s < n {
+            let [l, d, u, r] = eq[i + s];
+            if d == 0.0 {
+                return Err(GaussError::Singular(i + s));
+            }
+            let k = upper / d;
+            out[2] = -u * k;
+            out[1] -= l * k;
+            out[3] -= r * k;
+        }
+        Ok(out)
+    })
+    .collect()
+}
+
+// Parallel cyclic reduction: log2(n) steps that each reduce every equation
+// at once, split over the pool in contiguous chunks, until every equation
+// holds a single unknown. Like thomas it does not pivot.
+pub fn cyclic_reduction(t: &Tridiagonal, d: &[f64], num_threads: usize) -> Result<Vec<f64>, GaussError> {
+    let n = d.len();
+    let num_threads = num_threads.max(1);
+    let pool = new_pool(num_threads);
+    let chunk = n.div_ceil(num_threads).max(1);
+    let mut eq: Arc<Vec<[f64; 4]>> = Arc::new(
+        (0..n)
+            .map(|i| [t.lower[i], t.diag[i], t.upper[i], d[i]])
+            .collect(),
+    );
+
+    let mut s = 1;
+    while s < n {
+        if pool.cancel_token().is_cancelled() {
+            return Err(GaussError::Cancelled);
+        }
+        let (tx, rx) = channel();
+        for start in (0..n).step_by(chunk) {
+            let eq = Arc::clone(&eq);
+            let tx = tx.clone();
+            pool.execute(move || {
+                let part = reduce(&eq, start..(start + chunk).min(n), s);
+                tx.send((start, part)).expect("reduction result channel closed");
+            });
+        }
+        drop(tx);
+        pool.join()?;
+        let mut next = vec![[0.0; 4]; n];
+        for (start, part) in rx {
+            let part = part?;
+            next[start..start + part.len()].copy_from_slice(&part);
+        }
+        eq = Arc::new(next);
+        s *= 2;
+    }
+
+    eq.iter()
+        .enumerate()
+        .map(|(i, &[_, diag, _, rhs])| {
+            if diag == 0.0 {
+                Err(GaussError::Singular(i))
+            } else {
+                Ok(rhs / diag)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{new_data, Lcg};
+    use crate::{compute_gauss, init};
+
+    // A matrix with lower bandwidth kl and upper bandwidth ku whose
+    // diagonal is zero every third row, so elimination has to swap.
+    fn band_matrix(nsize: usize, kl: usize, ku: usize) -> Vec<Vec<f64>> {
+        let mut rng = Lcg::new(7);
+        let mut matrix = vec![vec![0.0; nsize]; nsize];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                if j + kl >= i && j <= i + ku && !(i == j && i % 3 == 1) {
+                    let seed = rng.next_u64();
+                    let sign = if seed >> 63 == 0 { 1.0 } else { -1.0 };
+                    *x = sign * ((seed >> 40) % 9 + 1) as f64;
+                }
+            }
+        }
+        matrix
+    }
+
+    fn tridiagonal(nsize: usize) -> Tridiagonal {
+        Tridiagonal {
+            lower: (0..nsize).map(|i| if i == 0 { 0.0 } else { -1.0 - (i % 3) as f64 }).collect(),
+            diag: (0..nsize).map(|i| 6.0 + (i % 5) as f64).collect(),
+            upper: (0..nsize).map(|i| if i + 1 == nsize { 0.0 } else { -2.0 }).collect(),
+        }
+    }
+
+    #[test]
+    fn detects_bandwidth() {
+        let matrix = band_matrix(12, 2, 3);
+        assert_eq!(bandwidth(&matrix), (2, 3));
+        assert_eq!(Banded::from_dense(&matrix, 2, 3).to_dense(), matrix);
+        assert!(Tridiagonal::from_dense(&matrix).is_none());
+        assert!(Tridiagonal::from_dense(&band_matrix(12, 1, 1)).is_some());
+
+        let mut data = new_data(10, 1);
+        init(&mut data);
+        assert_eq!(bandwidth(&data.matrix), (9, 9));
+    }
+
+    #[test]
+    fn banded_matches_dense() {
+        for &(kl, ku) in &[(1, 1), (2, 1), (1, 3), (3, 2)] {
+            let nsize = 40;
+            let matrix = band_matrix(nsize, kl, ku);
+            let mut dense = new_data(nsize, 1);
+            dense.matrix = matrix.clone();
+            dense.b = (0..nsize).map(|i| i as f64).collect();
+            dense.swap = (0..nsize as u64).collect();
+            let mut banded = new_data(nsize, 1);
+            banded.matrix = matrix.clone();
+            banded.b = dense.b.clone();
+            banded.swap = dense.swap.clone();
+
+            compute_gauss(&mut dense);
+            compute_gauss_banded(&mut banded).unwrap();
+            assert_eq!(banded.matrix, dense.matrix);
+            assert_eq!(banded.b, dense.b);
+            assert_eq!(banded.swap, dense.swap);
+
+            // The band storage solves the original system.
+            let mut a = Banded::from_dense(&matrix, kl, ku);
+            let mut b: Vec<f64> = (0..nsize).map(|i| i as f64).collect();
+            let mut swap: Vec<u64> = (0..nsize as u64).collect();
+            eliminate(&mut a, &mut b, &mut swap).unwrap();
+            let x = solve_banded(&a, &b);
+            for (i, row) in matrix.iter().enumerate() {
+                let ax: f64 = row.iter().zip(&x).map(|(a, x)| a * x).sum();
+                assert!((ax - i as f64).abs() < 1e-8, "row {}: {} != {}", i, ax, i);
+            }
+        }
+    }
+
+    #[test]
+    fn banded_singular() {
+        let matrix = vec![
+            vec![1.0, 2.0, 0.0],
+            vec![2.0, 4.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+        ];
+        let mut data = new_data(3, 1);
+        data.matrix = matrix;
+        data.b = vec![1.0, 2.0, 3.0];
+        data.swap = vec![0, 1, 2];
+        assert_eq!(compute_gauss_banded(&mut data), Err(GaussError::Singular(1)));
+    }
+
+    #[test]
+    fn tridiagonal_solvers_agree() {
+        for &nsize in &[1, 2, 7, 1000] {
+            let t = tridiagonal(nsize);
+            let x: Vec<f64> = (0..nsize).map(|i| (i % 7) as f64 - 3.0).collect();
+            let d: Vec<f64> = (0..nsize)
+                .map(|i| {
+                    let mut y = t.diag[i] * x[i];
+                    if i > 0 {
+                        y += t.lower[i] * x[i - 1];
+                    }
+                    if i + 1 < nsize {
+                        y += t.upper[i] * x[i + 1];
+                    }
+                    y
+                })
+                .collect();
+            for solved in [thomas(&t, &d).unwrap(), cyclic_reduction(&t, &d, 3).unwrap()] {
+                for (a, b) in solved.iter().zip(&x) {
+                    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tridiagonal_zero_pivot() {
+        let t = Tridiagonal {
+            lower: vec![0.0, 1.0],
+            diag: vec![0.0, 1.0],
+            upper: vec![1.0, 0.0],
+        };
+        assert_eq!(thomas(&t, &[1.0, 1.0]), Err(GaussError::Singular(0)));
+        assert_eq!(cyclic_reduction(&t, &[1.0, 1.0], 2), Err(GaussError::Singular(0)));
+    }
+}
@@ -0,0 +1,673 @@
+use crate::error::GaussError;
+use crate::parallel::{
+    finish, local_pivot, pool_for, set_pivot, update_row, SharedMatrix,
+};
+use crate::{check_dense, Data};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+pub use threadpool_crossbeam::channel::{
+    Backend, Crossbeam, LockFree, Receiver, RingBuf, Sender, Std,
+};
+use threadpool_crossbeam::stats::{self, ChannelStats, Stamp};
+use threadpool_crossbeam::trace;
+
+// Fixed-size FIFO queue, as CircularBuffer in flix/src/ChannelImpl.
+#[derive(Debug)]
+pub struct CircularBuffer<T> {
+    buf: Vec<Option<T>>,
+    start: usize,
+    end: usize,
+    count: usize,
+}
+
+impl<T> CircularBuffer<T> {
+    pub fn new(capacity: usize) -> CircularBuffer<T> {
+        CircularBuffer {
+            buf: (0..capacity).map(|_| None).collect(),
+            start: 0,
+            end: 0,
+            count: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count == self.capacity()
+    }
+
+    // Appends x, or hands it back if the buffer is full.
+    pub fn put(&mut self, x: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(x);
+        }
+        self.buf[self.end] = Some(x);
+        self.end = (self.end + 1) % self.capacity();
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn try_get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let x = self.buf[self.start].take();
+        self.start = (self.start + 1) % self.capacity();
+        self.count -= 1;
+        x
+    }
+
+    pub fn get(&mut self) -> T {
+        self.try_get().expect("the buffer is empty")
+    }
+}
+
+// What a blocked get or select sleeps on. The Flix version pairs a lock with
+// a condition and holds the lock while registering; the flag does the same
+// job here, so a put that comes in before the wait is not lost.
+struct Waiter {
+    ready: Mutex<bool>,
+    cv: Condvar,
+}
+
+impl Waiter {
+    fn new() -> Arc<Waiter> {
+        Arc::new(Waiter {
+            ready: Mutex::new(false),
+            cv: Condvar::new(),
+        })
+    }
+
+    fn notify(&self) {
+        *self.ready.lock().unwrap() = true;
+        self.cv.notify_all();
+    }
+
+    fn wait(&self) {
+        let _span = trace::span("get", "flix");
+        let mut ready = self.ready.lock().unwrap();
+        while !*ready {
+            ready = self.cv.wait(ready).unwrap();
+        }
+        *ready = false;
+    }
+}
+
+struct State<T> {
+    queue: CircularBuffer<T>,
+    // Cleared on every put; a getter that loses the race registers again.
+    waiting_getters: Vec<Arc<Waiter>>,
+    // Elements put and taken so far, so an unbuffered put can tell when its
+    // element has been handed off.
+    puts: u64,
+    gets: u64,
+}
+
+struct Inner<T> {
+    id: u64,
+    unbuffered: bool,
+    state: Mutex<State<T>>,
+    waiting_setters: Condvar,
+}
+
+// Ids give select a global order to lock channels in.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Port of the Mpmc channel in flix/src/ChannelImpl/ChannelImpl.flix: a
+// circular buffer behind one lock, a condition for blocked setters and a
+// list of conditions for blocked getters, which select also joins.
+pub struct Channel<T>(Arc<Inner<T>>);
+
+impl<T> Clone for Channel<T> {
+    fn clone(&self) -> Channel<T> {
+        Channel(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Channel<T> {
+    // A buffer size of 0 makes an unbuffered channel: put waits until its
+    // element has been taken.
+    pub fn new(buffer_size: usize) -> Channel<T> {
+        Channel(Arc::new(Inner {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            unbuffered: buffer_size == 0,
+            state: Mutex::new(State {
+                queue: CircularBuffer::new(buffer_size.max(1)),
+                waiting_getters: Vec::new(),
+                puts: 0,
+                gets: 0,
+            }),
+            waiting_setters: Condvar::new(),
+        }))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.0.state.lock().unwrap()
+    }
+
+    // Takes an element from a channel that is already locked.
+    fn take(&self, s: &mut State<T>) -> Option<T> {
+        let x = s.queue.try_get()?;
+        s.gets += 1;
+        self.0.waiting_setters.notify_all();
+        Some(x)
+    }
+
+    pub fn put(&self, x: T) {
+        let mut s = self.lock();
+        while s.queue.is_full() {
+            let _span = trace::span("put", "flix");
+            s = self.0.waiting_setters.wait(s).unwrap();
+        }
+        if s.queue.put(x).is_err() {
+            unreachable!("put into a full buffer");
+        }
+        s.puts += 1;
+        for w in s.waiting_getters.drain(..) {
+            w.notify();
+        }
+
+        if self.0.unbuffered {
+            let ticket = s.puts;
+            while s.gets < ticket {
+                let _span = trace::span("put", "flix");
+                s = self.0.waiting_setters.wait(s).unwrap();
+            }
+        }
+    }
+
+    pub fn get(&self) -> T {
+        let mut s = self.lock();
+        loop {
+            if let Some(x) = self.take(&mut s) {
+                return x;
+            }
+            let w = Waiter::new();
+            s.waiting_getters.push(Arc::clone(&w));
+            drop(s);
+            w.wait();
+            s = self.lock();
+        }
+    }
+
+    pub fn try_get(&self) -> Option<T> {
+        self.take(&mut self.lock())
+    }
+}
+
+// Receives from whichever of `channels` has an element first, returning its
+// index with the element, as selectImpl does. With `has_default` it returns
+// None instead of blocking when all of them are empty. The channels are
+// locked in id order so two selects over the same channels cannot deadlock.
+// Unlike selectImpl, only the chosen channel gives up an element.
+pub fn select<T>(channels: &[&Channel<T>], has_default: bool) -> Option<(usize, T)> {
+    let mut order: Vec<usize> = (0..channels.len()).collect();
+    order.sort_by_key(|&k| channels[k].id());
+    order.dedup_by_key(|k| channels[*k].id());
+    let waiter = Waiter::new();
+
+    loop {
+        let mut locked: Vec<(usize, MutexGuard<'_, State<T>>)> =
+            order.iter().map(|&k| (k, channels[k].lock())).collect();
+        locked.sort_by_key(|&(k, _)| k);
+
+        for (k, s) in locked.iter_mut() {
+            if let Some(x) = channels[*k].take(s) {
+                return Some((*k, x));
+            }
+        }
+        if has_default {
+            return None;
+        }
+
+        for (_, s) in locked.iter_mut() {
+            s.waiting_getters.push(Arc::clone(&waiter));
+        }
+        drop(locked);
+        waiter.wait();
+    }
+}
+
+// Same rule as parallel::pivot_p, the largest entry in the column, so the
+// strategies agree on the numbers. The scan is serial: the rows are only read
+// between phases.
+fn pivot_largest(
+    m: &SharedMatrix,
+    swap: &mut [u64],
+    currow: usize,
+) -> Result<(), GaussError> {
+    let _span = trace::span("pivot", "gauss").phase(currow);
+    let best = local_pivot(m, currow, 0, 1);
+    if best.big == 0.0 {
+        return Err(GaussError::Singular(currow));
+    }
+    set_pivot(m, swap, best, currow);
+    Ok(())
+}
+
+// How many rows a channel can hold back before a send waits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capacity {
+    // Every send waits for its receiver.
+    Rendezvous,
+    Fixed(usize),
+    Unbounded,
+}
+
+impl FromStr for Capacity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Capacity, String> {
+        match s {
+            "rendezvous" | "0" => Ok(Capacity::Rendezvous),
+            "unbounded" => Ok(Capacity::Unbounded),
+            _ => s
+                .parse()
+                .map(Capacity::Fixed)
+                .map_err(|_| format!("unknown capacity {}", s)),
+        }
+    }
+}
+
+// How the rows of a phase go out over the channel: its capacity, counted in
+// messages, and how many consecutive rows one message carries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dispatch {
+    pub capacity: Capacity,
+    pub batch: usize,
+}
+
+impl Default for Dispatch {
+    fn default() -> Dispatch {
+        Dispatch {
+            capacity: Capacity::Unbounded,
+            batch: 1,
+        }
+    }
+}
+
+impl Dispatch {
+    fn channel<C: Backend, T: Send + 'static>(&self) -> (C::Sender<T>, C::Receiver<T>) {
+        match self.capacity {
+            Capacity::Rendezvous => C::bounded(0),
+            Capacity::Fixed(n) => C::bounded(n),
+            Capacity::Unbounded => C::unbounded(),
+        }
+    }
+
+    // The rows below pivot i, cut into messages.
+    fn batches(&self, i: usize, nsize: usize) -> impl Iterator<Item = Range<usize>> {
+        let batch = self.batch.max(1);
+        (i + 1..nsize)
+            .step_by(batch)
+            .map(move |j| j..(j + batch).min(nsize))
+    }
+}
+
+fn channel_worker(m: &SharedMatrix, i: usize, w: usize, rows: &Channel<Range<usize>>) {
+    let counts = ChannelStats::new("rows");
+    let _span = trace::span("update", "gauss").phase(i);
+    let mut busy = Duration::from_secs(0);
+    let pivot = m.rows[i].read().unwrap();
+    while let Some(batch) = rows.try_get() {
+        counts.received(Duration::from_secs(0));
+        let start = Stamp::now();
+        for j in batch {
+            update_row(&pivot, &mut m.rows[j].write().unwrap(), i);
+        }
+        busy += start.elapsed();
+    }
+    stats::busy(w, i, busy);
+}
+
+// compute_gauss on the channel above, after flix/src/GaussParallelFlix.flix:
+// every phase the rows below the pivot are put into a fresh channel and the
+// workers drain it with try_get until it is empty. The channel is filled
+// before the workers start, so it is always made big enough for the phase
+// and only the batch size of `dispatch` applies.
+pub fn compute_gauss_channel(data: &mut Data, dispatch: Dispatch) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let num_threads = data.num_threads.max(1);
+    let nsize = data.nsize;
+    let pool = pool_for(data).build();
+    let m = Arc::new(SharedMatrix::from_data(data));
+    let cancel = pool.cancel_token();
+
+    let counts = ChannelStats::new("rows");
+    let result = (0..nsize).try_for_each(|i| {
+        if cancel.is_cancelled() {
+            return Err(GaussError::Cancelled);
+        }
+        let start = Stamp::now();
+        pivot_largest(&m, &mut data.swap, i)?;
+        let batches: Vec<Range<usize>> = dispatch.batches(i, nsize).collect();
+        let rows = Channel::new(batches.len());
+        for batch in batches {
+            let put = Stamp::now();
+            rows.put(batch);
+            counts.sent(put.elapsed());
+        }
+        for w in 0..num_threads {
+            let m = Arc::clone(&m);
+            let rows = rows.clone();
+            pool.execute(move || channel_worker(&m, i, w, &rows));
+        }
+        pool.join()?;
+        stats::phase(i, start.elapsed());
+        Ok(())
+    });
+
+    Arc::try_unwrap(m).unwrap().into_data(data);
+    finish(&cancel, result)
+}
+
+fn backend_worker<R: Receiver<Range<usize>>>(m: &SharedMatrix, i: usize, w: usize, rows: &R) {
+    let _span = trace::span("update", "gauss").phase(i);
+    let mut busy = Duration::from_secs(0);
+    let pivot = m.rows[i].read().unwrap();
+    while let Ok(batch) = rows.recv() {
+        let start = Stamp::now();
+        for j in batch {
+            update_row(&pivot, &mut m.rows[j].write().unwrap(), i);
+        }
+        busy += start.elapsed();
+    }
+    stats::busy(w, i, busy);
+}
+
+// compute_gauss_channel on a channel of backend C. The workers are started
+// first and the rows of a phase sent after them, so a channel smaller than
+// the phase cannot block the sender; closing it stops the workers at the
+// disconnect rather than at the first empty try_get. The pool queues its jobs
+// on C too.
+pub fn compute_gauss_channel_on<C: Backend>(
+    data: &mut Data,
+    dispatch: Dispatch,
+) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let num_threads = data.num_threads.max(1);
+    let nsize = data.nsize;
+    let pool = pool_for(data).build_with_channel::<C>();
+    let m = Arc::new(SharedMatrix::from_data(data));
+    let cancel = pool.cancel_token();
+
+    let result = (0..nsize).try_for_each(|i| {
+        if cancel.is_cancelled() {
+            return Err(GaussError::Cancelled);
+        }
+        let start = Stamp::now();
+        pivot_largest(&m, &mut data.swap, i)?;
+        let (tx, rx) = stats::counted("rows", dispatch.channel::<C, Range<usize>>());
+        for w in 0..num_threads {
+            let m = Arc::clone(&m);
+            let rx = rx.clone();
+            pool.execute(move || backend_worker(&m, i, w, &rx));
+        }
+        drop(rx);
+        // The sends fail once the pool has dropped the workers after a
+        // cancel or a panic; join and finish tell which it was.
+        let mut dispatched = Ok(());
+        for batch in dispatch.batches(i, nsize) {
+            if tx.send(batch).is_err() {
+                dispatched = Err(GaussError::Cancelled);
+                break;
+            }
+        }
+        tx.close();
+        pool.join()?;
+        dispatched?;
+        stats::phase(i, start.elapsed());
+        Ok(())
+    });
+
+    Arc::try_unwrap(m).unwrap().into_data(data);
+    finish(&cancel, result)
+}
+
+// Which channel the channel strategy runs on. Flix is the hand-written
+// channel above; the others are pool crate backends.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChannelKind {
+    Flix,
+    Std,
+    #[default]
+    Crossbeam,
+    RingBuf,
+    LockFree,
+}
+
+impl FromStr for ChannelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ChannelKind, String> {
+        match s {
+            "flix" => Ok(ChannelKind::Flix),
+            "std" => Ok(ChannelKind::Std),
+            "crossbeam" => Ok(ChannelKind::Crossbeam),
+            "ringbuf" => Ok(ChannelKind::RingBuf),
+            "lockfree" => Ok(ChannelKind::LockFree),
+            _ => Err(format!("unknown channel {}", s)),
+        }
+    }
+}
+
+pub fn compute_gauss_channel_with(
+    data: &mut Data,
+    kind: ChannelKind,
+    dispatch: Dispatch,
+) -> Result<(), GaussError> {
+    match kind {
+        ChannelKind::Flix => compute_gauss_channel(data, dispatch),
+        ChannelKind::Std => compute_gauss_channel_on::<Std>(data, dispatch),
+        ChannelKind::Crossbeam => compute_gauss_channel_on::<Crossbeam>(data, dispatch),
+        ChannelKind::RingBuf => compute_gauss_channel_on::<RingBuf>(data, dispatch),
+        ChannelKind::LockFree => compute_gauss_channel_on::<LockFree>(data, dispatch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parallel::compute_gauss_p;
+    use crate::test_util::new_data;
+    use crate::{compute_gauss, init};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn circular_buffer_wraps() {
+        let mut cb = CircularBuffer::new(3);
+        for x in 0..3 {
+            assert_eq!(cb.put(x), Ok(()));
+        }
+        assert!(cb.is_full());
+        assert_eq!(cb.put(3), Err(3));
+        assert_eq!(cb.get(), 0);
+        assert_eq!(cb.put(3), Ok(()));
+        assert_eq!(cb.len(), 3);
+        assert_eq!((cb.get(), cb.get(), cb.get()), (1, 2, 3));
+        assert_eq!(cb.try_get(), None);
+    }
+
+    #[test]
+    fn channel_mpmc() {
+        let c = Channel::new(4);
+        let putters: Vec<_> = (0..4)
+            .map(|p| {
+                let c = c.clone();
+                thread::spawn(move || {
+                    for x in 0..1000u64 {
+                        c.put(p * 1000 + x);
+                    }
+                })
+            })
+            .collect();
+        let getters: Vec<_> = (0..4)
+            .map(|_| {
+                let c = c.clone();
+                thread::spawn(move || (0..1000).map(|_| c.get()).collect::<Vec<u64>>())
+            })
+            .collect();
+        for p in putters {
+            p.join().unwrap();
+        }
+        let mut got: Vec<u64> = getters.into_iter().flat_map(|g| g.join().unwrap()).collect();
+        got.sort_unstable();
+        assert_eq!(got, (0..4000).collect::<Vec<u64>>());
+        assert_eq!(c.try_get(), None);
+    }
+
+    #[test]
+    fn unbuffered_put_waits() {
+        let c = Channel::new(0);
+        let taken = Arc::new(Mutex::new(false));
+        let getter = {
+            let (c, taken) = (c.clone(), Arc::clone(&taken));
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                *taken.lock().unwrap() = true;
+                c.get()
+            })
+        };
+        c.put(7);
+        assert!(*taken.lock().unwrap());
+        assert_eq!(getter.join().unwrap(), 7);
+    }
+
+    #[test]
+    fn select_test() {
+        let (a, b) = (Channel::new(1), Channel::new(1));
+        assert_eq!(select(&[&a, &b], true), None);
+        b.put(2);
+        a.put(1);
+        assert_eq!(select(&[&b, &a], true), Some((0, 2)));
+        assert_eq!(a.try_get(), Some(1));
+
+        let putter = {
+            let b = b.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                b.put(5);
+            })
+        };
+        assert_eq!(select(&[&a, &b, &a], false), Some((1, 5)));
+        putter.join().unwrap();
+    }
+
+    #[test]
+    fn channel_matches_pool() {
+        for num_threads in 1..5 {
+            let nsize = 61;
+            let mut seq = new_data(nsize, num_threads);
+            init(&mut seq);
+            seq.matrix[3][3] = 0.0;
+            let mut par = new_data(nsize, num_threads);
+            par.matrix = seq.matrix.clone();
+            par.b = seq.b.clone();
+            par.swap = seq.swap.clone();
+
+            compute_gauss_p(&mut seq).unwrap();
+            compute_gauss_channel(&mut par, Dispatch::default()).unwrap();
+
+            assert_eq!(seq.matrix, par.matrix);
+            assert_eq!(seq.b, par.b);
+            assert_eq!(seq.swap, par.swap);
+        }
+    }
+
+    #[test]
+    fn backends_match_flix() {
+        let nsize = 45;
+        let mut flix = new_data(nsize, 3);
+        init(&mut flix);
+        flix.matrix[0][0] = 0.0;
+        let (matrix, b, swap) = (flix.matrix.clone(), flix.b.clone(), flix.swap.clone());
+        compute_gauss_channel(&mut flix, Dispatch::default()).unwrap();
+
+        for kind in &["std", "crossbeam", "ringbuf", "lockfree"] {
+            let mut data = new_data(nsize, 3);
+            data.matrix = matrix.clone();
+            data.b = b.clone();
+            data.swap = swap.clone();
+            compute_gauss_channel_with(&mut data, kind.parse().unwrap(), Dispatch::default())
+                .unwrap();
+
+            assert_eq!(flix.matrix, data.matrix);
+            assert_eq!(flix.b, data.b);
+            assert_eq!(flix.swap, data.swap);
+        }
+        assert!("go".parse::<ChannelKind>().is_err());
+    }
+
+    #[test]
+    fn worker_panic_ends_the_dispatch() {
+        // With b cut to two entries only rows 0 and 1 are shared, so the
+        // only worker indexes past them on the batch for row 2 and the sends
+        // after it find no receiver.
+        for &capacity in &[Capacity::Rendezvous, Capacity::Fixed(1)] {
+            let nsize = 30;
+            let mut data = new_data(nsize, 1);
+            init(&mut data);
+            data.b.truncate(2);
+            let dispatch = Dispatch { capacity, batch: 1 };
+            match compute_gauss_channel_with(&mut data, ChannelKind::Crossbeam, dispatch) {
+                Err(GaussError::Panicked(msg)) => assert!(msg.contains("index out of bounds")),
+                other => panic!("{:?}: {:?}", capacity, other),
+            }
+            assert_eq!(data.matrix.len(), 2);
+        }
+    }
+
+    #[test]
+    fn dispatch_settings_match() {
+        assert_eq!("rendezvous".parse(), Ok(Capacity::Rendezvous));
+        assert_eq!("0".parse(), Ok(Capacity::Rendezvous));
+        assert_eq!("unbounded".parse(), Ok(Capacity::Unbounded));
+        assert_eq!("16".parse(), Ok(Capacity::Fixed(16)));
+        assert!("lots".parse::<Capacity>().is_err());
+        let d = Dispatch {
+            capacity: Capacity::Unbounded,
+            batch: 4,
+        };
+        assert_eq!(d.batches(2, 12).collect::<Vec<_>>(), [3..7, 7..11, 11..12]);
+
+        let nsize = 37;
+        let mut seq = new_data(nsize, 1);
+        init(&mut seq);
+        let (matrix, b, swap) = (seq.matrix.clone(), seq.b.clone(), seq.swap.clone());
+        compute_gauss(&mut seq);
+
+        for &capacity in &[Capacity::Rendezvous, Capacity::Fixed(3), Capacity::Unbounded] {
+            for &batch in &[1, 5, 100] {
+                for &kind in &[ChannelKind::Flix, ChannelKind::Std, ChannelKind::LockFree] {
+                    let mut data = new_data(nsize, 3);
+                    data.matrix = matrix.clone();
+                    data.b = b.clone();
+                    data.swap = swap.clone();
+                    compute_gauss_channel_with(&mut data, kind, Dispatch { capacity, batch })
+                        .unwrap();
+
+                    assert_eq!(seq.matrix, data.matrix);
+                    assert_eq!(seq.b, data.b);
+                }
+            }
+        }
+    }
+}
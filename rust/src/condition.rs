@@ -0,0 +1,206 @@
+use crate::inverse::Lu;
+use crate::Data;
+use std::fmt;
+
+// The largest column sum of absolute values.
+pub fn norm1(matrix: &[Vec<f64>]) -> f64 {
+    let ncols = matrix.first().map_or(0, Vec::len);
+    (0..ncols)
+        .map(|j| matrix.iter().map(|row| row[j].abs()).sum::<f64>())
+        .fold(0.0, f64::max)
+}
+
+fn sum_abs(x: &[f64]) -> f64 {
+    x.iter().map(|x| x.abs()).sum()
+}
+
+// An estimate of |A^-1|_1 from the LU factors, without forming the inverse:
+// Hager's gradient ascent of |A^-1 x|_1 over the unit ball, a solve with A
+// and one with A^T per step, with Higham's refinements from LAPACK's dlacon.
+// It never overestimates and is usually exact or within a small factor.
+pub fn inverse_norm1(lu: &Lu) -> f64 {
+    let n = lu.nsize();
+    if n == 0 {
+        return 0.0;
+    }
+    let mut x = vec![1.0 / n as f64; n];
+    let mut est = 0.0;
+    for iter in 0..5 {
+        let y = lu.solve(&x);
+        let next = sum_abs(&y);
+        if iter > 0 && next <= est {
+            break;
+        }
+        est = next;
+        let signs: Vec<f64> = y.iter().map(|&y| if y >= 0.0 { 1.0 } else { -1.0 }).collect();
+        let z = lu.solve_transpose(&signs);
+        let (j, zj) = z
+            .iter()
+            .map(|z| z.abs())
+            .enumerate()
+            .fold((0, -1.0), |best, c| if c.1 > best.1 { c } else { best });
+        let ztx: f64 = z.iter().zip(&x).map(|(z, x)| z * x).sum();
+        if iter > 0 && zj <= ztx {
+            break;
+        }
+        x = vec![0.0; n];
+        x[j] = 1.0;
+    }
+
+    // An alternating vector that catches the matrices the ascent stalls on.
+    let last = (n - 1).max(1) as f64;
+    let alt: Vec<f64> = (0..n)
+        .map(|i| {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            sign * (1.0 + i as f64 / last)
+        })
+        .collect();
+    let alt_est = 2.0 * sum_abs(&lu.solve(&alt)) / (3.0 * n as f64);
+    est.max(alt_est)
+}
+
+// An estimate of the 1-norm condition number |A|_1 |A^-1|_1 of `matrix`
+// from its factors, such as the ones lib::compute_gauss_lu kept from the
+// solve.
+pub fn condition(matrix: &[Vec<f64>], lu: &Lu) -> f64 {
+    norm1(matrix) * inverse_norm1(lu)
+}
+
+// How far a computed x can be from the solution of A x = b, in 1-norms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErrorBounds {
+    pub condition: f64,
+    // |b - A x|
+    pub residual: f64,
+    // |r| / (|A| |x| + |b|): the smallest relative change to A and b that
+    // makes x exact (Rigal and Gaches).
+    pub backward: f64,
+    // condition |r| / (|A| |x|), which bounds |x - x*| / |x|.
+    pub forward: f64,
+}
+
+impl ErrorBounds {
+    pub fn new(matrix: &[Vec<f64>], b: &[f64], x: &[f64], condition: f64) -> ErrorBounds {
+        let r: Vec<f64> = matrix
+            .iter()
+            .zip(b)
+            .map(|(row, b)| b - row.iter().zip(x).map(|(a, x)| a * x).sum::<f64>())
+            .collect();
+        let residual = sum_abs(&r);
+        let (norm_a, norm_x) = (norm1(matrix), sum_abs(x));
+        ErrorBounds {
+            condition,
+            residual,
+            backward: residual / (norm_a * norm_x + sum_abs(b)),
+            forward: condition * residual / (norm_a * norm_x),
+        }
+    }
+}
+
+impl fmt::Display for ErrorBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "condition {:.3e}, residual {:.3e}, backward error {:.3e}, forward error <= {:.3e}",
+            self.condition, self.residual, self.backward, self.forward
+        )
+    }
+}
+
+// Bounds for x as a solution of `original`, the system before elimination,
+// which `lu` factors.
+pub fn error_bounds(original: &Data, x: &[f64], lu: &Lu) -> ErrorBounds {
+    ErrorBounds::new(&original.matrix, &original.b, x, condition(&original.matrix, lu))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::{compute_gauss, compute_gauss_lu, init};
+    use crate::inverse::{inverse, lu};
+
+    #[test]
+    fn condition_of_init() {
+        // The column sums of init's matrix peak at the last, n (n + 1); those
+        // of its tridiagonal inverse at 2.
+        for &nsize in &[3, 10, 40] {
+            let mut data = new_data(nsize, 1);
+            init(&mut data);
+            let exact = 2.0 * (nsize * (nsize + 1)) as f64;
+            let est = condition(&data.matrix, &lu(&data).unwrap());
+            assert!(est <= exact * (1.0 + 1e-9) && est >= exact / 3.0, "{} against {}", est, exact);
+        }
+    }
+
+    #[test]
+    fn estimate_against_inverse() {
+        let nsize = 25;
+        let mut data = new_data(nsize, 1);
+        data.matrix = (0..nsize)
+            .map(|i| (0..nsize).map(|j| 1.0 / (i + j + 1) as f64 + if i == j { 1e-3 } else { 0.0 }).collect())
+            .collect();
+        let exact = norm1(&data.matrix) * norm1(&inverse(&data).unwrap());
+        let est = condition(&data.matrix, &lu(&data).unwrap());
+        assert!(est <= exact * (1.0 + 1e-6) && est >= exact / 10.0, "{} against {}", est, exact);
+        assert!(est > 1e3);
+    }
+
+    #[test]
+    fn bounds_cover_the_error() {
+        let nsize = 20;
+        let mut data = new_data(nsize, 1);
+        init(&mut data);
+        let factors = lu(&data).unwrap();
+        let exact = factors.solve(&data.b);
+        let bounds = error_bounds(&data, &exact, &factors);
+        assert!(bounds.backward < 1e-14);
+
+        // A perturbed solution: the bound has to cover its actual error.
+        let x: Vec<f64> = exact.iter().enumerate().map(|(i, x)| x + 1e-6 * (i % 3) as f64).collect();
+        let bounds = error_bounds(&data, &x, &factors);
+        let err: f64 = x.iter().zip(&exact).map(|(a, b)| (a - b).abs()).sum::<f64>() / sum_abs(&x);
+        assert!(bounds.forward >= err, "{} < {}", bounds.forward, err);
+        assert!(bounds.backward > 1e-10);
+        assert!(format!("{}", bounds).starts_with("condition "));
+    }
+
+    #[test]
+    fn factors_from_the_solve() {
+        // A zero on the diagonal makes compute_gauss swap rows, and the
+        // pivots other than 1 make it scale them.
+        let matrix = vec![
+            vec![0.0, 2.0, 1.0, 3.0],
+            vec![4.0, 1.0, 0.0, 2.0],
+            vec![1.0, 5.0, 3.0, 0.0],
+            vec![2.0, 0.0, 6.0, 1.0],
+        ];
+        let nsize = matrix.len();
+        let mut original = new_data(nsize, 1);
+        original.matrix = matrix;
+        original.b = vec![1.0, 2.0, 3.0, 4.0];
+        original.swap = (0..nsize as u64).collect();
+
+        let mut data = new_data(nsize, 1);
+        data.matrix = original.matrix.clone();
+        data.b = original.b.clone();
+        data.swap = original.swap.clone();
+        let (_, factors) = compute_gauss_lu(&mut data).unwrap();
+
+        let mut plain = new_data(nsize, 1);
+        plain.matrix = original.matrix.clone();
+        plain.b = original.b.clone();
+        plain.swap = original.swap.clone();
+        compute_gauss(&mut plain);
+        assert_eq!(data.matrix, plain.matrix);
+        assert_eq!(data.b, plain.b);
+
+        let x = lu(&original).unwrap().solve(&original.b);
+        for (a, b) in factors.solve(&original.b).iter().zip(&x) {
+            assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+        }
+        let exact = norm1(&original.matrix) * norm1(&inverse(&original).unwrap());
+        let est = condition(&original.matrix, &factors);
+        assert!((est - exact).abs() < 1e-9 * exact, "{} against {}", est, exact);
+    }
+}
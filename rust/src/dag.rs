@@ -0,0 +1,571 @@
+use crate::error::GaussError;
+use crate::parallel::{combine, finish, pool_for, Candidate};
+use crate::{check_dense, Data};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+use threadpool_crossbeam::cancel::CancelToken;
+use threadpool_crossbeam::stats::{self, Stamp};
+use threadpool_crossbeam::{current_worker, ThreadPool};
+
+pub const DEFAULT_TILE: usize = 64;
+// Priority of the tasks the next panel waits on, over the rest of the update.
+const CRITICAL: i32 = 1;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Tasks and the edges between them. Nothing runs until `run`, which hands
+// every task with no dependencies to the pool; a finished task releases its
+// successors and submits those whose last input it was. On a pool built with
+// a priority queue, ready tasks of higher priority run first.
+#[derive(Default)]
+pub struct TaskGraph {
+    jobs: Vec<Job>,
+    deps: Vec<usize>,
+    succ: Vec<Vec<usize>>,
+    priority: Vec<i32>,
+}
+
+struct Running {
+    jobs: Vec<Mutex<Option<Job>>>,
+    pending: Vec<AtomicUsize>,
+    succ: Vec<Vec<usize>>,
+    priority: Vec<i32>,
+}
+
+impl TaskGraph {
+    pub fn new() -> TaskGraph {
+        TaskGraph::default()
+    }
+
+    pub fn add_task<F>(&mut self, job: F) -> usize
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.jobs.push(Box::new(job));
+        self.deps.push(0);
+        self.succ.push(Vec::new());
+        self.priority.push(0);
+        self.jobs.len() - 1
+    }
+
+    pub fn set_priority(&mut self, t: usize, priority: i32) {
+        self.priority[t] = priority;
+    }
+
+    // `after` will not start before `before` has finished.
+    pub fn add_dep(&mut self, before: usize, after: usize) {
+        assert!(before < after, "tasks must be added in a topological order");
+        self.succ[before].push(after);
+        self.deps[after] += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    // Runs every task on `pool` and returns once all of them are done, or
+    // once the pool has given up on the rest after a task panicked.
+    pub fn run(self, pool: &ThreadPool) -> Result<(), GaussError> {
+        let ready: Vec<usize> = (0..self.len()).filter(|&t| self.deps[t] == 0).collect();
+        let graph = Arc::new(Running {
+            jobs: self.jobs.into_iter().map(|j| Mutex::new(Some(j))).collect(),
+            pending: self.deps.into_iter().map(AtomicUsize::new).collect(),
+            succ: self.succ,
+            priority: self.priority,
+        });
+        for t in ready {
+            submit(pool, &graph, t);
+        }
+        // A task submits its successors before it counts as finished, so the
+        // pool cannot run dry until the whole graph has executed.
+        pool.join()?;
+        Ok(())
+    }
+}
+
+fn submit(pool: &ThreadPool, graph: &Arc<Running>, t: usize) {
+    let graph = Arc::clone(graph);
+    let handle = pool.clone();
+    pool.execute_with_priority(graph.priority[t], move || {
+        let job = graph.jobs[t].lock().unwrap().take().unwrap();
+        job();
+        for &s in &graph.succ[t] {
+            if graph.pending[s].fetch_sub(1, Ordering::SeqCst) == 1 {
+                submit(&handle, &graph, s);
+            }
+        }
+    });
+}
+
+// The augmented matrix [A | b] cut into tile x tile blocks, each locked on
+// its own. Tile (rt, ct) is stored row-major.
+struct Tiles {
+    nsize: usize,
+    tile: usize,
+    nrt: usize,
+    nct: usize,
+    tiles: Vec<RwLock<Vec<f64>>>,
+}
+
+impl Tiles {
+    fn from_data(data: &mut Data, tile: usize) -> Tiles {
+        let nsize = data.nsize;
+        let nrt = nsize.div_ceil(tile);
+        let nct = (nsize + 1).div_ceil(tile);
+        let mut tiles = Vec::with_capacity(nrt * nct);
+        for rt in 0..nrt {
+            for ct in 0..nct {
+                let mut t = Vec::with_capacity(tile * tile);
+                for r in rt * tile..(nsize.min((rt + 1) * tile)) {
+                    for c in ct * tile..((nsize + 1).min((ct + 1) * tile)) {
+                        t.push(if c == nsize { data.b[r] } else { data.matrix[r][c] });
+                    }
+                }
+                tiles.push(RwLock::new(t));
+            }
+        }
+        data.matrix.clear();
+        Tiles {
+            nsize,
+            tile,
+            nrt,
+            nct,
+            tiles,
+        }
+    }
+
+    fn into_data(self, data: &mut Data) {
+        let nsize = self.nsize;
+        let tile = self.tile;
+        let nct = self.nct;
+        let tiles: Vec<Vec<f64>> = self
+            .tiles
+            .into_iter()
+            .map(|t| t.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        for r in 0..nsize {
+            let mut row = Vec::with_capacity(nsize);
+            for c in 0..=nsize {
+                let w = (nsize + 1).min((c / tile + 1) * tile) - c / tile * tile;
+                let x = tiles[r / tile * nct + c / tile][(r % tile) * w + c % tile];
+                if c == nsize {
+                    data.b[r] = x;
+                } else if c < r {
+                    // The multipliers were kept below the diagonal until now.
+                    row.push(0.0);
+                } else {
+                    row.push(x);
+                }
+            }
+            data.matrix.push(row);
+        }
+    }
+
+    fn get(&self, rt: usize, ct: usize) -> &RwLock<Vec<f64>> {
+        &self.tiles[rt * self.nct + ct]
+    }
+
+    // Number of columns in tile column ct.
+    fn width(&self, ct: usize) -> usize {
+        (self.nsize + 1).min((ct + 1) * self.tile) - ct * self.tile
+    }
+
+    // Last matrix row (exclusive) of tile row rt.
+    fn row_end(&self, rt: usize) -> usize {
+        self.nsize.min((rt + 1) * self.tile)
+    }
+}
+
+// A column of tiles, from tile row `from` down, locked for writing. Rows are
+// addressed by their index in the full matrix.
+struct Column<'a> {
+    from: usize,
+    tile: usize,
+    width: usize,
+    tiles: Vec<RwLockWriteGuard<'a, Vec<f64>>>,
+}
+
+impl<'a> Column<'a> {
+    fn lock(t: &'a Tiles, from: usize, ct: usize) -> Column<'a> {
+        Column {
+            from,
+            tile: t.tile,
+            width: t.width(ct),
+            tiles: (from..t.nrt).map(|rt| t.get(rt, ct).write().unwrap()).collect(),
+        }
+    }
+
+    fn row(&mut self, r: usize) -> &mut [f64] {
+        let w = self.width;
+        let off = (r % self.tile) * w;
+        &mut self.tiles[r / self.tile - self.from][off..off + w]
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        if a / self.tile == b / self.tile {
+            let w = self.width;
+            let t = &mut self.tiles[a / self.tile - self.from];
+            for c in 0..w {
+                t.swap((a % self.tile) * w + c, (b % self.tile) * w + c);
+            }
+        } else {
+            let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+            let (left, right) = self.tiles.split_at_mut(hi / self.tile - self.from);
+            let w = self.width;
+            let x = &mut left[lo / self.tile - self.from][(lo % self.tile) * w..][..w];
+            let y = &mut right[0][(hi % self.tile) * w..][..w];
+            x.swap_with_slice(y);
+        }
+    }
+}
+
+// What the panel factorization decided, replayed on every tile column to
+// its right. It is written once by the panel task and only read afterwards,
+// so the update_top tasks of a panel share it.
+#[derive(Default)]
+struct Panel {
+    piv: Vec<usize>,
+    scale: Vec<f64>,
+}
+
+struct DagShared {
+    t: Tiles,
+    panels: Vec<RwLock<Panel>>,
+    // When the latest panel task started. Phase k runs from the start of
+    // panel k to the start of panel k + 1, the critical path through the
+    // graph; the tasks it overlaps with count as busy for phase k too.
+    phase: Mutex<Stamp>,
+    // Once set, the remaining tasks return without touching the tiles.
+    error: Mutex<Option<GaussError>>,
+    cancel: CancelToken,
+}
+
+impl DagShared {
+    fn stopped(&self) -> bool {
+        let mut error = self.error.lock().unwrap();
+        if error.is_none() && self.cancel.is_cancelled() {
+            *error = Some(GaussError::Cancelled);
+        }
+        error.is_some()
+    }
+}
+
+// Factors tile column k: for each of its columns picks the largest pivot
+// below the diagonal, swaps it up, normalizes it and eliminates underneath,
+// touching only this tile column. The multipliers are left in place for the
+// tasks that update the tiles to the right.
+fn factor_panel(s: &DagShared, k: usize) {
+    if s.stopped() {
+        return;
+    }
+    let mut phase = s.phase.lock().unwrap();
+    if k > 0 {
+        stats::phase(k - 1, phase.elapsed());
+    }
+    *phase = Stamp::now();
+    drop(phase);
+    let t = &s.t;
+    let c0 = k * t.tile;
+    let mut col = Column::lock(t, k, k);
+    let mut panel = s.panels[k].write().unwrap();
+
+    for c in c0..t.row_end(k) {
+        let lc = c - c0;
+        let mut best = Candidate::none(c);
+        for r in c..t.nsize {
+            best = combine(
+                best,
+                Candidate {
+                    big: col.row(r)[lc],
+                    irow: r,
+                },
+            );
+        }
+        if best.big == 0.0 {
+            *s.error.lock().unwrap() = Some(GaussError::Singular(c));
+            return;
+        }
+        if best.irow != c {
+            col.swap(best.irow, c);
+        }
+
+        let pivot_row = col.row(c);
+        let pivot_val = pivot_row[lc];
+        if (pivot_val - 1.0).abs() > 0.0000001 {
+            pivot_row[lc] = 1.0;
+            for x in pivot_row[lc + 1..].iter_mut() {
+                *x /= pivot_val;
+            }
+        }
+        panel.piv.push(best.irow);
+        panel.scale.push(pivot_val);
+
+        let pivot_row = col.row(c).to_vec();
+        for r in c + 1..t.nsize {
+            let row = col.row(r);
+            let pivot_val = row[lc];
+            for (x, p) in row[lc + 1..].iter_mut().zip(&pivot_row[lc + 1..]) {
+                *x -= pivot_val * p;
+            }
+        }
+    }
+}
+
+// Applies panel k's row swaps to tile column j, then the pivot scaling and
+// elimination to the tile in row k, the only one whose rows depend on each
+// other.
+fn update_top(s: &DagShared, k: usize, j: usize) {
+    if s.stopped() {
+        return;
+    }
+    let t = &s.t;
+    let c0 = k * t.tile;
+    let mut col = Column::lock(t, k, j);
+    let panel = s.panels[k].read().unwrap();
+    let l = t.get(k, k).read().unwrap();
+    let lw = t.width(k);
+
+    for (i, &p) in panel.piv.iter().enumerate() {
+        if p != c0 + i {
+            col.swap(p, c0 + i);
+        }
+    }
+    for (i, &pivot_val) in panel.scale.iter().enumerate() {
+        let c = c0 + i;
+        let pivot_row = col.row(c);
+        if (pivot_val - 1.0).abs() > 0.0000001 {
+            for x in pivot_row.iter_mut() {
+                *x /= pivot_val;
+            }
+        }
+        let pivot_row = pivot_row.to_vec();
+        for r in c + 1..t.row_end(k) {
+            let m = l[(r - c0) * lw + i];
+            for (x, p) in col.row(r).iter_mut().zip(&pivot_row) {
+                *x -= m * p;
+            }
+        }
+    }
+}
+
+// Runs a task of panel k's phase, counting it as busy time for the worker.
+fn timed<F: FnOnce()>(k: usize, task: F) {
+    let start = Stamp::now();
+    task();
+    stats::busy(current_worker().unwrap_or(0), k, start.elapsed());
+}
+
+// Eliminates panel k's columns from tile (rt, j), below the panel.
+fn update_tile(s: &DagShared, k: usize, j: usize, rt: usize) {
+    if s.stopped() {
+        return;
+    }
+    let t = &s.t;
+    let l = t.get(rt, k).read().unwrap();
+    let u = t.get(k, j).read().unwrap();
+    let mut a = t.get(rt, j).write().unwrap();
+    let (lw, w) = (t.width(k), t.width(j));
+    let rows = t.row_end(rt) - rt * t.tile;
+    let cols = t.row_end(k) - k * t.tile;
+
+    for r in 0..rows {
+        let a = &mut a[r * w..(r + 1) * w];
+        for c in 0..cols {
+            let m = l[r * lw + c];
+            for (x, p) in a.iter_mut().zip(&u[c * w..(c + 1) * w]) {
+                *x -= m * p;
+            }
+        }
+    }
+}
+
+// Task-graph version of compute_gauss on tile x tile blocks. Per tile column
+// k there is a panel task, a task per tile column to its right that replays
+// the panel's swaps and solves the top tile, and a task per tile below that
+// for the actual update. Each starts as soon as the tiles it reads are final,
+// so the next panel overlaps with what is left of the current update.
+// Pivots are chosen like compute_gauss_p.
+pub fn compute_gauss_dag(data: &mut Data, tile: usize) -> Result<(), GaussError> {
+    assert!(tile > 0);
+    check_dense(data)?;
+    let pool = pool_for(data).priority_queue(true).build();
+    let t = Tiles::from_data(data, tile);
+    let (nrt, nct) = (t.nrt, t.nct);
+    let shared = Arc::new(DagShared {
+        t,
+        panels: (0..nrt).map(|_| RwLock::new(Panel::default())).collect(),
+        phase: Mutex::new(Stamp::now()),
+        error: Mutex::new(None),
+        cancel: pool.cancel_token(),
+    });
+
+    let mut graph = TaskGraph::new();
+    // Last task to write tile (rt, ct), if any.
+    let mut last = vec![None; nrt * nct];
+    for k in 0..nrt {
+        let s = Arc::clone(&shared);
+        let p = graph.add_task(move || timed(k, || factor_panel(&s, k)));
+        graph.set_priority(p, CRITICAL);
+        for rt in k..nrt {
+            if let Some(d) = last[rt * nct + k] {
+                graph.add_dep(d, p);
+            }
+        }
+
+        for j in k + 1..nct {
+            let s = Arc::clone(&shared);
+            let top = graph.add_task(move || timed(k, || update_top(&s, k, j)));
+            graph.add_dep(p, top);
+            if j == k + 1 {
+                graph.set_priority(top, CRITICAL);
+            }
+            for rt in k..nrt {
+                if let Some(d) = last[rt * nct + j] {
+                    graph.add_dep(d, top);
+                }
+                last[rt * nct + j] = Some(top);
+            }
+
+            for rt in k + 1..nrt {
+                let s = Arc::clone(&shared);
+                let up = graph.add_task(move || timed(k, || update_tile(&s, k, j, rt)));
+                graph.add_dep(top, up);
+                if j == k + 1 {
+                    graph.set_priority(up, CRITICAL);
+                }
+                last[rt * nct + j] = Some(up);
+            }
+        }
+    }
+    let ran = graph.run(&pool);
+    if nrt > 0 {
+        stats::phase(nrt - 1, shared.phase.lock().unwrap().elapsed());
+    }
+
+    // A task that panicked has poisoned its locks; the data is written back
+    // as it was left.
+    let shared = Arc::try_unwrap(shared).ok().unwrap();
+    for (k, panel) in shared.panels.into_iter().enumerate() {
+        let panel = panel.into_inner().unwrap_or_else(|e| e.into_inner());
+        for (i, &p) in panel.piv.iter().enumerate() {
+            data.swap.swap(p, k * tile + i);
+        }
+    }
+    shared.t.into_data(data);
+    ran?;
+    finish(&pool.cancel_token(), shared.error.into_inner().unwrap().map_or(Ok(()), Err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::init;
+    use crate::parallel::{compute_gauss_p, pool_builder};
+    use threadpool_crossbeam::Builder;
+
+    #[test]
+    fn task_graph_order() {
+        let pool = ThreadPool::new(4);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut graph = TaskGraph::new();
+        let mut ids = Vec::new();
+        for i in 0..6 {
+            let log = Arc::clone(&log);
+            ids.push(graph.add_task(move || log.lock().unwrap().push(i)));
+        }
+        // 0 -> {1, 2} -> 3 -> {4, 5}
+        graph.add_dep(ids[0], ids[1]);
+        graph.add_dep(ids[0], ids[2]);
+        graph.add_dep(ids[1], ids[3]);
+        graph.add_dep(ids[2], ids[3]);
+        graph.add_dep(ids[3], ids[4]);
+        graph.add_dep(ids[3], ids[5]);
+        graph.run(&pool).unwrap();
+
+        let log = log.lock().unwrap();
+        let pos = |t| log.iter().position(|&x| x == t).unwrap();
+        assert_eq!(log.len(), 6);
+        assert_eq!(pos(0), 0);
+        assert_eq!(pos(3), 3);
+        assert!(pos(1) < pos(3) && pos(2) < pos(3));
+    }
+
+    #[test]
+    fn task_graph_priority() {
+        let pool = Builder::new().num_threads(1).priority_queue(true).build();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut graph = TaskGraph::new();
+        let mut ids = Vec::new();
+        for i in 0..4 {
+            let log = Arc::clone(&log);
+            ids.push(graph.add_task(move || log.lock().unwrap().push(i)));
+        }
+        // 0 -> {1, 2, 3}, all released at once onto the single worker.
+        for &t in &ids[1..] {
+            graph.add_dep(ids[0], t);
+        }
+        graph.set_priority(ids[3], CRITICAL);
+        graph.run(&pool).unwrap();
+
+        assert_eq!(*log.lock().unwrap(), vec![0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn task_graph_panic() {
+        let pool = pool_builder(2).build();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let mut graph = TaskGraph::new();
+        let bad = graph.add_task(|| panic!("tile 3 is missing"));
+        for _ in 0..3 {
+            let ran = Arc::clone(&ran);
+            let t = graph.add_task(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+            graph.add_dep(bad, t);
+        }
+
+        assert_eq!(
+            graph.run(&pool),
+            Err(GaussError::Panicked("tile 3 is missing".to_string()))
+        );
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn dag_matches_pool() {
+        for &(nsize, tile) in &[(1, 4), (17, 4), (64, 16), (100, 7), (50, 64)] {
+            let mut pool = new_data(nsize, 3);
+            init(&mut pool);
+            pool.matrix[nsize / 2][nsize / 3] = -7.0 * nsize as f64;
+            let mut dag = new_data(nsize, 3);
+            dag.matrix = pool.matrix.clone();
+            dag.b = pool.b.clone();
+            dag.swap = pool.swap.clone();
+
+            compute_gauss_p(&mut pool).unwrap();
+            compute_gauss_dag(&mut dag, tile).unwrap();
+
+            assert_eq!(pool.matrix, dag.matrix);
+            assert_eq!(pool.b, dag.b);
+            assert_eq!(pool.swap, dag.swap);
+        }
+    }
+
+    #[test]
+    fn dag_singular() {
+        let mut data = new_data(3, 2);
+        data.matrix = vec![
+            vec![1.0, 2.0, 1.0],
+            vec![2.0, 4.0, 2.0],
+            vec![3.0, 6.0, 3.0],
+        ];
+        data.b = vec![1.0, 2.0, 3.0];
+        data.swap = vec![0, 1, 2];
+        assert_eq!(compute_gauss_dag(&mut data, 2), Err(GaussError::Singular(1)));
+    }
+}
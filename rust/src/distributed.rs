@@ -0,0 +1,579 @@
+use crate::actor::{fill_displacement, fill_sendcounts, Layout};
+use crate::error::GaussError;
+use crate::parallel::{combine, update_row, Candidate};
+use crate::{check_dense, Data};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+
+// Frame tags. Every frame is
+//   [tag: u8][number of ints: u32][number of floats: u32][ints: u64...][floats: f64...]
+// all little endian.
+const INIT: u8 = 1;
+const CANDIDATE: u8 = 2;
+const TAKE: u8 = 3;
+const ROW: u8 = 4;
+const PUT: u8 = 5;
+const PIVOT: u8 = 6;
+const GATHER: u8 = 7;
+const ROWS: u8 = 8;
+// The most 8-byte words a frame may carry, 2 GiB. A header that claims more
+// is rejected before anything is allocated for the body.
+const MAX_FRAME_WORDS: u64 = 1 << 28;
+
+fn check_words(words: u64, kind: io::ErrorKind) -> io::Result<usize> {
+    if words > MAX_FRAME_WORDS {
+        return Err(io::Error::new(
+            kind,
+            format!("frame of {} words is over the limit of {}", words, MAX_FRAME_WORDS),
+        ));
+    }
+    Ok(words as usize)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub tag: u8,
+    pub ints: Vec<u64>,
+    pub vals: Vec<f64>,
+}
+
+impl Frame {
+    pub fn new(tag: u8, ints: Vec<u64>, vals: Vec<f64>) -> Frame {
+        Frame { tag, ints, vals }
+    }
+
+    pub fn write_to<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        let words = self.ints.len() as u64 + self.vals.len() as u64;
+        let mut buf = Vec::with_capacity(9 + 8 * check_words(words, io::ErrorKind::InvalidInput)?);
+        buf.push(self.tag);
+        buf.extend_from_slice(&(self.ints.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(self.vals.len() as u32).to_le_bytes());
+        for x in &self.ints {
+            buf.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in &self.vals {
+            buf.extend_from_slice(&x.to_le_bytes());
+        }
+        w.write_all(&buf)?;
+        w.flush()
+    }
+
+    pub fn read_from<R: Read + ?Sized>(r: &mut R) -> io::Result<Frame> {
+        let mut head = [0u8; 9];
+        r.read_exact(&mut head)?;
+        let nints = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as usize;
+        let nvals = u32::from_le_bytes([head[5], head[6], head[7], head[8]]) as usize;
+        let len = 8 * check_words(nints as u64 + nvals as u64, io::ErrorKind::InvalidData)?;
+        // The body grows as it arrives, so a header that lies about its
+        // length costs no more memory than was actually sent.
+        let mut body = Vec::new();
+        (&mut *r).take(len as u64).read_to_end(&mut body)?;
+        if body.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut words = body.chunks_exact(8).map(|c| {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(c);
+            b
+        });
+        let ints = words.by_ref().take(nints).map(u64::from_le_bytes).collect();
+        let vals = words.map(f64::from_le_bytes).collect();
+        Ok(Frame {
+            tag: head[0],
+            ints,
+            vals,
+        })
+    }
+
+    fn expect(self, tag: u8) -> io::Result<Frame> {
+        if self.tag == tag {
+            Ok(self)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected frame {}, got {}", tag, self.tag),
+            ))
+        }
+    }
+}
+
+pub trait Conn: Read + Write + Send {}
+impl Conn for TcpStream {}
+impl Conn for UnixStream {}
+
+fn send(conn: &mut dyn Conn, tag: u8, ints: Vec<u64>, vals: Vec<f64>) -> io::Result<()> {
+    Frame::new(tag, ints, vals).write_to(conn)
+}
+
+fn recv(conn: &mut dyn Conn, tag: u8) -> io::Result<Frame> {
+    Frame::read_from(conn)?.expect(tag)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    Tcp,
+    Unix,
+}
+
+impl std::str::FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Transport, String> {
+        match s {
+            "tcp" => Ok(Transport::Tcp),
+            "unix" => Ok(Transport::Unix),
+            _ => Err(format!("unknown transport {}", s)),
+        }
+    }
+}
+
+// Where the coordinator waits for its workers. The address handed to the
+// workers is "tcp:<host>:<port>" or "unix:<path>".
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn bind(transport: Transport) -> io::Result<Listener> {
+        match transport {
+            Transport::Tcp => Ok(Listener::Tcp(TcpListener::bind("127.0.0.1:0")?)),
+            Transport::Unix => {
+                let path = std::env::temp_dir().join(format!(
+                    "gauss-{}-{:?}.sock",
+                    std::process::id(),
+                    std::thread::current().id()
+                ));
+                let _ = std::fs::remove_file(&path);
+                Ok(Listener::Unix(UnixListener::bind(&path)?, path))
+            }
+        }
+    }
+
+    pub fn addr(&self) -> io::Result<String> {
+        match self {
+            Listener::Tcp(l) => Ok(format!("tcp:{}", l.local_addr()?)),
+            Listener::Unix(_, path) => Ok(format!("unix:{}", path.display())),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Box<dyn Conn>> {
+        match self {
+            Listener::Tcp(l) => {
+                let (s, _) = l.accept()?;
+                s.set_nodelay(true)?;
+                Ok(Box::new(s))
+            }
+            Listener::Unix(l, _) => Ok(Box::new(l.accept()?.0)),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub fn connect(addr: &str) -> io::Result<Box<dyn Conn>> {
+    if let Some(addr) = addr.strip_prefix("tcp:") {
+        let s = TcpStream::connect(addr)?;
+        s.set_nodelay(true)?;
+        Ok(Box::new(s))
+    } else if let Some(path) = addr.strip_prefix("unix:") {
+        Ok(Box::new(UnixStream::connect(path)?))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bad worker address {}", addr),
+        ))
+    }
+}
+
+// Worker processes started by spawn_workers, with their connections in rank
+// order.
+pub struct Workers {
+    pub conns: Vec<Box<dyn Conn>>,
+    pub children: Vec<Child>,
+}
+
+impl Workers {
+    pub fn wait(self) -> io::Result<()> {
+        drop(self.conns);
+        for mut child in self.children {
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
+
+// Starts `n` copies of `exe` as workers ("<exe> --worker <addr>") and waits
+// for all of them to connect.
+pub fn spawn_workers(exe: &std::path::Path, n: usize, transport: Transport) -> io::Result<Workers> {
+    let listener = Listener::bind(transport)?;
+    let addr = listener.addr()?;
+    let mut children = Vec::with_capacity(n);
+    for _ in 0..n {
+        children.push(Command::new(exe).arg("--worker").arg(&addr).spawn()?);
+    }
+    let conns = (0..n)
+        .map(|_| listener.accept())
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(Workers { conns, children })
+}
+
+// The worker side: receives its rows, then follows the coordinator's
+// orders until it is asked to hand them back.
+pub fn worker(addr: &str) -> io::Result<()> {
+    let mut conn = connect(addr)?;
+    let conn = &mut *conn;
+    let init = recv(conn, INIT)?;
+    let (rank, l) = (
+        init.ints[0] as usize,
+        Layout {
+            nactors: init.ints[1] as usize,
+            nsize: init.ints[2] as usize,
+            block: init.ints[3] as usize,
+        },
+    );
+    let mut rows: Vec<Vec<f64>> = init
+        .vals
+        .chunks_exact(l.nsize + 1)
+        .map(|r| r.to_vec())
+        .collect();
+
+    let candidate = |rows: &[Vec<f64>], i: usize| {
+        l.rows(rank)
+            .filter(|&r| r >= i)
+            .fold(Candidate::none(i), |best, r| {
+                combine(
+                    best,
+                    Candidate {
+                        big: rows[l.local(r)][i],
+                        irow: r,
+                    },
+                )
+            })
+    };
+    let send_candidate = |conn: &mut dyn Conn, c: Candidate| {
+        send(conn, CANDIDATE, vec![c.irow as u64], vec![c.big])
+    };
+
+    if l.nsize > 0 {
+        send_candidate(conn, candidate(&rows, 0))?;
+    }
+    loop {
+        let frame = Frame::read_from(conn)?;
+        match frame.tag {
+            TAKE => {
+                let r = frame.ints[0] as usize;
+                let row = std::mem::take(&mut rows[l.local(r)]);
+                send(conn, ROW, vec![r as u64], row)?;
+            }
+            PUT => rows[l.local(frame.ints[0] as usize)] = frame.vals,
+            PIVOT => {
+                let i = frame.ints[0] as usize;
+                for r in l.rows(rank).filter(|&r| r > i) {
+                    update_row(&frame.vals, &mut rows[l.local(r)], i);
+                }
+                if l.owner(i) == rank {
+                    rows[l.local(i)] = frame.vals;
+                }
+                if i + 1 < l.nsize {
+                    send_candidate(conn, candidate(&rows, i + 1))?;
+                }
+            }
+            GATHER => {
+                send(conn, ROWS, Vec::new(), rows.concat())?;
+                return Ok(());
+            }
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected frame {}", tag),
+                ))
+            }
+        }
+    }
+}
+
+// Multi-process version of compute_gauss_actor. The coordinator (the caller)
+// scatters block-cyclic rows to the workers behind `conns`, combines their
+// pivot candidates every phase, swaps the pivot row into place by fetching
+// it from its owner, and broadcasts it normalized. The rows are gathered back
+// at the end, or as far as they got when the matrix turns out singular or
+// the run is cancelled. If talking to a worker fails the rows cannot be
+// gathered, so data is put back the way it was passed in.
+pub fn compute_gauss_distributed(
+    data: &mut Data,
+    conns: &mut [Box<dyn Conn>],
+    block: usize,
+) -> Result<(), GaussError> {
+    assert!(block > 0 && !conns.is_empty());
+    check_dense(data)?;
+    let l = Layout {
+        nsize: data.nsize,
+        block,
+        nactors: conns.len(),
+    };
+    let sendcounts = fill_sendcounts(&l);
+    let displ = fill_displacement(&sendcounts);
+
+    let mut sendbuf: Vec<Vec<f64>> = vec![Vec::new(); l.nsize];
+    let mut next = displ.clone();
+    for (r, mut row) in data.matrix.drain(..).enumerate() {
+        row.push(data.b[r]);
+        let w = l.owner(r);
+        sendbuf[next[w]] = row;
+        next[w] += 1;
+    }
+
+    let swap = data.swap.clone();
+    match coordinate(data, conns, &l, &sendbuf, &displ, &sendcounts) {
+        Ok((recvbuf, stop)) => {
+            unscatter(data, &l, &displ, recvbuf);
+            stop.map_or(Ok(()), Err)
+        }
+        Err(e) => {
+            data.swap = swap;
+            unscatter(data, &l, &displ, sendbuf);
+            Err(e)
+        }
+    }
+}
+
+// Puts rows in send order back into data.matrix and data.b.
+fn unscatter(data: &mut Data, l: &Layout, displ: &[usize], mut rows: Vec<Vec<f64>>) {
+    let mut next = displ.to_vec();
+    for r in 0..l.nsize {
+        let w = l.owner(r);
+        let mut row = std::mem::take(&mut rows[next[w]]);
+        next[w] += 1;
+        data.b[r] = row.pop().unwrap();
+        data.matrix.push(row);
+    }
+}
+
+// The conversation with the workers. Returns the rows they hand back, in
+// send order, and the reason the elimination stopped early if it did.
+fn coordinate(
+    data: &mut Data,
+    conns: &mut [Box<dyn Conn>],
+    l: &Layout,
+    sendbuf: &[Vec<f64>],
+    displ: &[usize],
+    sendcounts: &[usize],
+) -> Result<(Vec<Vec<f64>>, Option<GaussError>), GaussError> {
+    for (w, conn) in conns.iter_mut().enumerate() {
+        let rows = sendbuf[displ[w]..displ[w] + sendcounts[w]].concat();
+        let ints = vec![w as u64, l.nactors as u64, l.nsize as u64, l.block as u64];
+        send(&mut **conn, INIT, ints, rows)?;
+    }
+
+    let mut stop = None;
+    for i in 0..l.nsize {
+        let mut best = Candidate::none(i);
+        for conn in conns.iter_mut() {
+            let c = recv(&mut **conn, CANDIDATE)?;
+            best = combine(
+                best,
+                Candidate {
+                    big: c.vals[0],
+                    irow: c.ints[0] as usize,
+                },
+            );
+        }
+        if data.cancel.is_cancelled() {
+            stop = Some(GaussError::Cancelled);
+        } else if best.big == 0.0 {
+            stop = Some(GaussError::Singular(i));
+        }
+        if stop.is_some() {
+            break;
+        }
+
+        let owner = &mut *conns[l.owner(i)];
+        send(owner, TAKE, vec![i as u64], Vec::new())?;
+        let row = recv(owner, ROW)?.vals;
+        let mut pivot = if best.irow != i {
+            data.swap.swap(best.irow, i);
+            let other = &mut *conns[l.owner(best.irow)];
+            send(other, TAKE, vec![best.irow as u64], Vec::new())?;
+            let pivot = recv(other, ROW)?.vals;
+            send(other, PUT, vec![best.irow as u64], row)?;
+            pivot
+        } else {
+            row
+        };
+
+        let pivot_val = pivot[i];
+        if (pivot_val - 1.0).abs() > 0.0000001 {
+            pivot[i] = 1.0;
+            for x in pivot[i + 1..].iter_mut() {
+                *x /= pivot_val;
+            }
+        }
+        for conn in conns.iter_mut() {
+            send(&mut **conn, PIVOT, vec![i as u64], pivot.clone())?;
+        }
+    }
+
+    let mut recvbuf: Vec<Vec<f64>> = Vec::with_capacity(l.nsize);
+    for conn in conns.iter_mut() {
+        send(&mut **conn, GATHER, Vec::new(), Vec::new())?;
+        let rows = recv(&mut **conn, ROWS)?.vals;
+        recvbuf.extend(rows.chunks_exact(l.nsize + 1).map(|r| r.to_vec()));
+    }
+    if recvbuf.len() != l.nsize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "workers lost rows").into());
+    }
+    Ok((recvbuf, stop))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::init;
+    use crate::parallel::compute_gauss_p;
+    use std::thread;
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = Frame::new(PIVOT, vec![3, u64::MAX], vec![1.5, -0.0, f64::INFINITY]);
+        let mut buf = Vec::new();
+        frame.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 9 + 5 * 8);
+        assert_eq!(Frame::read_from(&mut &buf[..]).unwrap(), frame);
+    }
+
+    #[test]
+    fn frame_limits() {
+        let mut head = vec![PIVOT];
+        head.extend_from_slice(&u32::MAX.to_le_bytes());
+        head.extend_from_slice(&1u32.to_le_bytes());
+        let e = Frame::read_from(&mut &head[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // A header promising more than arrives.
+        let mut buf = Vec::new();
+        Frame::new(ROW, vec![1], vec![2.0; 4]).write_to(&mut buf).unwrap();
+        buf.truncate(buf.len() - 8);
+        let e = Frame::read_from(&mut &buf[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // Runs `run` as the workers' side of the connections, one thread each.
+    fn local_conns<F>(
+        transport: Transport,
+        nworkers: usize,
+        run: F,
+    ) -> (Vec<Box<dyn Conn>>, Vec<thread::JoinHandle<()>>)
+    where
+        F: Fn(&str) + Send + Sync + Copy + 'static,
+    {
+        let listener = Listener::bind(transport).unwrap();
+        let addr = listener.addr().unwrap();
+        let workers = (0..nworkers)
+            .map(|_| {
+                let addr = addr.clone();
+                thread::spawn(move || run(&addr))
+            })
+            .collect();
+        let conns = (0..nworkers).map(|_| listener.accept().unwrap()).collect();
+        (conns, workers)
+    }
+
+    fn run_local(transport: Transport, nsize: usize, nworkers: usize, block: usize) {
+        let mut pool = new_data(nsize, nworkers);
+        init(&mut pool);
+        pool.matrix[nsize / 2][nsize / 3] = -7.0 * nsize as f64;
+        let mut dist = new_data(nsize, nworkers);
+        dist.matrix = pool.matrix.clone();
+        dist.b = pool.b.clone();
+        dist.swap = pool.swap.clone();
+
+        let (mut conns, workers) = local_conns(transport, nworkers, |addr| worker(addr).unwrap());
+        compute_gauss_distributed(&mut dist, &mut conns, block).unwrap();
+        for w in workers {
+            w.join().unwrap();
+        }
+
+        compute_gauss_p(&mut pool).unwrap();
+        assert_eq!(pool.matrix, dist.matrix);
+        assert_eq!(pool.b, dist.b);
+        assert_eq!(pool.swap, dist.swap);
+    }
+
+    #[test]
+    fn singular_gathers_the_rows() {
+        // Columns 2 and 4 are equal, so a later phase finds no pivot.
+        let nsize = 9;
+        let mut pool = new_data(nsize, 2);
+        init(&mut pool);
+        for row in pool.matrix.iter_mut() {
+            row[4] = row[2];
+        }
+        let mut dist = new_data(nsize, 2);
+        dist.matrix = pool.matrix.clone();
+        dist.b = pool.b.clone();
+        dist.swap = pool.swap.clone();
+
+        let (mut conns, workers) = local_conns(Transport::Tcp, 2, |addr| worker(addr).unwrap());
+        let result = compute_gauss_distributed(&mut dist, &mut conns, 2);
+        for w in workers {
+            w.join().unwrap();
+        }
+
+        let expected = compute_gauss_p(&mut pool);
+        assert!(matches!(expected, Err(GaussError::Singular(_))));
+        assert_eq!(result, expected);
+        assert_eq!(pool.matrix, dist.matrix);
+        assert_eq!(pool.swap, dist.swap);
+    }
+
+    #[test]
+    fn lost_worker_restores_the_data() {
+        let nsize = 12;
+        let mut data = new_data(nsize, 2);
+        init(&mut data);
+        let (matrix, b, swap) = (data.matrix.clone(), data.b.clone(), data.swap.clone());
+
+        // The second worker hangs up as soon as it has its rows.
+        let (mut conns, workers) = local_conns(Transport::Unix, 2, |addr| {
+            let mut conn = connect(addr).unwrap();
+            let init = recv(&mut *conn, INIT).unwrap();
+            if init.ints[0] == 0 {
+                // Waits for the coordinator to give up on the other one.
+                send(&mut *conn, CANDIDATE, vec![0], vec![1.0]).unwrap();
+                let _ = Frame::read_from(&mut *conn);
+            }
+        });
+        match compute_gauss_distributed(&mut data, &mut conns, 3) {
+            Err(GaussError::Io(..)) => {}
+            other => panic!("{:?}", other),
+        }
+        drop(conns);
+        for w in workers {
+            w.join().unwrap();
+        }
+        assert_eq!(data.matrix, matrix);
+        assert_eq!(data.b, b);
+        assert_eq!(data.swap, swap);
+    }
+
+    #[test]
+    fn distributed_tcp() {
+        run_local(Transport::Tcp, 37, 3, 2);
+    }
+
+    #[test]
+    fn distributed_unix() {
+        run_local(Transport::Unix, 20, 2, 4);
+    }
+}
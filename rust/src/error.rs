@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use threadpool_crossbeam::panic::JobPanic;
+
+// Why a parallel solver stopped before the matrix was reduced. The matrix in
+// Data is left partly eliminated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GaussError {
+    // No non-zero pivot in this column.
+    Singular(usize),
+    // Cholesky met a diagonal that is not positive in this column.
+    NotPositiveDefinite(usize),
+    // Reducing [A | b] left 0 = c with c nonzero in this row.
+    Inconsistent(usize),
+    // Exact arithmetic went past the range of i128.
+    Overflow,
+    // Exact arithmetic was given a NaN or an infinity.
+    NotFinite,
+    // The Data holds a system set up by sparse::init_sparse, which only
+    // compute_gauss and compute_gauss_with can solve.
+    Sparse,
+    // Talking to the worker processes of compute_gauss_distributed failed.
+    Io(io::ErrorKind, String),
+    // The run was cancelled through Data::cancel, e.g. by Ctrl-C.
+    Cancelled,
+    // A pool job panicked; the message of the first panic. The pools of the
+    // solvers are fail-fast, so the rest of the phase was dropped.
+    Panicked(String),
+}
+
+impl fmt::Display for GaussError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GaussError::Singular(i) => write!(f, "The matrix is singular (column {})", i),
+            GaussError::NotPositiveDefinite(i) => {
+                write!(f, "The matrix is not positive definite (column {})", i)
+            }
+            GaussError::Inconsistent(i) => write!(f, "The system has no solution (row {})", i),
+            GaussError::Overflow => write!(f, "Integer overflow in exact arithmetic"),
+            GaussError::NotFinite => write!(f, "A NaN or infinity has no exact value"),
+            GaussError::Sparse => write!(f, "The matrix is sparse; this solver only takes dense ones"),
+            GaussError::Io(_, msg) => write!(f, "I/O error: {}", msg),
+            GaussError::Cancelled => write!(f, "Cancelled"),
+            GaussError::Panicked(msg) => write!(f, "A worker panicked: {}", msg),
+        }
+    }
+}
+
+impl Error for GaussError {}
+
+impl From<Vec<JobPanic>> for GaussError {
+    fn from(panics: Vec<JobPanic>) -> GaussError {
+        GaussError::Panicked(panics[0].message().to_string())
+    }
+}
+
+impl From<io::Error> for GaussError {
+    fn from(e: io::Error) -> GaussError {
+        GaussError::Io(e.kind(), e.to_string())
+    }
+}
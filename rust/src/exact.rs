@@ -0,0 +1,324 @@
+use crate::error::GaussError;
+use crate::{check_dense, Data};
+use std::fmt;
+
+// A fraction num/den in lowest terms with den > 0. Every operation checks
+// for overflow of i128 and reports it as GaussError::Overflow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rational {
+    pub num: i128,
+    pub den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a.abs()
+}
+
+fn checked(x: Option<i128>) -> Result<i128, GaussError> {
+    x.ok_or(GaussError::Overflow)
+}
+
+impl Rational {
+    // Panics if den is zero.
+    pub fn new(num: i128, den: i128) -> Result<Rational, GaussError> {
+        assert!(den != 0, "zero denominator");
+        let g = gcd(num, den);
+        let (num, den) = (num / g, den / g);
+        if den < 0 {
+            Ok(Rational {
+                num: checked(num.checked_neg())?,
+                den: checked(den.checked_neg())?,
+            })
+        } else {
+            Ok(Rational { num, den })
+        }
+    }
+
+    pub fn from_int(n: i128) -> Rational {
+        Rational { num: n, den: 1 }
+    }
+
+    // The exact value of x, which is m 2^e for integers m and e. Fails with
+    // Overflow if that does not fit, and NotFinite if x is NaN or infinite.
+    pub fn from_f64(x: f64) -> Result<Rational, GaussError> {
+        if !x.is_finite() {
+            return Err(GaussError::NotFinite);
+        }
+        if x == 0.0 {
+            return Ok(Rational::from_int(0));
+        }
+        let bits = x.to_bits();
+        let exp = ((bits >> 52) & 0x7ff) as i32;
+        let frac = (bits & ((1 << 52) - 1)) as i128;
+        let (mut m, mut e) = if exp == 0 {
+            (frac, -1074)
+        } else {
+            (frac | 1 << 52, exp - 1075)
+        };
+        while m % 2 == 0 {
+            m /= 2;
+            e += 1;
+        }
+        if x < 0.0 {
+            m = -m;
+        }
+        if e >= 0 {
+            let scale = checked(1i128.checked_shl(e as u32).filter(|_| e < 127))?;
+            Ok(Rational::from_int(checked(m.checked_mul(scale))?))
+        } else if e > -127 {
+            Ok(Rational { num: m, den: 1 << -e })
+        } else {
+            Err(GaussError::Overflow)
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn checked_add(self, other: Rational) -> Result<Rational, GaussError> {
+        let g = gcd(self.den, other.den);
+        let den = checked((self.den / g).checked_mul(other.den))?;
+        let a = checked(self.num.checked_mul(other.den / g))?;
+        let b = checked(other.num.checked_mul(self.den / g))?;
+        Rational::new(checked(a.checked_add(b))?, den)
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Result<Rational, GaussError> {
+        // Cross-cancel first so the products stay as small as they can.
+        let g1 = gcd(self.num, other.den).max(1);
+        let g2 = gcd(other.num, self.den).max(1);
+        let num = checked((self.num / g1).checked_mul(other.num / g2))?;
+        let den = checked((self.den / g2).checked_mul(other.den / g1))?;
+        Rational::new(num, den)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+// The result of Bareiss elimination on n rows: the first n columns are upper
+// triangular, and the last pivot is the determinant up to det's sign.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bareiss {
+    pub matrix: Vec<Vec<i128>>,
+    pub det: i128,
+    // swap[i] is the original row now at i.
+    pub swap: Vec<usize>,
+}
+
+// Fraction-free elimination: every step divides by the previous pivot,
+// which by Sylvester's identity divides exactly, so all entries stay integer
+// minors of the matrix and grow no faster than its determinant. Columns past
+// the first n (an appended right hand side) are carried along. A zero pivot
+// is swapped with the first nonzero entry below, as in compute_gauss.
+pub fn bareiss(matrix: &[Vec<i128>]) -> Result<Bareiss, GaussError> {
+    let n = matrix.len();
+    let mut m = matrix.to_vec();
+    let mut swap: Vec<usize> = (0..n).collect();
+    let mut negate = false;
+    let mut prev = 1;
+
+    for k in 0..n {
+        if m[k][k] == 0 {
+            let r = (k + 1..n).find(|&r| m[r][k] != 0).ok_or(GaussError::Singular(k))?;
+            m.swap(k, r);
+            swap.swap(k, r);
+            negate = !negate;
+        }
+        let (top, rest) = m.split_at_mut(k + 1);
+        let pivot = &top[k];
+        for row in rest {
+            let f = row[k];
+            for (x, &p) in row[k + 1..].iter_mut().zip(&pivot[k + 1..]) {
+                let a = checked(x.checked_mul(pivot[k]))?;
+                let b = checked(f.checked_mul(p))?;
+                *x = checked(checked(a.checked_sub(b))?.checked_div(prev))?;
+            }
+            row[k] = 0;
+        }
+        prev = m[k][k];
+    }
+
+    let det = if n == 0 {
+        1
+    } else if negate {
+        checked(m[n - 1][n - 1].checked_neg())?
+    } else {
+        m[n - 1][n - 1]
+    };
+    Ok(Bareiss { matrix: m, det, swap })
+}
+
+// Zero for a singular matrix.
+pub fn determinant_exact(matrix: &[Vec<i128>]) -> Result<i128, GaussError> {
+    match bareiss(matrix) {
+        Ok(b) => Ok(b.det),
+        Err(GaussError::Singular(_)) => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+// Solves A x = b exactly. Bareiss on [A | b] leaves U with U[n-1][n-1] = d,
+// the determinant up to sign; by Cramer's rule d x is an integer vector, so
+// back substitution for d x divides exactly.
+pub fn solve_exact(matrix: &[Vec<i128>], b: &[i128]) -> Result<Vec<Rational>, GaussError> {
+    let n = matrix.len();
+    let augmented: Vec<Vec<i128>> = matrix
+        .iter()
+        .zip(b)
+        .map(|(row, &b)| {
+            let mut row = row.clone();
+            row.push(b);
+            row
+        })
+        .collect();
+    let u = bareiss(&augmented)?.matrix;
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+
+    let d = u[n - 1][n - 1];
+    let mut y = vec![0i128; n];
+    for i in (0..n).rev() {
+        let mut s = checked(d.checked_mul(u[i][n]))?;
+        for j in i + 1..n {
+            s = checked(s.checked_sub(checked(u[i][j].checked_mul(y[j]))?))?;
+        }
+        y[i] = checked(s.checked_div(u[i][i]))?;
+    }
+    y.into_iter().map(|y| Rational::new(y, d)).collect()
+}
+
+// solve_exact on data's system as it stands. Every f64 is a fraction with a
+// power of two for denominator, so each row is scaled by the least common
+// multiple of its denominators to make it integer without changing the
+// solution.
+pub fn solve_data_exact(data: &Data) -> Result<Vec<Rational>, GaussError> {
+    check_dense(data)?;
+    let mut matrix = Vec::with_capacity(data.nsize);
+    let mut b = Vec::with_capacity(data.nsize);
+    for (row, &rhs) in data.matrix.iter().zip(&data.b) {
+        let row: Vec<Rational> = row
+            .iter()
+            .chain(Some(&rhs))
+            .map(|&x| Rational::from_f64(x))
+            .collect::<Result<_, _>>()?;
+        let mut scale = 1i128;
+        for x in &row {
+            scale = checked((scale / gcd(scale, x.den)).checked_mul(x.den))?;
+        }
+        let mut ints = row
+            .iter()
+            .map(|x| checked(x.num.checked_mul(scale / x.den)))
+            .collect::<Result<Vec<i128>, _>>()?;
+        b.push(ints.pop().unwrap());
+        matrix.push(ints);
+    }
+    solve_exact(&matrix, &b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{new_data, Lcg};
+    use crate::inverse::lu;
+    use crate::{back_substitute, compute_gauss, init};
+
+    fn int_matrix(nsize: usize, seed: u64) -> Vec<Vec<i128>> {
+        let mut rng = Lcg::new(seed);
+        (0..nsize)
+            .map(|_| (0..nsize).map(|_| (rng.next_u64() >> 33) as i128 % 19 - 9).collect())
+            .collect()
+    }
+
+    #[test]
+    fn rationals() {
+        assert_eq!(Rational::new(2, -4), Ok(Rational { num: -1, den: 2 }));
+        assert_eq!(Rational::from_f64(0.75), Ok(Rational { num: 3, den: 4 }));
+        assert_eq!(Rational::from_f64(-6.0), Ok(Rational::from_int(-6)));
+        let tenth = Rational::from_f64(0.1).unwrap();
+        assert_eq!(tenth.den, 1 << 55);
+        assert_eq!(tenth.to_f64(), 0.1);
+        assert_eq!(Rational::from_f64(f64::NAN), Err(GaussError::NotFinite));
+        assert_eq!(Rational::from_f64(f64::INFINITY), Err(GaussError::NotFinite));
+        assert_eq!(Rational::from_f64(1e300), Err(GaussError::Overflow));
+
+        let third = Rational::new(1, 3).unwrap();
+        let sum = third.checked_add(Rational::new(1, 6).unwrap()).unwrap();
+        assert_eq!(format!("{}", sum), "1/2");
+        assert_eq!(sum.checked_mul(Rational::from_int(4)).unwrap().to_string(), "2");
+    }
+
+    #[test]
+    fn exact_determinants() {
+        let mut data = new_data(12, 1);
+        init(&mut data);
+        let matrix: Vec<Vec<i128>> = data.matrix.iter().map(|row| row.iter().map(|&x| x as i128).collect()).collect();
+        assert_eq!(determinant_exact(&matrix), Ok(1 << 12));
+        assert_eq!(determinant_exact(&[vec![0, 1], vec![1, 0]]), Ok(-1));
+        assert_eq!(determinant_exact(&[vec![2, 4], vec![1, 2]]), Ok(0));
+        assert_eq!(determinant_exact(&[vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 10]]), Ok(-3));
+    }
+
+    #[test]
+    fn exact_solution_checks_float_paths() {
+        // init's system has the solution -1/2, 0, ..., 0, 1/2.
+        let nsize = 10;
+        let mut data = new_data(nsize, 1);
+        init(&mut data);
+        let exact = solve_data_exact(&data).unwrap();
+        let mut want = vec![Rational::from_int(0); nsize];
+        want[0] = Rational::new(-1, 2).unwrap();
+        want[nsize - 1] = Rational::new(1, 2).unwrap();
+        assert_eq!(exact, want);
+
+        let by_lu = lu(&data).unwrap().solve(&data.b);
+        compute_gauss(&mut data);
+        back_substitute(&mut data);
+        for ((x, y), e) in data.c.iter().zip(&by_lu).zip(&exact) {
+            assert!((x - e.to_f64()).abs() < 1e-12);
+            assert!((y - e.to_f64()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn exact_solve_satisfies_system() {
+        let matrix = int_matrix(7, 5);
+        let b: Vec<i128> = (0..7).map(|i| i * i - 3).collect();
+        let x = solve_exact(&matrix, &b).unwrap();
+        for (row, &b) in matrix.iter().zip(&b) {
+            let mut sum = Rational::from_int(0);
+            for (&a, &x) in row.iter().zip(&x) {
+                sum = sum.checked_add(Rational::from_int(a).checked_mul(x).unwrap()).unwrap();
+            }
+            assert_eq!(sum, Rational::from_int(b));
+        }
+    }
+
+    #[test]
+    fn overflow_is_reported() {
+        let big = i128::MAX / 2;
+        assert_eq!(bareiss(&[vec![big, 3], vec![3, big]]), Err(GaussError::Overflow));
+        // The second step divides i128::MIN by the first pivot, -1.
+        let m = vec![
+            vec![-1, 0, 0],
+            vec![0, -1, -(1 << 63)],
+            vec![0, -(1 << 63), 1 << 126],
+        ];
+        assert_eq!(bareiss(&m), Err(GaussError::Overflow));
+        assert_eq!(solve_exact(&[vec![0, 0], vec![0, 1]], &[1, 1]), Err(GaussError::Singular(0)));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::error::GaussError;
+use crate::parallel::{for_rows, new_pool};
+use threadpool_crossbeam::ThreadPool;
+
+fn mul_mod(a: u64, b: u64, p: u64) -> u64 {
+    (a as u128 * b as u128 % p as u128) as u64
+}
+
+fn sub_mod(a: u64, b: u64, p: u64) -> u64 {
+    if a >= b {
+        a - b
+    } else {
+        p - (b - a)
+    }
+}
+
+fn pow_mod(mut a: u64, mut e: u64, p: u64) -> u64 {
+    let mut r = 1 % p;
+    while e > 0 {
+        if e & 1 == 1 {
+            r = mul_mod(r, a, p);
+        }
+        a = mul_mod(a, a, p);
+        e >>= 1;
+    }
+    r
+}
+
+// The inverse of a nonzero a by Fermat's little theorem.
+fn inv_mod(a: u64, p: u64) -> u64 {
+    pow_mod(a, p - 2, p)
+}
+
+// Miller-Rabin with the first twelve primes as witnesses, which is exact for
+// every u64.
+pub fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(&w) = WITNESSES.iter().find(|&&w| n.is_multiple_of(w)) {
+        return n == w;
+    }
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+// What a pivot column means in a field. Rows are words: one residue per word
+// for GF(p), 64 packed bits per word for GF(2).
+trait Field: Copy + Send + Sync + 'static {
+    fn is_nonzero(self, row: &[u64], col: usize) -> bool;
+    // Scales row so that row[col] is 1.
+    fn normalize(self, row: &mut [u64], col: usize);
+    // Subtracts row[col] times pivot, which has a 1 at col, from row.
+    fn eliminate(self, row: &mut [u64], pivot: &[u64], col: usize);
+}
+
+#[derive(Clone, Copy)]
+struct Prime(u64);
+
+impl Field for Prime {
+    fn is_nonzero(self, row: &[u64], col: usize) -> bool {
+        row[col] != 0
+    }
+
+    fn normalize(self, row: &mut [u64], col: usize) {
+        let inv = inv_mod(row[col], self.0);
+        for x in row[col..].iter_mut() {
+            *x = mul_mod(*x, inv, self.0);
+        }
+    }
+
+    fn eliminate(self, row: &mut [u64], pivot: &[u64], col: usize) {
+        let f = row[col];
+        for (x, &q) in row[col..].iter_mut().zip(&pivot[col..]) {
+            *x = sub_mod(*x, mul_mod(f, q, self.0), self.0);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Binary;
+
+impl Field for Binary {
+    fn is_nonzero(self, row: &[u64], col: usize) -> bool {
+        row[col / 64] >> (col % 64) & 1 == 1
+    }
+
+    fn normalize(self, _row: &mut [u64], _col: usize) {}
+
+    // Words before the pivot's are zero in both rows by now.
+    fn eliminate(self, row: &mut [u64], pivot: &[u64], col: usize) {
+        for (x, q) in row[col / 64..].iter_mut().zip(&pivot[col / 64..]) {
+            *x ^= q;
+        }
+    }
+}
+
+// Gauss-Jordan over a field: the first row from the diagonal down with a
+// nonzero in the column is the pivot, since every nonzero is as good as any
+// other; a column without one is skipped. The clearing of each column is
+// split over the pool if there is one. Returns the reduced rows and the
+// pivot columns.
+fn reduce<F: Field>(
+    field: F,
+    matrix: Vec<Vec<u64>>,
+    ncols: usize,
+    pool: Option<&ThreadPool>,
+) -> Result<(Vec<Vec<u64>>, Vec<usize>), GaussError> {
+    let nrows = matrix.len();
+    let mut rows = matrix;
+    let mut pivots = Vec::new();
+
+    for col in 0..ncols {
+        let at = pivots.len();
+        if at == nrows {
+            break;
+        }
+        let p = match (at..nrows).find(|&i| field.is_nonzero(&rows[i], col)) {
+            Some(p) => p,
+            None => continue,
+        };
+        rows.swap(p, at);
+        field.normalize(&mut rows[at], col);
+        let pivot = rows[at].clone();
+
+        // Clears the column from every row but the pivot row.
+        for_rows(pool, &mut rows, 0, move |i, row| {
+            if i != at && field.is_nonzero(row, col) {
+                field.eliminate(row, &pivot, col);
+            }
+        })?;
+        pivots.push(col);
+    }
+    Ok((rows, pivots))
+}
+
+// The reduced row echelon form over GF(p), as rref::Rref: row i has a 1 in
+// column pivots[i] and that column is zero elsewhere.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModRref {
+    pub p: u64,
+    pub rows: Vec<Vec<u64>>,
+    pub rank: usize,
+    pub pivots: Vec<usize>,
+}
+
+fn factor_mod(a: &[Vec<u64>], p: u64, pool: Option<&ThreadPool>) -> Result<ModRref, GaussError> {
+    assert!(is_prime(p), "{} is not prime", p);
+    let ncols = a.first().map_or(0, Vec::len);
+    assert!(a.iter().all(|row| row.len() == ncols), "rows of different lengths");
+    let matrix = a.iter().map(|row| row.iter().map(|x| x % p).collect()).collect();
+    let (rows, pivots) = reduce(Prime(p), matrix, ncols, pool)?;
+    Ok(ModRref {
+        p,
+        rows,
+        rank: pivots.len(),
+        pivots,
+    })
+}
+
+// Entries are taken mod p. Panics if p is not prime.
+pub fn rref_mod(a: &[Vec<u64>], p: u64) -> ModRref {
+    factor_mod(a, p, None).expect("sequential elimination failed")
+}
+
+pub fn rref_mod_p(a: &[Vec<u64>], p: u64, num_threads: usize) -> Result<ModRref, GaussError> {
+    let pool = new_pool(num_threads.max(1));
+    factor_mod(a, p, Some(&pool))
+}
+
+pub fn rank_mod(a: &[Vec<u64>], p: u64) -> usize {
+    rref_mod(a, p).rank
+}
+
+fn augment(a: &[Vec<u64>], b: &[u64]) -> Vec<Vec<u64>> {
+    assert_eq!(b.len(), a.len(), "right hand side does not match the rows");
+    a.iter()
+        .zip(b)
+        .map(|(row, &b)| {
+            let mut row = row.clone();
+            row.push(b);
+            row
+        })
+        .collect()
+}
+
+// A solution of [A | b] reduced: Inconsistent if b's column has a pivot,
+// otherwise the free variables are zero.
+fn particular(rows: &[Vec<u64>], pivots: &[usize], n: usize, get: impl Fn(&[u64], usize) -> u64) -> Result<Vec<u64>, GaussError> {
+    if let Some(i) = pivots.iter().position(|&c| c == n) {
+        return Err(GaussError::Inconsistent(i));
+    }
+    let mut x = vec![0; n];
+    for (row, &c) in rows.iter().zip(pivots) {
+        x[c] = get(row, n);
+    }
+    Ok(x)
+}
+
+// One solution of A x = b mod p, with every free variable zero.
+pub fn solve_mod(a: &[Vec<u64>], b: &[u64], p: u64) -> Result<Vec<u64>, GaussError> {
+    let n = a.first().map_or(0, Vec::len);
+    let r = rref_mod(&augment(a, b), p);
+    particular(&r.rows, &r.pivots, n, |row, j| row[j])
+}
+
+pub fn solve_mod_p(a: &[Vec<u64>], b: &[u64], p: u64, num_threads: usize) -> Result<Vec<u64>, GaussError> {
+    let n = a.first().map_or(0, Vec::len);
+    let r = rref_mod_p(&augment(a, b), p, num_threads)?;
+    particular(&r.rows, &r.pivots, n, |row, j| row[j])
+}
+
+// A matrix over GF(2) with each row packed 64 columns to a word: column j is
+// bit j % 64 of word j / 64. Bits past ncols are zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub rows: Vec<Vec<u64>>,
+}
+
+impl BitMatrix {
+    pub fn new(nrows: usize, ncols: usize) -> BitMatrix {
+        BitMatrix {
+            nrows,
+            ncols,
+            rows: vec![vec![0; ncols.div_ceil(64)]; nrows],
+        }
+    }
+
+    // Panics if the rows have different lengths.
+    pub fn from_rows(rows: &[Vec<bool>]) -> BitMatrix {
+        let ncols = rows.first().map_or(0, Vec::len);
+        assert!(rows.iter().all(|row| row.len() == ncols), "rows of different lengths");
+        let mut m = BitMatrix::new(rows.len(), ncols);
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &bit) in row.iter().enumerate() {
+                m.set(i, j, bit);
+            }
+        }
+        m
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> bool {
+        Binary.is_nonzero(&self.rows[i], j)
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, bit: bool) {
+        let (w, mask) = (j / 64, 1u64 << (j % 64));
+        if bit {
+            self.rows[i][w] |= mask;
+        } else {
+            self.rows[i][w] &= !mask;
+        }
+    }
+
+    // A x over GF(2): the parity of each row ANDed with x.
+    pub fn mul_vec(&self, x: &[bool]) -> Vec<bool> {
+        assert_eq!(x.len(), self.ncols, "vector does not match the columns");
+        let packed = BitMatrix::from_rows(&[x.to_vec()]).rows.remove(0);
+        self.rows
+            .iter()
+            .map(|row| row.iter().zip(&packed).map(|(a, b)| (a & b).count_ones()).sum::<u32>() % 2 == 1)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gf2Rref {
+    pub matrix: BitMatrix,
+    pub rank: usize,
+    pub pivots: Vec<usize>,
+}
+
+fn factor_gf2(a: &BitMatrix, pool: Option<&ThreadPool>) -> Result<Gf2Rref, GaussError> {
+    let (rows, pivots) = reduce(Binary, a.rows.clone(), a.ncols, pool)?;
+    Ok(Gf2Rref {
+        matrix: BitMatrix {
+            nrows: a.nrows,
+            ncols: a.ncols,
+            rows,
+        },
+        rank: pivots.len(),
+        pivots,
+    })
+}
+
+// Every row update is an XOR of the words from the pivot's on.
+pub fn rref_gf2(a: &BitMatrix) -> Gf2Rref {
+    factor_gf2(a, None).expect("sequential elimination failed")
+}
+
+pub fn rref_gf2_p(a: &BitMatrix, num_threads: usize) -> Result<Gf2Rref, GaussError> {
+    let pool = new_pool(num_threads.max(1));
+    factor_gf2(a, Some(&pool))
+}
+
+pub fn rank_gf2(a: &BitMatrix) -> usize {
+    rref_gf2(a).rank
+}
+
+fn augment_gf2(a: &BitMatrix, b: &[bool]) -> BitMatrix {
+    assert_eq!(b.len(), a.nrows, "right hand side does not match the rows");
+    let mut m = BitMatrix::new(a.nrows, a.ncols + 1);
+    for (row, src) in m.rows.iter_mut().zip(&a.rows) {
+        row[..src.len()].copy_from_slice(src);
+    }
+    for (i, &bit) in b.iter().enumerate() {
+        m.set(i, a.ncols, bit);
+    }
+    m
+}
+
+fn solution_gf2(r: &Gf2Rref, n: usize) -> Result<Vec<bool>, GaussError> {
+    let x = particular(&r.matrix.rows, &r.pivots, n, |row, j| Binary.is_nonzero(row, j) as u64)?;
+    Ok(x.into_iter().map(|x| x == 1).collect())
+}
+
+// One solution of A x = b over GF(2), with every free variable zero.
+pub fn solve_gf2(a: &BitMatrix, b: &[bool]) -> Result<Vec<bool>, GaussError> {
+    solution_gf2(&rref_gf2(&augment_gf2(a, b)), a.ncols)
+}
+
+pub fn solve_gf2_p(a: &BitMatrix, b: &[bool], num_threads: usize) -> Result<Vec<bool>, GaussError> {
+    solution_gf2(&rref_gf2_p(&augment_gf2(a, b), num_threads)?, a.ncols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Lcg;
+
+    // The largest prime below 2^64, so products need the full u128.
+    const BIG: u64 = 18446744073709551557;
+
+    fn random_mod(nrows: usize, ncols: usize, p: u64, seed: u64) -> Vec<Vec<u64>> {
+        let mut rng = Lcg::new(seed);
+        (0..nrows).map(|_| (0..ncols).map(|_| (rng.next_u64() >> 11) % p).collect()).collect()
+    }
+
+    fn random_bits(nrows: usize, ncols: usize, seed: u64) -> BitMatrix {
+        let mut rng = Lcg::new(seed);
+        let rows: Vec<Vec<bool>> = (0..nrows)
+            .map(|_| (0..ncols).map(|_| (rng.next_u64() >> 11) % 2 == 1).collect())
+            .collect();
+        BitMatrix::from_rows(&rows)
+    }
+
+    fn mul_vec_mod(a: &[Vec<u64>], x: &[u64], p: u64) -> Vec<u64> {
+        a.iter()
+            .map(|row| row.iter().zip(x).fold(0, |s, (&a, &x)| ((s as u128 + mul_mod(a, x, p) as u128) % p as u128) as u64))
+            .collect()
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        assert!(is_prime(2) && is_prime(1_000_000_007) && is_prime(BIG));
+        assert!(!is_prime(1) && !is_prime(561) && !is_prime(BIG - 2));
+        assert_eq!(mul_mod(BIG - 1, BIG - 1, BIG), 1);
+        assert_eq!(mul_mod(inv_mod(12345, BIG), 12345, BIG), 1);
+        assert_eq!(sub_mod(3, 5, 7), 5);
+    }
+
+    #[test]
+    fn rank_depends_on_the_field() {
+        // The determinant is -2.
+        let a = vec![vec![1, 2], vec![3, 4]];
+        assert_eq!(rank_mod(&a, 2), 1);
+        assert_eq!(rank_mod(&a, 7), 2);
+        let bits = BitMatrix::from_rows(&[vec![true, false], vec![true, false]]);
+        assert_eq!(rank_gf2(&bits), 1);
+
+        let r = rref_mod(&a, 7);
+        assert_eq!(r.rows, vec![vec![1, 0], vec![0, 1]]);
+    }
+
+    #[test]
+    fn solve_over_gfp() {
+        for &p in &[101, 1_000_000_007, BIG] {
+            let a = random_mod(30, 30, p, p);
+            let b: Vec<u64> = (0..30).map(|i| i * 7 % p).collect();
+            let x = solve_mod(&a, &b, p).unwrap();
+            assert_eq!(mul_vec_mod(&a, &x, p), b);
+            assert_eq!(solve_mod_p(&a, &b, p, 3).unwrap(), x);
+        }
+
+        // The third row is the sum of the first two, mod 5.
+        let a = vec![vec![1, 2, 3], vec![4, 4, 0], vec![0, 1, 3]];
+        assert_eq!(rank_mod(&a, 5), 2);
+        assert_eq!(solve_mod(&a, &[1, 1, 0], 5), Err(GaussError::Inconsistent(2)));
+        let x = solve_mod(&a, &[1, 1, 2], 5).unwrap();
+        assert_eq!(mul_vec_mod(&a, &x, 5), vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn solve_over_gf2() {
+        // Wider than two words, so updates cross word boundaries.
+        let a = random_bits(150, 140, 42);
+        let r = rref_gf2(&a);
+        assert_eq!(rref_gf2_p(&a, 4).unwrap(), r);
+        assert!(r.rank <= 140);
+
+        let x: Vec<bool> = (0..140).map(|i| i % 5 < 2).collect();
+        let b_in_range = a.mul_vec(&x);
+        let y = solve_gf2(&a, &b_in_range).unwrap();
+        assert_eq!(a.mul_vec(&y), b_in_range);
+        assert_eq!(solve_gf2_p(&a, &b_in_range, 3).unwrap(), y);
+        let dup = BitMatrix::from_rows(&[vec![true, true, false], vec![true, true, false]]);
+        assert_eq!(solve_gf2(&dup, &[true, false]), Err(GaussError::Inconsistent(1)));
+    }
+}
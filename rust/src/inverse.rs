@@ -0,0 +1,358 @@
+use crate::error::GaussError;
+use crate::parallel::pool_for;
+use crate::{check_dense, Data};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+// Columns of the identity one inverse_p job solves for.
+pub const DEFAULT_COLUMNS: usize = 32;
+
+// The sign of the row permutation a swap vector records, such as data.swap
+// after compute_gauss: -1 for an odd number of swaps.
+pub fn swap_sign(swap: &[u64]) -> f64 {
+    let mut seen = vec![false; swap.len()];
+    let mut odd = false;
+    for start in 0..swap.len() {
+        let mut len = 0;
+        let mut i = start;
+        while !seen[i] {
+            seen[i] = true;
+            i = swap[i] as usize;
+            len += 1;
+        }
+        // A cycle of length l takes l - 1 swaps.
+        if len > 0 && len % 2 == 0 {
+            odd = !odd;
+        }
+    }
+    if odd {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+// The factors of P A = L U, with P the row swaps: L on and below the
+// diagonal, U on and above it. lu leaves ones on L's diagonal;
+// lib::compute_gauss_lu puts what it scaled each pivot row by there and
+// keeps the U compute_gauss leaves, with its unit diagonal.
+pub struct Lu {
+    // Row i of L is l[i][..=i].
+    l: Vec<Vec<f64>>,
+    u: Vec<Vec<f64>>,
+    swap: Vec<u64>,
+}
+
+// Partial pivoting: the row from i down with the largest entry in column i,
+// as in rref and parallel::find_pivot. Taking the first nonzero entry
+// instead lets a tiny pivot blow up everything eliminated with it.
+fn largest(m: &[Vec<f64>], i: usize) -> Result<usize, GaussError> {
+    let (irow, big) = (i..m.len())
+        .map(|r| (r, m[r][i].abs()))
+        .fold((i, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+    if big == 0.0 {
+        return Err(GaussError::Singular(i));
+    }
+    Ok(irow)
+}
+
+// compute_gauss's elimination without the scaling of the pivot rows, and
+// with partial pivoting; the row swaps go to swap.
+pub fn lu(data: &Data) -> Result<Lu, GaussError> {
+    check_dense(data)?;
+    let n = data.nsize;
+    let mut m = data.matrix.clone();
+    let mut swap: Vec<u64> = (0..n as u64).collect();
+    for i in 0..n {
+        let irow = largest(&m, i)?;
+        if irow != i {
+            m.swap(irow, i);
+            swap.swap(irow, i);
+        }
+        let (top, rest) = m.split_at_mut(i + 1);
+        let pivot = &top[i];
+        for row in rest {
+            let f = row[i] / pivot[i];
+            row[i] = f;
+            if f != 0.0 {
+                for (x, p) in row[i + 1..].iter_mut().zip(&pivot[i + 1..]) {
+                    *x -= f * p;
+                }
+            }
+        }
+    }
+    let l = m
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut l = row[..i].to_vec();
+            l.push(1.0);
+            l
+        })
+        .collect();
+    Ok(Lu::new(l, m, swap))
+}
+
+impl Lu {
+    // `u` is only read on and above the diagonal. swap[i] is the row of A
+    // that ended up in row i, as in data.swap.
+    pub fn new(l: Vec<Vec<f64>>, u: Vec<Vec<f64>>, swap: Vec<u64>) -> Lu {
+        Lu { l, u, swap }
+    }
+
+    pub fn nsize(&self) -> usize {
+        self.u.len()
+    }
+
+    // The pivots of the elimination, whose product is det(P A).
+    fn pivots(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.nsize()).map(move |i| self.l[i][i] * self.u[i][i])
+    }
+
+    // Solves A x = b.
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.nsize();
+        let mut x: Vec<f64> = self.swap.iter().map(|&k| b[k as usize]).collect();
+        for i in 0..n {
+            let s: f64 = self.l[i][..i].iter().zip(&x[..i]).map(|(l, x)| l * x).sum();
+            x[i] = (x[i] - s) / self.l[i][i];
+        }
+        for i in (0..n).rev() {
+            let s: f64 = self.u[i][i + 1..].iter().zip(&x[i + 1..]).map(|(u, x)| u * x).sum();
+            x[i] = (x[i] - s) / self.u[i][i];
+        }
+        x
+    }
+
+    // Solves A^T x = b: U^T, then L^T, then the row swaps undone.
+    pub fn solve_transpose(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.nsize();
+        let mut w = b.to_vec();
+        for i in 0..n {
+            let s: f64 = (0..i).map(|k| self.u[k][i] * w[k]).sum();
+            w[i] = (w[i] - s) / self.u[i][i];
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|k| self.l[k][i] * w[k]).sum();
+            w[i] = (w[i] - s) / self.l[i][i];
+        }
+        let mut x = vec![0.0; n];
+        for (i, &k) in self.swap.iter().enumerate() {
+            x[k as usize] = w[i];
+        }
+        x
+    }
+}
+
+// The sign of det(A) and the log of its magnitude, which stays finite where
+// the determinant itself over- or underflows. A singular matrix gives
+// (0.0, -inf).
+pub fn log_determinant(data: &Data) -> Result<(f64, f64), GaussError> {
+    match lu(data) {
+        Ok(lu) => Ok(lu.pivots().fold((swap_sign(&lu.swap), 0.0), |(sign, log), p| {
+            (sign * p.signum(), log + p.abs().ln())
+        })),
+        Err(GaussError::Singular(_)) => Ok((0.0, f64::NEG_INFINITY)),
+        Err(e) => Err(e),
+    }
+}
+
+// The product of the pivots, with the sign of the row swaps.
+pub fn determinant(data: &Data) -> Result<f64, GaussError> {
+    match lu(data) {
+        Ok(lu) => Ok(lu.pivots().fold(swap_sign(&lu.swap), |det, p| det * p)),
+        Err(GaussError::Singular(_)) => Ok(0.0),
+        Err(e) => Err(e),
+    }
+}
+
+// Gauss-Jordan on [A | I] with partial pivoting: each pivot row is scaled to
+// a leading 1 and its column cleared above and below, which leaves
+// [I | A^-1].
+pub fn inverse(data: &Data) -> Result<Vec<Vec<f64>>, GaussError> {
+    check_dense(data)?;
+    let n = data.nsize;
+    let mut aug: Vec<Vec<f64>> = data
+        .matrix
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut row = row.clone();
+            row.resize(2 * n, 0.0);
+            row[n + i] = 1.0;
+            row
+        })
+        .collect();
+
+    for i in 0..n {
+        let irow = largest(&aug, i)?;
+        aug.swap(irow, i);
+        let pivot_val = aug[i][i];
+        for x in aug[i][i..].iter_mut() {
+            *x /= pivot_val;
+        }
+        let pivot = aug[i].clone();
+        for (r, row) in aug.iter_mut().enumerate() {
+            let f = row[i];
+            if r == i || f == 0.0 {
+                continue;
+            }
+            for (x, p) in row[i..].iter_mut().zip(&pivot[i..]) {
+                *x -= f * p;
+            }
+        }
+    }
+    Ok(aug.into_iter().map(|row| row[n..].to_vec()).collect())
+}
+
+// inverse by columns: A is factored once, then pool jobs solve for blocks of
+// `columns` columns of the identity at a time, independently of each other.
+// Uses data.num_threads workers.
+pub fn inverse_p(data: &Data, columns: usize) -> Result<Vec<Vec<f64>>, GaussError> {
+    let n = data.nsize;
+    let columns = columns.max(1);
+    let lu = Arc::new(lu(data)?);
+    if data.cancel.is_cancelled() {
+        return Err(GaussError::Cancelled);
+    }
+    let pool = pool_for(data).build();
+    let (tx, rx) = channel();
+    for start in (0..n).step_by(columns) {
+        let (lu, tx) = (Arc::clone(&lu), tx.clone());
+        pool.execute(move || {
+            let block: Vec<Vec<f64>> = (start..(start + columns).min(n))
+                .map(|j| {
+                    let mut e = vec![0.0; n];
+                    e[j] = 1.0;
+                    lu.solve(&e)
+                })
+                .collect();
+            tx.send((start, block)).expect("inverse column channel closed");
+        });
+    }
+    drop(tx);
+    pool.join()?;
+    if data.cancel.is_cancelled() {
+        return Err(GaussError::Cancelled);
+    }
+
+    let mut inv = vec![vec![0.0; n]; n];
+    for (start, block) in rx {
+        for (j, col) in block.iter().enumerate() {
+            for (row, &x) in inv.iter_mut().zip(col) {
+                row[start + j] = x;
+            }
+        }
+    }
+    Ok(inv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::{compute_gauss, init};
+
+    fn from_matrix(matrix: Vec<Vec<f64>>) -> Data {
+        let nsize = matrix.len();
+        let mut data = new_data(nsize, 1);
+        data.matrix = matrix;
+        data.b = vec![0.0; nsize];
+        data.swap = (0..nsize as u64).collect();
+        data
+    }
+
+    // init's matrix is 2 min(i + 1, j + 1): twice L L^T with L all ones on
+    // and below the diagonal. So its determinant is 2^n, and its inverse is
+    // tridiagonal with 1 on the diagonal (0.5 in the corner) and -0.5 next
+    // to it.
+    fn closed_form_inverse(nsize: usize) -> Vec<Vec<f64>> {
+        (0..nsize)
+            .map(|i| {
+                (0..nsize)
+                    .map(|j| match (i, j) {
+                        _ if i == j && i == nsize - 1 => 0.5,
+                        _ if i == j => 1.0,
+                        _ if i + 1 == j || j + 1 == i => -0.5,
+                        _ => 0.0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn determinant_of_init() {
+        let mut data = new_data(20, 1);
+        init(&mut data);
+        assert!((determinant(&data).unwrap() - 2f64.powi(20)).abs() < 1e-6);
+        let (sign, log) = log_determinant(&data).unwrap();
+        assert_eq!(sign, 1.0);
+        assert!((log - 20.0 * 2f64.ln()).abs() < 1e-9);
+
+        // Too big for an f64, but not for its log.
+        data.matrix.iter_mut().flatten().for_each(|x| *x *= 1e20);
+        assert_eq!(determinant(&data).unwrap(), f64::INFINITY);
+        let (sign, log) = log_determinant(&data).unwrap();
+        assert_eq!(sign, 1.0);
+        assert!((log - 20.0 * (2e20f64).ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn determinant_sign() {
+        let data = from_matrix(vec![vec![0.0, 2.0], vec![3.0, 1.0]]);
+        assert_eq!(determinant(&data).unwrap(), -6.0);
+        assert_eq!(log_determinant(&data).unwrap().0, -1.0);
+
+        let mut data = from_matrix(vec![vec![0.0, 2.0], vec![3.0, 1.0]]);
+        compute_gauss(&mut data);
+        assert_eq!(swap_sign(&data.swap), -1.0);
+        assert_eq!(swap_sign(&[1, 2, 0]), 1.0);
+        assert_eq!(swap_sign(&[3, 2, 1, 0]), 1.0);
+        assert_eq!(swap_sign(&[1, 0, 2, 3]), -1.0);
+
+        let singular = from_matrix(vec![vec![1.0, 2.0], vec![2.0, 4.0]]);
+        assert_eq!(determinant(&singular).unwrap(), 0.0);
+        assert_eq!(log_determinant(&singular).unwrap(), (0.0, f64::NEG_INFINITY));
+        assert_eq!(inverse(&singular), Err(GaussError::Singular(1)));
+    }
+
+    #[test]
+    fn inverse_of_init() {
+        let nsize = 30;
+        let mut data = new_data(nsize, 3);
+        init(&mut data);
+        let expected = closed_form_inverse(nsize);
+        for inv in [inverse(&data).unwrap(), inverse_p(&data, 4).unwrap()] {
+            for (row, want) in inv.iter().zip(&expected) {
+                for (x, y) in row.iter().zip(want) {
+                    assert!((x - y).abs() < 1e-9, "{} != {}", x, y);
+                }
+            }
+        }
+
+        data.cancel.cancel();
+        assert_eq!(inverse_p(&data, 4), Err(GaussError::Cancelled));
+    }
+
+    #[test]
+    fn small_pivot() {
+        // Pivoting on the 1e-20 would leave 1 - 1e20 in the corner and lose
+        // the 1 to rounding. The inverse is [[-1, 1], [1, -1e-20]] to within
+        // rounding.
+        let mut data = from_matrix(vec![vec![1e-20, 1.0], vec![1.0, 1.0]]);
+        data.num_threads = 2;
+        let expected = [[-1.0, 1.0], [1.0, 0.0]];
+        for inv in [inverse(&data).unwrap(), inverse_p(&data, 1).unwrap()] {
+            for (row, want) in inv.iter().zip(&expected) {
+                for (x, y) in row.iter().zip(want) {
+                    assert!((x - y).abs() < 1e-12, "{:?}", inv);
+                }
+            }
+        }
+        assert!((determinant(&data).unwrap() + 1.0).abs() < 1e-12);
+        assert_eq!(log_determinant(&data).unwrap().0, -1.0);
+        let x = lu(&data).unwrap().solve(&[1.0, 2.0]);
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] - 1.0).abs() < 1e-12, "{:?}", x);
+    }
+}
@@ -1,4 +1,3 @@
-#[macro_use]
 macro_rules! SWAP {
     ($a:expr,$b:expr) => {{
         // $a ^= $b;
@@ -12,9 +11,33 @@ macro_rules! SWAP {
 
 // use core::num;
 use std::sync::{Arc, Mutex};
-use threadpool::ThreadPool;
-//use threadpool_crossbeam::ThreadPool;
-extern crate threadpool;
+use threadpool_crossbeam::affinity::Affinity;
+use threadpool_crossbeam::cancel::CancelToken;
+use crate::error::GaussError;
+use crate::inverse::Lu;
+use crate::parallel::pool_for;
+use crate::sparse::Sparse;
+
+pub mod actor;
+pub mod banded;
+pub mod channel;
+pub mod condition;
+pub mod dag;
+pub mod distributed;
+pub mod error;
+pub mod exact;
+pub mod finite;
+pub mod inverse;
+pub mod matrix;
+pub mod ordering;
+pub mod parallel;
+pub mod pipeline;
+pub mod qr;
+pub mod rref;
+pub mod sparse;
+pub mod symmetric;
+#[cfg(test)]
+pub(crate) mod test_util;
 
 #[derive(Debug)]
 pub struct Data {
@@ -25,6 +48,24 @@ pub struct Data {
     pub v: Vec<f64>,
     pub swap: Vec<u64>,
     pub num_threads: usize,
+    // CPUs the solvers pin their worker threads to, if any.
+    pub affinity: Option<Affinity>,
+    // Cancelling it stops the solver running on this Data with Cancelled;
+    // each checks it at least once per phase.
+    pub cancel: CancelToken,
+    // Set by sparse::init_sparse instead of filling in `matrix`.
+    // compute_gauss, compute_gauss_with and back_substitute solve it; the
+    // dense solvers return GaussError::Sparse.
+    pub sparse: Option<Sparse>,
+}
+
+// The dense solvers start with this, since `matrix` is left empty when the
+// system is in data.sparse.
+pub fn check_dense(data: &Data) -> Result<(), GaussError> {
+    if data.sparse.is_some() {
+        return Err(GaussError::Sparse);
+    }
+    Ok(())
 }
 
 
@@ -41,7 +82,7 @@ pub fn initp(mut data: Data) -> Arc<Mutex<Data>>{
     }
 
     let num_threads = data.num_threads;
-    let pool = ThreadPool::new(num_threads);
+    let pool = pool_for(&data).build();
     let sdata = Arc::new(Mutex::new(data));
 
     for i in 0..num_threads{
@@ -50,10 +91,10 @@ pub fn initp(mut data: Data) -> Arc<Mutex<Data>>{
             //eprintln!("hello from thread {}", i);
             let matrix = &mut sdata.lock().unwrap().matrix;
             for i in (i..matrix.len()).step_by(num_threads) {
-                for j in 0..matrix.len() {
+                for (j, x) in matrix[i].iter_mut().enumerate() {
                     let ii: f64 = i as f64;
                     let jj: f64 = j as f64;
-                    matrix[i][j] = if jj < ii {
+                    *x = if jj < ii {
                         2.0 * (jj + 1.0)
                     } else {
                         2.0 * (ii + 1.0)
@@ -62,7 +103,7 @@ pub fn initp(mut data: Data) -> Arc<Mutex<Data>>{
             }
         });
     }
-    pool.join();
+    pool.join().unwrap();
     sdata
 }
 
@@ -92,20 +133,65 @@ pub fn init(data: &mut Data) {
 }
 
 pub fn compute_gauss(data: &mut Data) {
+    if data.sparse.is_some() {
+        // Singular matrices panic, as in pivot.
+        sparse::solve_sparse(data).unwrap();
+        return;
+    }
+    eliminate(data, None);
+}
+
+// compute_gauss that also returns the pivot growth factor: the largest
+// entry the elimination produced over the largest entry it started from. A
+// large factor means the pivoting let rounding errors grow with it.
+pub fn compute_gauss_growth(data: &mut Data) -> Result<f64, GaussError> {
+    check_dense(data)?;
+    Ok(eliminate(data, None))
+}
+
+// compute_gauss_growth that also keeps the factors of the elimination, so
+// that condition::condition does not have to factor the matrix again.
+// data.swap has to start out as the identity.
+pub fn compute_gauss_lu(data: &mut Data) -> Result<(f64, Lu), GaussError> {
+    check_dense(data)?;
+    let mut l = vec![Vec::new(); data.nsize];
+    let growth = eliminate(data, Some(&mut l));
+    Ok((growth, Lu::new(l, data.matrix.clone(), data.swap.clone())))
+}
+
+// The elimination behind compute_gauss. Row j of `l`, if given, collects
+// what row j was scaled by when it became the pivot row and the multiples
+// of the earlier pivot rows subtracted from it: L in P A = L U.
+fn eliminate(data: &mut Data, mut l: Option<&mut Vec<Vec<f64>>>) -> f64 {
+    let start = data.matrix.iter().flatten().fold(0.0, |m: f64, x| m.max(x.abs()));
+    let mut big = start;
     for i in 0..data.nsize {
-        pivot(data, i);
+        let (irow, scale) = pivot(data, i);
+        if let Some(l) = l.as_mut() {
+            l.swap(irow, i);
+            l[i].push(scale);
+        }
 
         let mut pivot_val;
 
         for j in i + 1..data.nsize {
             pivot_val = data.matrix[j][i];
+            if let Some(l) = l.as_mut() {
+                l[j].push(pivot_val);
+            }
             data.matrix[j][i] = 0.0;
             for k in i + 1..data.nsize {
                 data.matrix[j][k] -= pivot_val * data.matrix[i][k];
+                big = big.max(data.matrix[j][k].abs());
             }
             data.b[j] -= pivot_val * data.b[i];
         }
     }
+    if start == 0.0 {
+        1.0
+    } else {
+        big / start
+    }
 }
 
 pub struct Message {
@@ -146,7 +232,9 @@ pub struct Message {
 //     }
 // }
 
-fn pivot(data: &mut Data, currow: usize) {
+// Returns the row swapped into currow and what the pivot row was divided
+// by.
+fn pivot(data: &mut Data, currow: usize) -> (usize, f64) {
     let (mut irow, mut big, mut tmp);
 
     big = data.matrix[currow][currow];
@@ -185,8 +273,10 @@ fn pivot(data: &mut Data, currow: usize) {
                 data.matrix[currow][i] /= pivot_val;
             }
             data.b[currow] /= pivot_val;
+            return (irow, pivot_val);
         }
     }
+    (irow, 1.0)
 }
 
 pub fn solve_gauss(data: &mut Data) {
@@ -200,6 +290,21 @@ pub fn solve_gauss(data: &mut Data) {
     data.c[..data.nsize].clone_from_slice(&data.v[..data.nsize]);
 }
 
+// Back substitution on the U compute_gauss leaves, into data.c. Unlike
+// solve_gauss it takes in every column.
+pub fn back_substitute(data: &mut Data) {
+    if data.sparse.is_some() {
+        sparse::solve_gauss_sparse(data);
+        return;
+    }
+    let n = data.nsize;
+    data.c.resize(n, 0.0);
+    for i in (0..n).rev() {
+        let s: f64 = (i + 1..n).map(|j| data.matrix[i][j] * data.c[j]).sum();
+        data.c[i] = (data.b[i] - s) / data.matrix[i][i];
+    }
+}
+
 pub fn print(data: &Data) {
     println!("{{");
     for row in data.matrix.iter().take(data.matrix.len() - 1) {
@@ -259,9 +364,17 @@ pub fn verify(data: &Data) {
     println!("Verified");
 }
 
+// The upstream tests, kept as written.
 #[cfg(test)]
+#[allow(
+    clippy::redundant_field_names,
+    clippy::assertions_on_constants,
+    clippy::explicit_auto_deref,
+    clippy::unnecessary_mut_passed
+)]
 mod tests {
     use super::*;
+    use crate::test_util::new_data;
 
     #[test]
     fn init_matrix_test() {
@@ -279,6 +392,9 @@ mod tests {
             v: v,
             swap: swap,
             num_threads: 1,
+            affinity: None,
+            cancel: CancelToken::new(),
+            sparse: None,
         };
         init(&mut data);
         assert_eq!(
@@ -303,6 +419,9 @@ mod tests {
             v: v,
             swap: swap,
             num_threads: 1,
+            affinity: None,
+            cancel: CancelToken::new(),
+            sparse: None,
         };
         init(&mut data);
         compute_gauss(&mut data);
@@ -328,6 +447,9 @@ mod tests {
             v: v,
             swap: swap,
             num_threads: 1,
+            affinity: None,
+            cancel: CancelToken::new(),
+            sparse: None,
         };
         init(&mut data);
         compute_gauss(&mut data);
@@ -336,6 +458,31 @@ mod tests {
         assert!(true);
     }
 
+    #[test]
+    fn growth_factor_test() {
+        // Wilkinson's matrix: the last column doubles in every phase.
+        let nsize = 10;
+        let matrix: Vec<Vec<f64>> = (0..nsize)
+            .map(|i| {
+                (0..nsize)
+                    .map(|j| if j == nsize - 1 || i == j { 1.0 } else if j < i { -1.0 } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        let mut data = new_data(nsize, 1);
+        data.matrix = matrix.clone();
+        data.b = vec![0.0; nsize];
+        data.swap = (0..nsize as u64).collect();
+        assert_eq!(compute_gauss_growth(&mut data), Ok(512.0));
+
+        let mut plain = new_data(nsize, 1);
+        plain.matrix = matrix;
+        plain.b = vec![0.0; nsize];
+        plain.swap = (0..nsize as u64).collect();
+        compute_gauss(&mut plain);
+        assert_eq!(plain.matrix, data.matrix);
+    }
+
 
     #[test]
     fn initp_matrix_test() {
@@ -353,6 +500,9 @@ mod tests {
             v: v,
             swap: swap,
             num_threads: 1,
+            affinity: None,
+            cancel: CancelToken::new(),
+            sparse: None,
         };
         let data = initp(data);
         let guard = Arc::try_unwrap(data).unwrap();
@@ -1,8 +1,10 @@
-pub mod lib;
+use gauss as lib;
 extern crate clap;
 use clap::{App, Arg};
 use std::sync::{Arc};// Mutex};
 use std::time::Instant;
+use threadpool_crossbeam::cancel::CancelToken;
+use threadpool_crossbeam::{stats, trace};
 
 fn main() {
     let matches = App::new("Gaussian internal")
@@ -13,7 +15,7 @@ fn main() {
                 .short("s")
                 .long("size")
                 .help("Sets matrix dimensions")
-                .required(true)
+                .required_unless("WORKER")
                 .takes_value(true),
         )
         .arg(
@@ -33,8 +35,129 @@ fn main() {
                 .default_value("0")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("STRATEGY")
+                .short("t")
+                .long("strategy")
+                .help("Sets how the threads are synchronized between phases")
+                .takes_value(true)
+                .possible_values(&["pool", "barrier", "pipelined", "dag", "actor", "channel"])
+                .default_value("pool")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("CHANNEL")
+                .long("channel")
+                .help("Sets the channel the channel strategy hands out rows on")
+                .takes_value(true)
+                .possible_values(&["flix", "std", "crossbeam", "ringbuf", "lockfree"])
+                .default_value("crossbeam")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("CAPACITY")
+                .long("capacity")
+                .help("Sets how many messages the row channel holds: rendezvous, unbounded or a number (not with --channel flix)")
+                .takes_value(true)
+                .default_value("unbounded")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("BATCH")
+                .long("batch")
+                .help("Sets how many rows go in one message on the row channel")
+                .takes_value(true)
+                .default_value("1")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("AFFINITY")
+                .long("affinity")
+                .help("Pins the worker threads: compact, scatter or a list of CPUs such as 0,2,4")
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("STATS")
+                .long("stats")
+                .help("Prints channel, queue and worker timings at the end (needs --features instrument)")
+                .takes_value(true)
+                .possible_values(&["table", "json"])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("TRACE")
+                .long("trace")
+                .help("Writes a Chrome trace of what every thread was doing to this file")
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("WORKERS")
+                .short("w")
+                .long("workers")
+                .help("Solves with this many worker processes on localhost")
+                .takes_value(true)
+                .default_value("0")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("TRANSPORT")
+                .long("transport")
+                .help("Sets how worker processes talk to the coordinator")
+                .takes_value(true)
+                .possible_values(&["tcp", "unix"])
+                .default_value("tcp")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("SPARSE")
+                .long("sparse")
+                .help("Solves a sparse grid system with SIZE unknowns instead of the dense one")
+                .takes_value(false)
+                .conflicts_with_all(&["SYMMETRIC", "BANDED"])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("BANDED")
+                .long("banded")
+                .help("Eliminates only inside the band detected in the matrix")
+                .takes_value(false)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("SYMMETRIC")
+                .long("symmetric")
+                .help("Solves with Cholesky or LDL^T if the matrix is symmetric, LU otherwise")
+                .takes_value(false)
+                .conflicts_with("BANDED")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ORDERING")
+                .long("ordering")
+                .help("Sets the order the sparse system's unknowns are eliminated in")
+                .takes_value(true)
+                .possible_values(&["natural", "rcm", "amd"])
+                .default_value("natural")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("WORKER")
+                .long("worker")
+                .help("Runs as a worker process for the coordinator at this address")
+                .takes_value(true)
+                .hidden(true)
+                .required(false),
+        )
         .get_matches();
 
+    if let Some(addr) = matches.value_of("WORKER") {
+        lib::distributed::worker(addr).expect("worker failed");
+        return;
+    }
+
+
     let size: usize = matches.value_of("SIZE").unwrap().parse::<usize>().unwrap();
     let mut verbose = false;
     let num_of_threads = matches
@@ -42,6 +165,45 @@ fn main() {
         .unwrap()
         .parse::<usize>()
         .unwrap();
+    let strategy: lib::parallel::Strategy = matches
+        .value_of("STRATEGY")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let channel: lib::channel::ChannelKind = matches
+        .value_of("CHANNEL")
+        .unwrap()
+        .parse()
+        .unwrap();
+    // The flix channel is sized to each phase, so a capacity would be ignored.
+    if channel == lib::channel::ChannelKind::Flix && matches.occurrences_of("CAPACITY") > 0 {
+        clap::Error::with_description(
+            "--capacity cannot be used with --channel flix",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    let dispatch = lib::channel::Dispatch {
+        capacity: matches.value_of("CAPACITY").unwrap().parse().unwrap(),
+        batch: matches
+            .value_of("BATCH")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap(),
+    };
+    let affinity = matches
+        .value_of("AFFINITY")
+        .map(|affinity| affinity.parse().unwrap());
+    let num_of_workers = matches
+        .value_of("WORKERS")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let transport: lib::distributed::Transport = matches
+        .value_of("TRANSPORT")
+        .unwrap()
+        .parse()
+        .unwrap();
 
     //println!("size: {}, verbose: {}, num threads {}", size, verbose, num_threads);
 
@@ -49,13 +211,27 @@ fn main() {
         verbose = true;
     }
 
+    let ordering: lib::ordering::Reorder = matches
+        .value_of("ORDERING")
+        .unwrap()
+        .parse()
+        .unwrap();
+    // The worker processes only take dense rows.
+    if matches.is_present("SPARSE") && num_of_workers > 0 {
+        clap::Error::with_description(
+            "--sparse cannot be used with --workers",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+
     let matrix: Vec<Vec<f64>> = Vec::with_capacity(size);
     let b: Vec<f64> = Vec::with_capacity(size);
     let c: Vec<f64> = Vec::with_capacity(size);
     let v: Vec<f64> = Vec::with_capacity(size);
     let swap: Vec<u64> = Vec::with_capacity(size);
 
-    let data = lib::Data {
+    let mut data = lib::Data {
         nsize: size,
         matrix,
         b,
@@ -63,6 +239,9 @@ fn main() {
         v,
         swap,
         num_threads: num_of_threads,
+        affinity,
+        cancel: CancelToken::new(),
+        sparse: None,
     };
 
     // lib::init(&mut data);
@@ -78,15 +257,130 @@ fn main() {
     // if verbose {
     //     lib::print(&data);
     // }
+    let mut workers = if num_of_workers > 0 {
+        let exe = std::env::current_exe().unwrap();
+        Some(lib::distributed::spawn_workers(&exe, num_of_workers, transport).unwrap())
+    } else {
+        None
+    };
+
+    if matches.is_present("TRACE") {
+        trace::enable();
+    }
+    // The sparse system is set up outside the timing, as the ordering is
+    // part of the solve.
+    if matches.is_present("SPARSE") {
+        lib::sparse::init_sparse(&mut data, ordering);
+        if verbose {
+            let a = &data.sparse.as_ref().unwrap().matrix;
+            for r in lib::ordering::fill_report(a) {
+                println!("{:?}: ordered in {} sec, nnz {}, fill {}", r.order, r.seconds, r.nnz, r.fill);
+            }
+        }
+    }
     let now = Instant::now();
-    let data_arc = lib::initp(data);
+    let mut data = if data.sparse.is_some() {
+        data
+    } else if num_of_threads > 0 {
+        let data_arc = lib::initp(data);
+        Arc::try_unwrap(data_arc).unwrap().into_inner().unwrap()
+    } else {
+        lib::init(&mut data);
+        data
+    };
+    if num_of_threads > 0 || num_of_workers > 0 {
+        // The first Ctrl-C lets the solver stop at the next phase, a second
+        // one gives up on it.
+        let token = data.cancel.clone();
+        ctrlc::set_handler(move || {
+            if token.cancel() {
+                std::process::exit(130);
+            }
+        })
+        .unwrap();
+    }
+    let mut growth = None;
+    let mut factors = None;
+    let result = if let Some(workers) = workers.as_mut() {
+        let block = lib::actor::DEFAULT_BLOCK;
+        lib::distributed::compute_gauss_distributed(&mut data, &mut workers.conns, block)
+            .map_err(|e| e.to_string())
+    } else if matches.is_present("SYMMETRIC") {
+        lib::symmetric::solve_symmetric(&mut data)
+            .map(|method| {
+                if verbose {
+                    println!("Solved with {:?}", method);
+                }
+            })
+            .map_err(|e| e.to_string())
+    } else if matches.is_present("BANDED") {
+        lib::banded::compute_gauss_banded(&mut data).map_err(|e| e.to_string())
+    } else if num_of_threads > 0
+        && strategy == lib::parallel::Strategy::Channel
+        && data.sparse.is_none()
+    {
+        lib::channel::compute_gauss_channel_with(&mut data, channel, dispatch)
+            .map_err(|e| e.to_string())
+    } else if num_of_threads > 0 {
+        lib::parallel::compute_gauss_with(&mut data, strategy).map_err(|e| e.to_string())
+    } else if verbose && data.sparse.is_none() {
+        lib::compute_gauss_lu(&mut data)
+            .map(|(g, lu)| {
+                growth = Some(g);
+                factors = Some(lu);
+            })
+            .map_err(|e| e.to_string())
+    } else {
+        lib::compute_gauss(&mut data);
+        Ok(())
+    };
     let time = now.elapsed().as_nanos();
     println!("Program finished in {} sec", (time as f64)/10e8);
-    if verbose {
-        let guard = Arc::try_unwrap(data_arc).unwrap();
-        let inner = guard.lock().unwrap(); 
-        let t  = &*inner;
-        lib::print(t);
+    if let Some(workers) = workers {
+        workers.wait().unwrap();
+    }
+    if let Err(e) = result {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+    if verbose && data.sparse.is_some() {
+        println!("fill {}", data.sparse.as_ref().unwrap().fill);
+        lib::back_substitute(&mut data);
+        lib::sparse::verify_sparse(&data);
+    } else if verbose {
+        lib::print(&data);
+        // Checked against the system init generated, before elimination.
+        let mut original = lib::Data {
+            nsize: size,
+            matrix: Vec::with_capacity(size),
+            b: Vec::with_capacity(size),
+            c: Vec::with_capacity(size),
+            v: Vec::with_capacity(size),
+            swap: Vec::with_capacity(size),
+            num_threads: 0,
+            affinity: None,
+            cancel: CancelToken::new(),
+            sparse: None,
+        };
+        lib::init(&mut original);
+        if !matches.is_present("SYMMETRIC") {
+            lib::back_substitute(&mut data);
+        }
+        // Only the sequential solve keeps L; the bounds for the others
+        // factor the original again.
+        match factors.map_or_else(|| lib::inverse::lu(&original), Ok) {
+            Ok(lu) => println!("{}", lib::condition::error_bounds(&original, &data.c, &lu)),
+            Err(e) => println!("{}", e),
+        }
+        if let Some(growth) = growth {
+            println!("Pivot growth {}", growth);
+        }
+    }
+    if let Some(format) = matches.value_of("STATS") {
+        print!("{}", stats::report(format.parse().unwrap()));
+    }
+    if let Some(path) = matches.value_of("TRACE") {
+        trace::write(path).unwrap();
     }
     // let guard = Arc::try_unwrap(data_arc).unwrap();
     // let inner = guard.lock().unwrap(); 
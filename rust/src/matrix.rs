@@ -0,0 +1,75 @@
+use crate::Data;
+
+// A dense nrows x ncols matrix stored by rows, for systems that are not
+// square. Data stays the square system the solvers share.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub rows: Vec<Vec<f64>>,
+}
+
+impl Matrix {
+    pub fn new(nrows: usize, ncols: usize) -> Matrix {
+        Matrix {
+            nrows,
+            ncols,
+            rows: vec![vec![0.0; ncols]; nrows],
+        }
+    }
+
+    // Panics unless every row has the same length.
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Matrix {
+        let ncols = rows.first().map_or(0, Vec::len);
+        assert!(rows.iter().all(|row| row.len() == ncols), "rows of different lengths");
+        Matrix {
+            nrows: rows.len(),
+            ncols,
+            rows,
+        }
+    }
+
+    pub fn from_data(data: &Data) -> Matrix {
+        Matrix::from_rows(data.matrix.clone())
+    }
+
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.ncols, "vector does not match the columns");
+        self.rows
+            .iter()
+            .map(|row| row.iter().zip(x).map(|(a, x)| a * x).sum())
+            .collect()
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        Matrix {
+            nrows: self.ncols,
+            ncols: self.nrows,
+            rows: (0..self.ncols)
+                .map(|j| self.rows.iter().map(|row| row[j]).collect())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangular() {
+        let a = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+        assert_eq!((a.nrows, a.ncols), (3, 2));
+        assert_eq!(a.mul_vec(&[1.0, -1.0]), vec![-1.0, -1.0, -1.0]);
+        let t = a.transpose();
+        assert_eq!(t.rows, vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]]);
+        assert_eq!(t.transpose(), a);
+        assert_eq!(Matrix::new(2, 3).rows, vec![vec![0.0; 3]; 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn ragged_rows() {
+        Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0]]);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::sparse::{analyze, Csr, Symbolic};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::Instant;
+
+// Which order the unknowns of a sparse system are eliminated in. The chosen
+// permutation is applied to the rows and columns alike, so the diagonal
+// stays the diagonal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reorder {
+    Natural,
+    Rcm,
+    Amd,
+}
+
+impl Reorder {
+    pub const ALL: [Reorder; 3] = [Reorder::Natural, Reorder::Rcm, Reorder::Amd];
+
+    // perm[new] = old.
+    pub fn permutation(self, a: &Csr) -> Vec<usize> {
+        match self {
+            Reorder::Natural => (0..a.nrows).collect(),
+            Reorder::Rcm => rcm(a),
+            Reorder::Amd => amd(a),
+        }
+    }
+}
+
+impl FromStr for Reorder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Reorder, String> {
+        match s {
+            "natural" => Ok(Reorder::Natural),
+            "rcm" => Ok(Reorder::Rcm),
+            "amd" => Ok(Reorder::Amd),
+            _ => Err(format!("unknown ordering {}", s)),
+        }
+    }
+}
+
+// The pattern of A + A^T without the diagonal: the graph both orderings
+// work on.
+fn symmetric_pattern(a: &Csr) -> Vec<Vec<usize>> {
+    let mut adj = vec![Vec::new(); a.nrows];
+    for i in 0..a.nrows {
+        for &j in a.row(i).0 {
+            if i != j {
+                adj[i].push(j);
+                adj[j].push(i);
+            }
+        }
+    }
+    for list in adj.iter_mut() {
+        list.sort_unstable();
+        list.dedup();
+    }
+    adj
+}
+
+// The vertices of root's component by their distance from root. `mark` and
+// `stamp` tell visited vertices apart without clearing between calls.
+fn level_structure(
+    adj: &[Vec<usize>],
+    root: usize,
+    mark: &mut [usize],
+    stamp: &mut usize,
+) -> Vec<Vec<usize>> {
+    *stamp += 1;
+    mark[root] = *stamp;
+    let mut levels = vec![vec![root]];
+    loop {
+        let mut next = Vec::new();
+        for &v in levels.last().unwrap() {
+            for &u in &adj[v] {
+                if mark[u] != *stamp {
+                    mark[u] = *stamp;
+                    next.push(u);
+                }
+            }
+        }
+        if next.is_empty() {
+            return levels;
+        }
+        levels.push(next);
+    }
+}
+
+// George and Liu: move to a vertex of least degree in the last level until
+// the level structure stops getting deeper.
+fn pseudo_peripheral(adj: &[Vec<usize>], start: usize, mark: &mut [usize], stamp: &mut usize) -> usize {
+    let mut root = start;
+    let mut levels = level_structure(adj, root, mark, stamp);
+    loop {
+        let far = *levels
+            .last()
+            .unwrap()
+            .iter()
+            .min_by_key(|&&v| adj[v].len())
+            .unwrap();
+        let far_levels = level_structure(adj, far, mark, stamp);
+        if far_levels.len() <= levels.len() {
+            return root;
+        }
+        root = far;
+        levels = far_levels;
+    }
+}
+
+// Reverse Cuthill-McKee: a breadth-first search from a pseudo-peripheral
+// vertex of every component, neighbours in order of increasing degree,
+// reversed. It keeps the entries close to the diagonal.
+pub fn rcm(a: &Csr) -> Vec<usize> {
+    let adj = symmetric_pattern(a);
+    let n = adj.len();
+    let mut mark = vec![0; n];
+    let mut stamp = 0;
+    let mut visited = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+
+    let mut starts: Vec<usize> = (0..n).collect();
+    starts.sort_by_key(|&v| adj[v].len());
+    for s in starts {
+        if visited[s] {
+            continue;
+        }
+        let root = pseudo_peripheral(&adj, s, &mut mark, &mut stamp);
+        visited[root] = true;
+        let mut head = order.len();
+        order.push(root);
+        while head < order.len() {
+            let v = order[head];
+            head += 1;
+            let mut next: Vec<usize> = adj[v].iter().copied().filter(|&u| !visited[u]).collect();
+            next.sort_by_key(|&u| adj[u].len());
+            for u in next {
+                visited[u] = true;
+                order.push(u);
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+// Approximate minimum degree on the quotient graph: an eliminated vertex
+// becomes an element standing for the clique it created, instead of adding
+// the clique's edges. The degree of a vertex is bounded from above as in
+// Amestoy, Davis and Duff, without their supervariables.
+pub fn amd(a: &Csr) -> Vec<usize> {
+    let mut adj = symmetric_pattern(a);
+    let n = adj.len();
+    // The elements next to each variable, and the variables of each element.
+    let mut elems: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut degree: Vec<usize> = adj.iter().map(Vec::len).collect();
+    let mut queue: BTreeSet<(usize, usize)> = (0..n).map(|i| (degree[i], i)).collect();
+    let mut eliminated = vec![false; n];
+    let mut absorbed = vec![false; n];
+    let mut mark = vec![0; n];
+    let mut stamp = 0;
+    // |Le \ Lp| of the elements next to the pivot's variables.
+    let mut w = vec![0isize; n];
+    let mut w_stamp = vec![0; n];
+    let mut order = Vec::with_capacity(n);
+
+    while let Some((_, p)) = queue.pop_first() {
+        eliminated[p] = true;
+        order.push(p);
+
+        // Lp: the variables p reaches directly or through its elements, which
+        // p's new element absorbs.
+        stamp += 1;
+        mark[p] = stamp;
+        let mut lp = Vec::new();
+        for &v in &adj[p] {
+            if !eliminated[v] && mark[v] != stamp {
+                mark[v] = stamp;
+                lp.push(v);
+            }
+        }
+        for &e in &elems[p] {
+            if absorbed[e] {
+                continue;
+            }
+            for &v in &members[e] {
+                if !eliminated[v] && mark[v] != stamp {
+                    mark[v] = stamp;
+                    lp.push(v);
+                }
+            }
+            absorbed[e] = true;
+            members[e] = Vec::new();
+        }
+        adj[p] = Vec::new();
+        elems[p] = Vec::new();
+
+        for &i in &lp {
+            for &e in &elems[i] {
+                if absorbed[e] {
+                    continue;
+                }
+                if w_stamp[e] != stamp {
+                    w_stamp[e] = stamp;
+                    w[e] = members[e].len() as isize;
+                }
+                w[e] -= 1;
+            }
+        }
+
+        let remaining = n - order.len();
+        for &i in &lp {
+            queue.remove(&(degree[i], i));
+            elems[i].retain(|&e| !absorbed[e]);
+            // What i shares with Lp is covered by p's element now.
+            adj[i].retain(|&v| !eliminated[v] && mark[v] != stamp);
+            let external: usize = elems[i].iter().map(|&e| w[e].max(0) as usize).sum();
+            elems[i].push(p);
+            let bound = adj[i].len() + (lp.len() - 1) + external;
+            degree[i] = bound.min(degree[i] + lp.len() - 1).min(remaining - 1);
+            queue.insert((degree[i], i));
+        }
+        members[p] = lp;
+    }
+    order
+}
+
+// P A P^T for perm[new] = old.
+pub fn permute(a: &Csr, perm: &[usize]) -> Csr {
+    let mut inv = vec![0; perm.len()];
+    for (new, &old) in perm.iter().enumerate() {
+        inv[old] = new;
+    }
+    let mut triplets = Vec::with_capacity(a.nnz());
+    for (new, &old) in perm.iter().enumerate() {
+        let (cols, vals) = a.row(old);
+        triplets.extend(cols.iter().zip(vals).map(|(&j, &x)| (new, inv[j], x)));
+    }
+    Csr::from_triplets(a.nrows, a.ncols, &triplets)
+}
+
+// sparse::analyze of `a` reordered by `order`. compute_gauss_sparse applies
+// the permutation it carries.
+pub fn analyze_with(a: &Csr, order: Reorder) -> Symbolic {
+    let perm = order.permutation(a);
+    let mut sym = analyze(&permute(a, &perm));
+    sym.perm = perm;
+    sym
+}
+
+// What an ordering costs and what it saves: the time to compute it and run
+// the symbolic analysis, and the fill the elimination will create.
+#[derive(Clone, Debug)]
+pub struct FillReport {
+    pub order: Reorder,
+    pub seconds: f64,
+    pub nnz: usize,
+    pub fill: usize,
+}
+
+pub fn fill_report(a: &Csr) -> Vec<FillReport> {
+    Reorder::ALL
+        .iter()
+        .map(|&order| {
+            let start = Instant::now();
+            let sym = analyze_with(a, order);
+            FillReport {
+                order,
+                seconds: start.elapsed().as_secs_f64(),
+                nnz: sym.nnz,
+                fill: sym.fill,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse::{compute_gauss_sparse, grid_width, init_sparse, solve_gauss_sparse, Sparse};
+    use crate::test_util::{new_data, Lcg};
+
+    // The init_sparse grid with its unknowns shuffled, which spreads the
+    // entries all over the matrix.
+    fn scrambled(nsize: usize) -> Csr {
+        let mut data = new_data(nsize, 1);
+        init_sparse(&mut data, Reorder::Natural);
+        let mut perm: Vec<usize> = (0..nsize).collect();
+        let mut rng = Lcg::new(12345);
+        for i in (1..nsize).rev() {
+            perm.swap(i, (rng.next_u64() >> 33) as usize % (i + 1));
+        }
+        permute(&data.sparse.unwrap().matrix, &perm)
+    }
+
+    fn bandwidth(a: &Csr) -> usize {
+        (0..a.nrows)
+            .flat_map(|i| a.row(i).0.iter().map(move |&j| i.max(j) - i.min(j)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn is_permutation(perm: &[usize]) -> bool {
+        let mut sorted = perm.to_vec();
+        sorted.sort_unstable();
+        sorted.iter().enumerate().all(|(i, &k)| i == k)
+    }
+
+    #[test]
+    fn rcm_restores_band() {
+        let a = scrambled(400);
+        let perm = rcm(&a);
+        assert!(is_permutation(&perm));
+        assert!(bandwidth(&a) > 100);
+        assert!(bandwidth(&permute(&a, &perm)) <= 2 * grid_width(400));
+    }
+
+    #[test]
+    fn orderings_reduce_fill() {
+        let a = scrambled(1600);
+        let report = fill_report(&a);
+        assert_eq!(report.len(), 3);
+        let natural = report[0].fill;
+        for r in &report[1..] {
+            assert!(r.fill * 4 < natural, "{:?} against {}", r, natural);
+        }
+        assert!(is_permutation(&amd(&a)));
+    }
+
+    #[test]
+    fn amd_eliminates_arrow_leaves_first() {
+        // Row and column 0 are full: eliminating 0 first fills everything.
+        let n = 50;
+        let mut triplets = vec![];
+        for i in 0..n {
+            triplets.push((i, i, 4.0));
+            if i > 0 {
+                triplets.push((0, i, 1.0));
+                triplets.push((i, 0, 1.0));
+            }
+        }
+        let a = Csr::from_triplets(n, n, &triplets);
+        assert_eq!(analyze_with(&a, Reorder::Natural).fill, (n - 1) * (n - 2));
+        assert_eq!(analyze_with(&a, Reorder::Amd).fill, 0);
+        assert_eq!(analyze_with(&a, Reorder::Rcm).fill, 0);
+    }
+
+    #[test]
+    fn ordered_solve() {
+        let nsize = 300;
+        let a = scrambled(nsize);
+        let x: Vec<f64> = (0..nsize).map(|i| i as f64).collect();
+        for &order in &Reorder::ALL {
+            let mut data = new_data(nsize, 1);
+            data.sparse = Some(Sparse::new(a.clone(), order));
+            data.b = a.mul_vec(&x);
+            data.c = vec![0.0; nsize];
+            data.swap = (0..nsize as u64).collect();
+            let sym = analyze_with(&a, order);
+            compute_gauss_sparse(&mut data, &sym).unwrap();
+            assert_eq!(data.sparse.as_ref().unwrap().fill, sym.fill);
+            solve_gauss_sparse(&mut data);
+            for (c, x) in data.c.iter().zip(&x) {
+                assert!((c - x).abs() < 1e-9, "{:?}: {} != {}", order, c, x);
+            }
+        }
+    }
+}
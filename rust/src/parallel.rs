@@ -0,0 +1,752 @@
+use crate::actor::{compute_gauss_actor, DEFAULT_BLOCK};
+use crate::channel::{compute_gauss_channel_with, ChannelKind, Dispatch};
+use crate::dag::{compute_gauss_dag, DEFAULT_TILE};
+use crate::error::GaussError;
+use crate::pipeline::compute_gauss_pipelined;
+use crate::sparse::solve_sparse;
+use crate::{check_dense, Data};
+use std::str::FromStr;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::Duration;
+use threadpool_crossbeam::cancel::CancelToken;
+use threadpool_crossbeam::stats::{self, Stamp};
+use threadpool_crossbeam::trace;
+use threadpool_crossbeam::{Builder, ThreadPool};
+
+// Below this many candidate rows per worker the pivot column is scanned on
+// the calling thread; dispatching jobs costs more than the scan.
+const PAR_PIVOT_MIN: usize = 64;
+// How often a worker blocked on the others checks whether its pool has been
+// cancelled, by the caller or by a panic in one of them.
+pub const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+// A builder for a pool of num_threads workers. A panicking job drops the
+// rest of the queue and comes back from join.
+pub fn pool_builder(num_threads: usize) -> Builder {
+    Builder::new().num_threads(num_threads).fail_fast(true)
+}
+
+pub fn new_pool(num_threads: usize) -> ThreadPool {
+    pool_builder(num_threads).build()
+}
+
+// pool_builder for a solve of data: data.num_threads workers, pinned as
+// data.affinity says. The pool's cancel token is data.cancel.
+pub fn pool_for(data: &Data) -> Builder {
+    let builder = pool_builder(data.num_threads.max(1)).cancel_token(data.cancel.clone());
+    match data.affinity.clone() {
+        Some(affinity) => builder.core_affinity(affinity),
+        None => builder,
+    }
+}
+
+// How a solve on a pool sharing `cancel` ends. Cancelling drops the jobs
+// still queued, so whatever the solver ran into after that is reported as
+// Cancelled. A panic cancels the token too, and is kept.
+pub fn finish(cancel: &CancelToken, result: Result<(), GaussError>) -> Result<(), GaussError> {
+    match result {
+        Err(GaussError::Panicked(msg)) => Err(GaussError::Panicked(msg)),
+        _ if cancel.is_cancelled() => Err(GaussError::Cancelled),
+        result => result,
+    }
+}
+
+// The matrix as seen by the pool jobs. Every row is locked on its own so
+// workers only contend when they touch the same row, which the cyclic row
+// assignment rules out during an update. Row i holds matrix[i] followed by
+// b[i], so the right hand side is eliminated and swapped with its row.
+// Solvers on other entries than f64 lend their rows through on_pool.
+#[derive(Debug)]
+pub struct SharedMatrix<T = f64> {
+    pub nsize: usize,
+    pub rows: Vec<RwLock<Vec<T>>>,
+}
+
+impl SharedMatrix {
+    pub fn from_data(data: &mut Data) -> SharedMatrix {
+        let rows = data
+            .matrix
+            .drain(..)
+            .zip(data.b.iter())
+            .map(|(mut row, &b)| {
+                row.push(b);
+                row
+            })
+            .collect();
+        SharedMatrix::from_rows(rows)
+    }
+
+    pub fn into_data(self, data: &mut Data) {
+        for (i, mut row) in self.into_rows().into_iter().enumerate() {
+            data.b[i] = row.pop().unwrap();
+            data.matrix.push(row);
+        }
+    }
+}
+
+impl<T> SharedMatrix<T> {
+    pub fn from_rows(rows: Vec<Vec<T>>) -> SharedMatrix<T> {
+        SharedMatrix {
+            nsize: rows.len(),
+            rows: rows.into_iter().map(RwLock::new).collect(),
+        }
+    }
+
+    pub fn into_rows(self) -> Vec<Vec<T>> {
+        // A job that panicked holding a row has poisoned it; the row is
+        // returned as it was left.
+        self.rows
+            .into_iter()
+            .map(|row| row.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .collect()
+    }
+}
+
+// Lends `rows` to the pool for one phase: job(m, worker) runs once for each
+// of the pool's workers, and the rows come back when they are done. This
+// keeps the solvers that only split some phases over a pool on plain rows,
+// without a lock, the rest of the time.
+pub fn on_pool<T, F>(pool: &ThreadPool, rows: &mut Vec<Vec<T>>, job: F) -> Result<(), GaussError>
+where
+    T: Send + Sync + 'static,
+    F: Fn(&SharedMatrix<T>, usize) + Send + Sync + 'static,
+{
+    if pool.cancel_token().is_cancelled() {
+        return Err(GaussError::Cancelled);
+    }
+    let m = Arc::new(SharedMatrix::from_rows(std::mem::take(rows)));
+    let job = Arc::new(job);
+    for worker in 0..pool.max_count() {
+        let (m, job) = (Arc::clone(&m), Arc::clone(&job));
+        pool.execute(move || job(&m, worker));
+    }
+    let joined = pool.join();
+    *rows = Arc::try_unwrap(m).ok().unwrap().into_rows();
+    joined?;
+    Ok(())
+}
+
+// Runs f(i, row) on every row from `from` on: on this thread without a
+// pool, or dealt out cyclically to the pool's workers through on_pool.
+pub fn for_rows<T, F>(
+    pool: Option<&ThreadPool>,
+    rows: &mut Vec<Vec<T>>,
+    from: usize,
+    f: F,
+) -> Result<(), GaussError>
+where
+    T: Send + Sync + 'static,
+    F: Fn(usize, &mut Vec<T>) + Send + Sync + 'static,
+{
+    let pool = match pool {
+        Some(pool) => pool,
+        None => {
+            for (i, row) in rows.iter_mut().enumerate().skip(from) {
+                f(i, row);
+            }
+            return Ok(());
+        }
+    };
+    let num_threads = pool.max_count();
+    on_pool(pool, rows, move |m, worker| {
+        for i in (from + worker..m.nsize).step_by(num_threads) {
+            f(i, &mut m.rows[i].write().unwrap());
+        }
+    })
+}
+
+// A worker's best pivot for the current column: the value of largest
+// magnitude and the row it was found in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate {
+    pub big: f64,
+    pub irow: usize,
+}
+
+impl Candidate {
+    pub fn none(currow: usize) -> Candidate {
+        Candidate {
+            big: 0.0,
+            irow: currow,
+        }
+    }
+}
+
+// Keeps the larger magnitude. Ties go to the lower row so the result does not
+// depend on the order the workers report in, and matches a serial scan.
+pub fn combine(a: Candidate, b: Candidate) -> Candidate {
+    if b.big.abs() > a.big.abs() || (b.big.abs() == a.big.abs() && b.irow < a.irow) {
+        b
+    } else {
+        a
+    }
+}
+
+// Reduces the rows owned by `worker` (currow + worker, stepping by
+// num_threads) to a single candidate.
+pub fn local_pivot(
+    m: &SharedMatrix,
+    currow: usize,
+    worker: usize,
+    num_threads: usize,
+) -> Candidate {
+    let mut best = Candidate::none(currow);
+    for i in (currow + worker..m.nsize).step_by(num_threads) {
+        let tmp = m.rows[i].read().unwrap()[currow];
+        best = combine(
+            best,
+            Candidate {
+                big: tmp,
+                irow: i,
+            },
+        );
+    }
+    best
+}
+
+pub fn find_pivot(
+    pool: &ThreadPool,
+    m: &Arc<SharedMatrix>,
+    currow: usize,
+    num_threads: usize,
+) -> Candidate {
+    if (m.nsize - currow) / num_threads < PAR_PIVOT_MIN {
+        return local_pivot(m, currow, 0, 1);
+    }
+
+    let (tx, rx) = channel();
+    for n in 0..num_threads {
+        let m = Arc::clone(m);
+        let tx = tx.clone();
+        pool.execute(move || {
+            tx.send(local_pivot(&m, currow, n, num_threads))
+                .expect("pivot search result channel closed");
+        });
+    }
+    drop(tx);
+    rx.iter().fold(Candidate::none(currow), combine)
+}
+
+// Swaps rows a and b from column `from` onwards, right hand side included.
+// This stays on the calling thread: handing column chunks to the pool took
+// about 25us, longer than swapping a 2^15-column row (13us), and a dense
+// matrix with rows that long already takes 8 GB.
+pub fn swap_rows(m: &SharedMatrix, a: usize, b: usize, from: usize) {
+    if a == b {
+        return;
+    }
+    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+    let mut lo = m.rows[lo].write().unwrap();
+    let mut hi = m.rows[hi].write().unwrap();
+    lo[from..].swap_with_slice(&mut hi[from..]);
+}
+
+// Moves the chosen pivot into currow and scales it to 1.0, as lib::pivot does
+// once it has found its row.
+pub fn set_pivot(m: &SharedMatrix, swap: &mut [u64], best: Candidate, currow: usize) {
+    if best.irow != currow {
+        swap_rows(m, best.irow, currow, currow);
+        swap.swap(best.irow, currow);
+    }
+
+    let mut row = m.rows[currow].write().unwrap();
+    let pivot_val = row[currow];
+    if (pivot_val - 1.0).abs() > 0.0000001 {
+        row[currow] = 1.0;
+        for x in row[currow + 1..].iter_mut() {
+            *x /= pivot_val;
+        }
+    }
+}
+
+// Same contract as lib::pivot, but selects the entry of largest magnitude in
+// the pivot column and searches for it across the workers.
+fn pivot_p(
+    pool: &ThreadPool,
+    m: &Arc<SharedMatrix>,
+    swap: &mut [u64],
+    currow: usize,
+    num_threads: usize,
+) -> Result<(), GaussError> {
+    let _span = trace::span("pivot", "gauss").phase(currow);
+    let best = find_pivot(pool, m, currow, num_threads);
+
+    if best.big == 0.0 {
+        return Err(GaussError::Singular(currow));
+    }
+
+    set_pivot(m, swap, best, currow);
+    Ok(())
+}
+
+// Eliminates column i from one row using the (normalized) pivot row i.
+pub fn update_row(pivot: &[f64], row: &mut [f64], i: usize) {
+    let pivot_val = row[i];
+    row[i] = 0.0;
+    for (x, p) in row[i + 1..].iter_mut().zip(&pivot[i + 1..]) {
+        *x -= pivot_val * p;
+    }
+}
+
+// The first row at or after `from` that worker w owns when rows are dealt
+// out cyclically by index.
+pub fn first_owned(from: usize, w: usize, num_threads: usize) -> usize {
+    from + (w + num_threads - from % num_threads) % num_threads
+}
+
+fn do_calc(m: &SharedMatrix, i: usize, n: usize, num_threads: usize) {
+    let start = Stamp::now();
+    let _span = trace::span("update", "gauss").phase(i);
+    let pivot = m.rows[i].read().unwrap();
+    for j in (i + 1 + n..m.nsize).step_by(num_threads) {
+        update_row(&pivot, &mut m.rows[j].write().unwrap(), i);
+    }
+    stats::busy(n, i, start.elapsed());
+}
+
+// Parallel version of compute_gauss. Each phase hands one job per thread to
+// the pool; job n updates rows i + 1 + n, i + 1 + n + num_threads, ...
+pub fn compute_gauss_p(data: &mut Data) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let num_threads = data.num_threads.max(1);
+    let nsize = data.nsize;
+    let pool = pool_for(data).build();
+    let m = Arc::new(SharedMatrix::from_data(data));
+
+    let cancel = pool.cancel_token();
+
+    let result = (0..nsize).try_for_each(|i| {
+        if cancel.is_cancelled() {
+            return Err(GaussError::Cancelled);
+        }
+        let start = Stamp::now();
+        pivot_p(&pool, &m, &mut data.swap, i, num_threads)?;
+        for n in 0..num_threads {
+            let m = Arc::clone(&m);
+            pool.execute(move || do_calc(&m, i, n, num_threads));
+        }
+        pool.join()?;
+        stats::phase(i, start.elapsed());
+        Ok(())
+    });
+
+    Arc::try_unwrap(m).unwrap().into_data(data);
+    finish(&cancel, result)
+}
+
+// std's Barrier, except that waiting gives up once `cancel` is cancelled, so
+// the workers are not left waiting for one that panicked or never started.
+pub struct CancelBarrier {
+    num_threads: usize,
+    // Workers arrived in this round, and the round.
+    state: Mutex<(usize, usize)>,
+    cv: Condvar,
+    cancel: CancelToken,
+}
+
+impl CancelBarrier {
+    pub fn new(num_threads: usize, cancel: CancelToken) -> CancelBarrier {
+        CancelBarrier {
+            num_threads,
+            state: Mutex::new((0, 0)),
+            cv: Condvar::new(),
+            cancel,
+        }
+    }
+
+    // Some(true) for the last worker to arrive, the leader, and Some(false)
+    // for the rest; None if the token was cancelled first.
+    pub fn wait(&self) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        let round = state.1;
+        state.0 += 1;
+        if state.0 == self.num_threads {
+            *state = (0, round + 1);
+            self.cv.notify_all();
+            return Some(true);
+        }
+        while state.1 == round {
+            if self.cancel.is_cancelled() {
+                return None;
+            }
+            state = self.cv.wait_timeout(state, CANCEL_POLL).unwrap().0;
+        }
+        Some(false)
+    }
+}
+
+struct BarrierShared {
+    m: SharedMatrix,
+    barrier: CancelBarrier,
+    candidates: Mutex<Vec<Candidate>>,
+    swap: Mutex<Vec<u64>>,
+    // Set by the leader to stop every worker after the phase's pivot step.
+    error: Mutex<Option<GaussError>>,
+    cancel: CancelToken,
+}
+
+// One long-lived worker of compute_gauss_barrier. Worker w owns the rows with
+// index % num_threads == w for the whole run, so the candidate it reports for
+// column i is taken from rows it has just finished updating itself. Worker 0
+// times the phases; the barriers keep everybody's phases within a wait of its.
+fn barrier_worker(s: &BarrierShared, w: usize, num_threads: usize) {
+    for i in 0..s.m.nsize {
+        let phase = Stamp::now();
+        let first = first_owned(i, w, num_threads);
+        s.candidates.lock().unwrap()[w] = local_pivot(&s.m, i, first - i, num_threads);
+        let mut busy = phase.elapsed();
+
+        let wait = trace::span("barrier", "gauss").phase(i);
+        let leader = match s.barrier.wait() {
+            Some(leader) => leader,
+            None => return,
+        };
+        drop(wait);
+        if leader {
+            let start = Stamp::now();
+            let _span = trace::span("pivot", "gauss").phase(i);
+            let best = s
+                .candidates
+                .lock()
+                .unwrap()
+                .iter()
+                .fold(Candidate::none(i), |a, &b| combine(a, b));
+            if s.cancel.is_cancelled() {
+                *s.error.lock().unwrap() = Some(GaussError::Cancelled);
+            } else if best.big == 0.0 {
+                *s.error.lock().unwrap() = Some(GaussError::Singular(i));
+            } else {
+                set_pivot(&s.m, &mut s.swap.lock().unwrap(), best, i);
+            }
+            busy += start.elapsed();
+        }
+        let wait = trace::span("barrier", "gauss").phase(i);
+        let met = s.barrier.wait();
+        drop(wait);
+        if met.is_none() || s.error.lock().unwrap().is_some() {
+            return;
+        }
+
+        let span = trace::span("update", "gauss").phase(i);
+        let start = Stamp::now();
+        let pivot = s.m.rows[i].read().unwrap();
+        for j in (first_owned(i + 1, w, num_threads)..s.m.nsize).step_by(num_threads) {
+            update_row(&pivot, &mut s.m.rows[j].write().unwrap(), i);
+        }
+        drop(pivot);
+        drop(span);
+        stats::busy(w, i, busy + start.elapsed());
+        if w == 0 {
+            stats::phase(i, phase.elapsed());
+        }
+    }
+}
+
+// Bulk-synchronous version of compute_gauss_p: the workers are started once
+// and meet at a barrier twice per phase, around the pivot step, instead of
+// being handed fresh jobs every phase.
+pub fn compute_gauss_barrier(data: &mut Data) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let num_threads = data.num_threads.max(1);
+    let pool = pool_for(data).build();
+    let shared = Arc::new(BarrierShared {
+        m: SharedMatrix::from_data(data),
+        barrier: CancelBarrier::new(num_threads, pool.cancel_token()),
+        candidates: Mutex::new(vec![Candidate::none(0); num_threads]),
+        swap: Mutex::new(std::mem::take(&mut data.swap)),
+        error: Mutex::new(None),
+        cancel: pool.cancel_token(),
+    });
+
+    for w in 0..num_threads {
+        let shared = Arc::clone(&shared);
+        pool.execute(move || barrier_worker(&shared, w, num_threads));
+    }
+    let joined = pool.join();
+
+    let shared = Arc::try_unwrap(shared).ok().unwrap();
+    data.swap = shared.swap.into_inner().unwrap();
+    shared.m.into_data(data);
+    joined?;
+    finish(&pool.cancel_token(), shared.error.into_inner().unwrap().map_or(Ok(()), Err))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    Pool,
+    Barrier,
+    Pipelined,
+    Dag,
+    Actor,
+    Channel,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Strategy, String> {
+        match s {
+            "pool" => Ok(Strategy::Pool),
+            "barrier" => Ok(Strategy::Barrier),
+            "pipelined" => Ok(Strategy::Pipelined),
+            "dag" => Ok(Strategy::Dag),
+            "actor" => Ok(Strategy::Actor),
+            "channel" => Ok(Strategy::Channel),
+            _ => Err(format!("unknown strategy {}", s)),
+        }
+    }
+}
+
+// A sparse matrix goes to the sparse elimination whichever strategy is
+// asked for; the strategies split up the rows of a dense one.
+pub fn compute_gauss_with(data: &mut Data, strategy: Strategy) -> Result<(), GaussError> {
+    if data.sparse.is_some() {
+        return solve_sparse(data);
+    }
+    match strategy {
+        Strategy::Pool => compute_gauss_p(data),
+        Strategy::Barrier => compute_gauss_barrier(data),
+        Strategy::Pipelined => compute_gauss_pipelined(data),
+        Strategy::Dag => compute_gauss_dag(data, DEFAULT_TILE),
+        Strategy::Actor => compute_gauss_actor(data, DEFAULT_BLOCK),
+        Strategy::Channel => {
+            compute_gauss_channel_with(data, ChannelKind::default(), Dispatch::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use threadpool_crossbeam::affinity::{self, Affinity};
+    use crate::{compute_gauss, init};
+
+    #[test]
+    fn combine_test() {
+        let a = Candidate { big: -3.0, irow: 4 };
+        let b = Candidate { big: 2.0, irow: 1 };
+        let c = Candidate { big: 3.0, irow: 2 };
+        assert_eq!(combine(a, b), a);
+        assert_eq!(combine(b, a), a);
+        assert_eq!(combine(a, c), c);
+        assert_eq!(combine(c, a), c);
+    }
+
+    #[test]
+    fn find_pivot_test() {
+        let nsize = 1024;
+        let mut data = new_data(nsize, 4);
+        init(&mut data);
+        data.matrix[700][3] = -5000.0;
+        let pool = ThreadPool::new(4);
+        let m = Arc::new(SharedMatrix::from_data(&mut data));
+        assert_eq!(
+            find_pivot(&pool, &m, 3, 4),
+            Candidate {
+                big: -5000.0,
+                irow: 700
+            }
+        );
+        assert_eq!(find_pivot(&pool, &m, 3, 4), local_pivot(&m, 3, 0, 1));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn affinity_comes_with_the_data() {
+        let allowed_on = |data: &Data| {
+            let pool = pool_for(data).build();
+            let (tx, rx) = channel();
+            pool.execute(move || tx.send(affinity::allowed()).unwrap());
+            rx.recv().unwrap()
+        };
+        let cpu = affinity::allowed()[0];
+        let mut pinned = new_data(4, 2);
+        pinned.affinity = Some(Affinity::Cores(vec![cpu]));
+        assert_eq!(allowed_on(&pinned), [cpu]);
+        assert_eq!(allowed_on(&new_data(4, 2)), affinity::allowed());
+    }
+
+    #[test]
+    fn swap_rows_test() {
+        let nsize = 12;
+        let m = SharedMatrix {
+            nsize: 2,
+            rows: vec![
+                RwLock::new(vec![1.0; nsize]),
+                RwLock::new(vec![2.0; nsize]),
+            ],
+        };
+        swap_rows(&m, 1, 0, 5);
+        swap_rows(&m, 1, 1, 0);
+        let a = m.rows[0].read().unwrap();
+        let b = m.rows[1].read().unwrap();
+        assert!(a[..5].iter().all(|&x| x == 1.0));
+        assert!(a[5..].iter().all(|&x| x == 2.0));
+        assert!(b[..5].iter().all(|&x| x == 2.0));
+        assert!(b[5..].iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn compute_gauss_p_matches_sequential() {
+        let nsize = 300;
+        let mut seq = new_data(nsize, 1);
+        init(&mut seq);
+        compute_gauss(&mut seq);
+
+        let mut par = new_data(nsize, 4);
+        init(&mut par);
+        compute_gauss_p(&mut par).unwrap();
+
+        assert_eq!(seq.matrix, par.matrix);
+        assert_eq!(seq.b, par.b);
+    }
+
+    #[test]
+    fn compute_gauss_p_swaps_rows() {
+        let mut data = new_data(3, 2);
+        data.matrix = vec![
+            vec![1.0, 2.0, 1.0],
+            vec![4.0, 1.0, 2.0],
+            vec![2.0, 3.0, 5.0],
+        ];
+        data.b = vec![1.0, 2.0, 3.0];
+        data.c = vec![0.0; 3];
+        data.v = vec![0.0; 3];
+        data.swap = vec![0, 1, 2];
+        compute_gauss_p(&mut data).unwrap();
+
+        assert_eq!(data.swap, [1, 2, 0]);
+        for i in 0..3 {
+            assert_eq!(data.matrix[i][i], 1.0);
+            for j in 0..i {
+                assert_eq!(data.matrix[i][j], 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn strategies_match_pool() {
+        // A zero on the diagonal and a large entry further down, so every
+        // strategy has to search for its pivots and they must agree on them.
+        let strategies = [
+            Strategy::Barrier,
+            Strategy::Pipelined,
+            Strategy::Dag,
+            Strategy::Actor,
+            Strategy::Channel,
+        ];
+        for num_threads in 1..5 {
+            let nsize = 130;
+            let mut pool = new_data(nsize, num_threads);
+            init(&mut pool);
+            pool.matrix[40][40] = 0.0;
+            pool.matrix[90][12] = -300.0;
+            let (matrix, b, swap) = (pool.matrix.clone(), pool.b.clone(), pool.swap.clone());
+            compute_gauss_with(&mut pool, Strategy::Pool).unwrap();
+
+            for &strategy in &strategies {
+                let mut data = new_data(nsize, num_threads);
+                data.matrix = matrix.clone();
+                data.b = b.clone();
+                data.swap = swap.clone();
+                compute_gauss_with(&mut data, strategy).unwrap();
+
+                assert_eq!(pool.matrix, data.matrix, "{:?}", strategy);
+                assert_eq!(pool.b, data.b, "{:?}", strategy);
+                assert_eq!(pool.swap, data.swap, "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_stops_only_its_own_solve() {
+        let strategies = [
+            Strategy::Pool,
+            Strategy::Barrier,
+            Strategy::Pipelined,
+            Strategy::Dag,
+            Strategy::Actor,
+            Strategy::Channel,
+        ];
+        for &strategy in &strategies {
+            let mut cancelled = new_data(50, 3);
+            init(&mut cancelled);
+            cancelled.cancel.cancel();
+            assert_eq!(
+                compute_gauss_with(&mut cancelled, strategy),
+                Err(GaussError::Cancelled),
+                "{:?}",
+                strategy
+            );
+
+            let mut data = new_data(50, 3);
+            init(&mut data);
+            assert_eq!(compute_gauss_with(&mut data, strategy), Ok(()), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn panic_in_one_worker_ends_the_solve() {
+        for &strategy in &[Strategy::Barrier, Strategy::Pipelined, Strategy::Actor] {
+            let nsize = 40;
+            let mut data = new_data(nsize, 4);
+            init(&mut data);
+            // The last row is cut short, so its owner indexes past its end in
+            // phase 1 while the others wait on it.
+            data.matrix[nsize - 1].clear();
+            data.b[nsize - 1] = 0.0;
+            match compute_gauss_with(&mut data, strategy) {
+                Err(GaussError::Panicked(msg)) => assert!(msg.contains("index out of bounds")),
+                other => panic!("{:?}: {:?}", strategy, other),
+            }
+            assert_eq!(data.matrix.len(), nsize);
+        }
+    }
+
+    // Stops the global trace when the test ends, passed or not.
+    struct Recording;
+
+    impl Drop for Recording {
+        fn drop(&mut self) {
+            trace::disable();
+            trace::reset();
+        }
+    }
+
+    #[test]
+    fn trace_records_every_phase() {
+        let nsize = 20;
+        let mut data = new_data(nsize, 3);
+        init(&mut data);
+        let _recording = Recording;
+        trace::enable();
+        compute_gauss_with(&mut data, Strategy::Barrier).unwrap();
+
+        let json = trace::to_json();
+        let spans = |name: &str, i: usize| {
+            let name = format!("\"name\":\"{}\",\"cat\":\"gauss\"", name);
+            let phase = format!("\"args\":{{\"phase\":{}}}}},", i);
+            json.lines()
+                .filter(|e| e.contains(&name) && e.ends_with(&phase))
+                .count()
+        };
+        for i in 0..nsize {
+            assert!(spans("pivot", i) >= 1);
+            assert!(spans("update", i) >= 3);
+            assert!(spans("barrier", i) >= 6);
+        }
+    }
+
+    #[test]
+    fn strategy_from_str_test() {
+        assert_eq!("pool".parse(), Ok(Strategy::Pool));
+        assert_eq!("barrier".parse(), Ok(Strategy::Barrier));
+        assert_eq!("pipelined".parse(), Ok(Strategy::Pipelined));
+        assert_eq!("dag".parse(), Ok(Strategy::Dag));
+        assert_eq!("actor".parse(), Ok(Strategy::Actor));
+        assert_eq!("channel".parse(), Ok(Strategy::Channel));
+        assert!("channels".parse::<Strategy>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::error::GaussError;
+use crate::parallel::{
+    combine, finish, first_owned, local_pivot, pool_for, set_pivot, update_row, Candidate,
+    SharedMatrix, CANCEL_POLL,
+};
+use crate::{check_dense, Data};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use threadpool_crossbeam::cancel::CancelToken;
+use threadpool_crossbeam::stats::{self, Stamp};
+use threadpool_crossbeam::trace;
+
+#[derive(Clone, Copy)]
+enum Msg {
+    // Row i is pivoted and normalized; eliminate column i with it.
+    Pivot(usize),
+    // The run is over; PipeShared::error says why.
+    Stop,
+}
+
+struct PipeShared {
+    m: SharedMatrix,
+    // The candidates reported so far for the next pivot: how many workers
+    // have reported and the best of them.
+    reduce: Mutex<(usize, Candidate)>,
+    swap: Mutex<Vec<u64>>,
+    error: Mutex<Option<GaussError>>,
+    cancel: CancelToken,
+}
+
+// Only the worker that would broadcast the next pivot stops the run, so no
+// one is left waiting on a phase that will not finish.
+fn stop(s: &PipeShared, txs: &[Sender<Msg>], e: GaussError) {
+    *s.error.lock().unwrap() = Some(e);
+    broadcast(txs, Msg::Stop);
+}
+
+fn broadcast(txs: &[Sender<Msg>], msg: Msg) {
+    for tx in txs {
+        // A worker only hangs up after seeing Stop, so a failed send here is
+        // harmless.
+        let _ = tx.send(msg);
+    }
+}
+
+// Moves the largest candidate into row currow, as parallel::pivot_p does, and
+// hands it to every worker. Both rows must be fully updated by now. Returns
+// false once the run is stopped.
+fn publish(s: &PipeShared, txs: &[Sender<Msg>], best: Candidate, currow: usize) -> bool {
+    if s.cancel.is_cancelled() {
+        stop(s, txs, GaussError::Cancelled);
+        return false;
+    }
+    if best.big == 0.0 {
+        stop(s, txs, GaussError::Singular(currow));
+        return false;
+    }
+    let span = trace::span("pivot", "gauss").phase(currow);
+    set_pivot(&s.m, &mut s.swap.lock().unwrap(), best, currow);
+    drop(span);
+    broadcast(txs, Msg::Pivot(currow));
+    true
+}
+
+// Adds this worker's candidate for pivot `next`. The last one to report gets
+// the best of them back and clears the slot for the next phase.
+fn report(s: &PipeShared, mine: Candidate, next: usize, num_threads: usize) -> Option<Candidate> {
+    let mut reduce = s.reduce.lock().unwrap();
+    reduce.0 += 1;
+    reduce.1 = combine(reduce.1, mine);
+    if reduce.0 < num_threads {
+        return None;
+    }
+    Some(std::mem::replace(&mut *reduce, (0, Candidate::none(next + 1))).1)
+}
+
+fn pipe_worker(
+    s: &PipeShared,
+    rx: Receiver<Msg>,
+    txs: &[Sender<Msg>],
+    w: usize,
+    num_threads: usize,
+) {
+    let nsize = s.m.nsize;
+
+    if w == 0 && !publish(s, txs, local_pivot(&s.m, 0, 0, 1), 0) {
+        return;
+    }
+
+    // Pivots come from different workers, so pivot i + 1 can overtake pivot
+    // i on the way here; hold on to it until its phase comes up.
+    let mut arrived = vec![false; nsize];
+    for i in 0..nsize {
+        // Phases overlap, so worker 0's time from one pivot to the next
+        // stands in for the phase; the time spent waiting on a pivot is not
+        // counted as busy.
+        let phase = Stamp::now();
+        let wait = trace::span("wait", "gauss").phase(i);
+        while !arrived[i] {
+            match rx.recv_timeout(CANCEL_POLL) {
+                Ok(Msg::Pivot(k)) => arrived[k] = true,
+                Ok(Msg::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) if s.cancel.is_cancelled() => return,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+        drop(wait);
+        let start = Stamp::now();
+
+        let next = i + 1;
+        if next < nsize {
+            let first = first_owned(next, w, num_threads);
+            let update = trace::span("update", "gauss").phase(i);
+            let pivot = s.m.rows[i].read().unwrap();
+            // Look-ahead: column next of each owned row is worked out without
+            // updating the rows, and only the rows the pivot can come from
+            // are finished before reporting. The value is computed as
+            // update_row would, so the choice matches a search after it.
+            let mine = (first..nsize)
+                .step_by(num_threads)
+                .map(|j| {
+                    let row = s.m.rows[j].read().unwrap();
+                    Candidate {
+                        big: row[next] - row[i] * pivot[next],
+                        irow: j,
+                    }
+                })
+                .fold(Candidate::none(next), combine);
+            let early = |j: usize| j % num_threads == w && (j == next || j == mine.irow);
+            if early(next) {
+                update_row(&pivot, &mut s.m.rows[next].write().unwrap(), i);
+            }
+            if mine.irow != next {
+                update_row(&pivot, &mut s.m.rows[mine.irow].write().unwrap(), i);
+            }
+            drop(pivot);
+            drop(update);
+
+            if let Some(best) = report(s, mine, next, num_threads) {
+                if !publish(s, txs, best, next) {
+                    return;
+                }
+            }
+
+            // The rest of the phase overlaps with the next pivot. Rows next
+            // and best are the only ones it can move, and both are done.
+            let _span = trace::span("update", "gauss").phase(i);
+            let pivot = s.m.rows[i].read().unwrap();
+            for j in (first..nsize).step_by(num_threads).filter(|&j| !early(j)) {
+                update_row(&pivot, &mut s.m.rows[j].write().unwrap(), i);
+            }
+        }
+        stats::busy(w, i, start.elapsed());
+        if w == 0 {
+            stats::phase(i, phase.elapsed());
+        }
+    }
+}
+
+// Look-ahead version of compute_gauss. Workers own rows cyclically as in
+// compute_gauss_barrier, but there is no barrier: during phase i each worker
+// reports its best candidate for pivot i + 1 first, and the last to report
+// pivots on the largest and broadcasts it over the workers' channels while
+// the rest are still busy with phase i.
+pub fn compute_gauss_pipelined(data: &mut Data) -> Result<(), GaussError> {
+    check_dense(data)?;
+    let num_threads = data.num_threads.max(1);
+    let pool = pool_for(data).build();
+    let shared = Arc::new(PipeShared {
+        m: SharedMatrix::from_data(data),
+        reduce: Mutex::new((0, Candidate::none(1))),
+        swap: Mutex::new(std::mem::take(&mut data.swap)),
+        error: Mutex::new(None),
+        cancel: pool.cancel_token(),
+    });
+
+    let (txs, rxs): (Vec<Sender<Msg>>, Vec<Receiver<Msg>>) =
+        (0..num_threads).map(|_| channel()).unzip();
+    for (w, rx) in rxs.into_iter().enumerate() {
+        let shared = Arc::clone(&shared);
+        let txs = txs.clone();
+        pool.execute(move || pipe_worker(&shared, rx, &txs, w, num_threads));
+    }
+    drop(txs);
+    let joined = pool.join();
+
+    let shared = Arc::try_unwrap(shared).ok().unwrap();
+    data.swap = shared.swap.into_inner().unwrap();
+    shared.m.into_data(data);
+    joined?;
+    finish(
+        &pool.cancel_token(),
+        shared.error.into_inner().unwrap().map_or(Ok(()), Err),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init;
+    use crate::parallel::compute_gauss_p;
+    use crate::test_util::new_data;
+
+    #[test]
+    fn pipelined_matches_pool() {
+        for num_threads in 1..5 {
+            let nsize = 97;
+            let mut pool = new_data(nsize, num_threads);
+            init(&mut pool);
+            pool.matrix[30][30] = 0.0;
+            pool.matrix[70][5] = -250.0;
+            let mut par = new_data(nsize, num_threads);
+            par.matrix = pool.matrix.clone();
+            par.b = pool.b.clone();
+            par.swap = pool.swap.clone();
+
+            compute_gauss_p(&mut pool).unwrap();
+            compute_gauss_pipelined(&mut par).unwrap();
+
+            assert_eq!(pool.matrix, par.matrix);
+            assert_eq!(pool.b, par.b);
+            assert_eq!(pool.swap, par.swap);
+        }
+    }
+
+    #[test]
+    fn pipelined_pivots_on_largest() {
+        let mut data = new_data(3, 2);
+        data.matrix = vec![
+            vec![1.0, 2.0, 1.0],
+            vec![1.0, 2.0, 2.0],
+            vec![2.0, 3.0, 5.0],
+        ];
+        data.b = vec![1.0, 2.0, 3.0];
+        data.c = vec![0.0; 3];
+        data.v = vec![0.0; 3];
+        data.swap = vec![0, 1, 2];
+        let mut pool = new_data(3, 2);
+        pool.matrix = data.matrix.clone();
+        pool.b = data.b.clone();
+        pool.swap = data.swap.clone();
+
+        compute_gauss_pipelined(&mut data).unwrap();
+        compute_gauss_p(&mut pool).unwrap();
+
+        assert_eq!(data.swap, [2, 1, 0]);
+        assert_eq!(data.matrix, pool.matrix);
+        assert_eq!(data.b, pool.b);
+    }
+
+    #[test]
+    fn pipelined_singular() {
+        let mut data = new_data(3, 2);
+        data.matrix = vec![
+            vec![1.0, 2.0, 1.0],
+            vec![2.0, 4.0, 2.0],
+            vec![3.0, 6.0, 3.0],
+        ];
+        data.b = vec![1.0, 2.0, 3.0];
+        data.swap = vec![0, 1, 2];
+        assert_eq!(
+            compute_gauss_pipelined(&mut data),
+            Err(GaussError::Singular(1))
+        );
+    }
+}
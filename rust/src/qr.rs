@@ -0,0 +1,258 @@
+use crate::error::GaussError;
+use crate::matrix::Matrix;
+use crate::parallel::{for_rows, new_pool, on_pool};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use threadpool_crossbeam::ThreadPool;
+
+// A = Q R for an m x n matrix, m >= n. Q is kept as the Householder
+// reflectors H_k = I - beta_k v_k v_k^T it is the product of; v_k acts on
+// rows k.. only.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Qr {
+    // R on and above the diagonal, zeros below.
+    pub r: Matrix,
+    pub vs: Vec<Vec<f64>>,
+    pub betas: Vec<f64>,
+}
+
+// A least-squares solution and the norm of b - A x it leaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lstsq {
+    pub x: Vec<f64>,
+    pub residual: f64,
+}
+
+// The reflector that maps x onto alpha e_1, with alpha of the opposite sign
+// to x[0] so that v[0] does not cancel. A zero x gets H = I (beta 0).
+fn reflector(x: &[f64]) -> (Vec<f64>, f64, f64) {
+    let norm = x.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm == 0.0 {
+        return (vec![0.0; x.len()], 0.0, 0.0);
+    }
+    let alpha = if x[0] > 0.0 { -norm } else { norm };
+    let mut v = x.to_vec();
+    v[0] -= alpha;
+    let vtv: f64 = v.iter().map(|x| x * x).sum();
+    (v, 2.0 / vtv, alpha)
+}
+
+// w += v[i - k] A[i][k + 1..] for row i of the reflection.
+fn add_row(w: &mut [f64], row: &[f64], k: usize, vi: f64) {
+    for (w, a) in w.iter_mut().zip(&row[k + 1..]) {
+        *w += vi * a;
+    }
+}
+
+// Applies H_k to the columns right of k: w = beta v^T A, then A -= v w.
+// With a pool each phase is one job per worker, like the row updates of
+// compute_gauss_p; the partial sums of w are added up on this thread.
+fn reflect(
+    pool: Option<&ThreadPool>,
+    rows: &mut Vec<Vec<f64>>,
+    k: usize,
+    v: Vec<f64>,
+    beta: f64,
+) -> Result<(), GaussError> {
+    let v = Arc::new(v);
+    let mut w = vec![0.0; rows[k].len() - k - 1];
+    match pool {
+        None => {
+            for (row, &vi) in rows[k..].iter().zip(v.iter()) {
+                add_row(&mut w, row, k, vi);
+            }
+        }
+        Some(pool) => {
+            let num_threads = pool.max_count();
+            let v = Arc::clone(&v);
+            let ncols = w.len();
+            let (tx, rx) = channel();
+            on_pool(pool, rows, move |m, worker| {
+                let mut w = vec![0.0; ncols];
+                for i in (k + worker..m.nsize).step_by(num_threads) {
+                    add_row(&mut w, &m.rows[i].read().unwrap(), k, v[i - k]);
+                }
+                // The receiver outlives the phase.
+                let _ = tx.send(w);
+            })?;
+            for part in rx {
+                w.iter_mut().zip(&part).for_each(|(w, p)| *w += p);
+            }
+        }
+    }
+    w.iter_mut().for_each(|w| *w *= beta);
+    for_rows(pool, rows, k, move |i, row| {
+        let vi = v[i - k];
+        for (a, w) in row[k + 1..].iter_mut().zip(&w) {
+            *a -= vi * w;
+        }
+    })
+}
+
+fn factor(a: &Matrix, pool: Option<&ThreadPool>) -> Result<Qr, GaussError> {
+    let (m, n) = (a.nrows, a.ncols);
+    assert!(m >= n, "QR needs at least as many rows as columns");
+    let mut rows = a.rows.clone();
+    let mut vs = Vec::with_capacity(n);
+    let mut betas = Vec::with_capacity(n);
+
+    for k in 0..n {
+        let x: Vec<f64> = rows[k..].iter().map(|row| row[k]).collect();
+        let (v, beta, alpha) = reflector(&x);
+        if k + 1 < n {
+            reflect(pool, &mut rows, k, v.clone(), beta)?;
+        }
+        rows[k][k] = alpha;
+        for row in rows[k + 1..].iter_mut() {
+            row[k] = 0.0;
+        }
+        vs.push(v);
+        betas.push(beta);
+    }
+
+    Ok(Qr {
+        r: Matrix::from_rows(rows),
+        vs,
+        betas,
+    })
+}
+
+pub fn householder(a: &Matrix) -> Qr {
+    // Only the pool can fail.
+    factor(a, None).unwrap()
+}
+
+// householder with every reflection split over num_threads pool jobs.
+pub fn householder_p(a: &Matrix, num_threads: usize) -> Result<Qr, GaussError> {
+    let pool = new_pool(num_threads.max(1));
+    factor(a, Some(&pool))
+}
+
+impl Qr {
+    // Q^T b = H_{n-1} ... H_0 b.
+    pub fn qt_mul(&self, b: &[f64]) -> Vec<f64> {
+        let mut y = b.to_vec();
+        for (k, (v, &beta)) in self.vs.iter().zip(&self.betas).enumerate() {
+            reflect_vec(&mut y[k..], v, beta);
+        }
+        y
+    }
+
+    // Q y = H_0 ... H_{n-1} y.
+    pub fn q_mul(&self, y: &[f64]) -> Vec<f64> {
+        let mut b = y.to_vec();
+        for (k, (v, &beta)) in self.vs.iter().zip(&self.betas).enumerate().rev() {
+            reflect_vec(&mut b[k..], v, beta);
+        }
+        b
+    }
+
+    // Minimizes |A x - b|: R x = (Q^T b)[..n], and what is left of Q^T b is
+    // the residual. A diagonal of R that is negligible next to the largest
+    // means A does not have full column rank; it is reported as Singular.
+    pub fn solve(&self, b: &[f64]) -> Result<Lstsq, GaussError> {
+        let (m, n) = (self.r.nrows, self.r.ncols);
+        assert_eq!(b.len(), m, "right hand side does not match the rows");
+        let r = &self.r.rows;
+        let biggest = (0..n).map(|k| r[k][k].abs()).fold(0.0, f64::max);
+        let tol = biggest * m as f64 * f64::EPSILON;
+        if let Some(k) = (0..n).find(|&k| r[k][k].abs() <= tol) {
+            return Err(GaussError::Singular(k));
+        }
+
+        let y = self.qt_mul(b);
+        let mut x = vec![0.0; n];
+        for i in (0..n).rev() {
+            let s: f64 = r[i][i + 1..].iter().zip(&x[i + 1..]).map(|(a, x)| a * x).sum();
+            x[i] = (y[i] - s) / r[i][i];
+        }
+        let residual = y[n..].iter().map(|y| y * y).sum::<f64>().sqrt();
+        Ok(Lstsq { x, residual })
+    }
+}
+
+fn reflect_vec(y: &mut [f64], v: &[f64], beta: f64) {
+    let s = beta * v.iter().zip(y.iter()).map(|(v, y)| v * y).sum::<f64>();
+    for (y, v) in y.iter_mut().zip(v) {
+        *y -= s * v;
+    }
+}
+
+pub fn lstsq(a: &Matrix, b: &[f64]) -> Result<Lstsq, GaussError> {
+    householder(a).solve(b)
+}
+
+pub fn lstsq_p(a: &Matrix, b: &[f64], num_threads: usize) -> Result<Lstsq, GaussError> {
+    householder_p(a, num_threads)?.solve(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An m x n matrix with entries that are not too regular.
+    fn tall(m: usize, n: usize) -> Matrix {
+        Matrix::from_rows(
+            (0..m)
+                .map(|i| (0..n).map(|j| ((i * 5 + j * 7 + i * j) % 13) as f64 - 6.0).collect())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn line_fit() {
+        // y = 1.5 + x misses every point by 0.5.
+        let a = Matrix::from_rows(vec![
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+            vec![1.0, 2.0],
+            vec![1.0, 3.0],
+        ]);
+        let fit = lstsq(&a, &[1.0, 3.0, 4.0, 4.0]).unwrap();
+        assert!((fit.x[0] - 1.5).abs() < 1e-12);
+        assert!((fit.x[1] - 1.0).abs() < 1e-12);
+        assert!((fit.residual - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn consistent_overdetermined() {
+        let a = tall(40, 6);
+        let x: Vec<f64> = (0..6).map(|i| i as f64 - 2.5).collect();
+        let b = a.mul_vec(&x);
+        for fit in [lstsq(&a, &b).unwrap(), lstsq_p(&a, &b, 3).unwrap()] {
+            assert!(fit.residual < 1e-9);
+            for (a, b) in fit.x.iter().zip(&x) {
+                assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn q_times_r_is_a() {
+        let a = tall(12, 5);
+        let qr = householder(&a);
+        let par = householder_p(&a, 3).unwrap();
+        for (x, y) in qr.r.rows.iter().flatten().zip(par.r.rows.iter().flatten()) {
+            assert!((x - y).abs() < 1e-9);
+        }
+        // Column j of A is Q times column j of R.
+        let r = qr.r.transpose();
+        let at = a.transpose();
+        for (rj, aj) in r.rows.iter().zip(&at.rows) {
+            for (x, y) in qr.q_mul(rj).iter().zip(aj) {
+                assert!((x - y).abs() < 1e-9);
+            }
+        }
+        // Q is orthogonal.
+        let b: Vec<f64> = (0..12).map(|i| i as f64).collect();
+        for (x, y) in qr.q_mul(&qr.qt_mul(&b)).iter().zip(&b) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn rank_deficient() {
+        let a = Matrix::from_rows(vec![vec![1.0, 2.0], vec![2.0, 4.0], vec![3.0, 6.0]]);
+        assert_eq!(lstsq(&a, &[1.0, 2.0, 3.0]), Err(GaussError::Singular(1)));
+    }
+}
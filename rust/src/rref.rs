@@ -0,0 +1,239 @@
+use crate::error::GaussError;
+use crate::matrix::Matrix;
+
+// The reduced row echelon form of a matrix: row i has a leading 1 in column
+// pivots[i] and every other entry of that column is zero. Rows from rank on
+// are zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rref {
+    pub matrix: Matrix,
+    pub rank: usize,
+    pub pivots: Vec<usize>,
+}
+
+// Entries at most this large count as zero: a few units of rounding in the
+// largest entry of `a`.
+pub fn default_tol(a: &Matrix) -> f64 {
+    let biggest = a.rows.iter().flatten().fold(0.0, |m: f64, x| m.max(x.abs()));
+    a.nrows.max(a.ncols) as f64 * f64::EPSILON * biggest
+}
+
+// Gauss-Jordan elimination. Unlike compute_gauss it takes the largest entry
+// of the column as the pivot, and a column with nothing above `tol` left in
+// it is skipped instead of being singular, so any matrix can be reduced.
+pub fn rref(a: &Matrix, tol: f64) -> Rref {
+    let mut m = a.clone();
+    let mut pivots = Vec::new();
+    let mut row = 0;
+
+    for col in 0..m.ncols {
+        if row == m.nrows {
+            break;
+        }
+        let (p, big) = (row..m.nrows)
+            .map(|i| (i, m.rows[i][col].abs()))
+            .fold((row, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+        if big <= tol {
+            for r in m.rows[row..].iter_mut() {
+                r[col] = 0.0;
+            }
+            continue;
+        }
+        m.rows.swap(row, p);
+
+        let pivot_val = m.rows[row][col];
+        for x in m.rows[row][col + 1..].iter_mut() {
+            *x /= pivot_val;
+        }
+        m.rows[row][col] = 1.0;
+        let pivot_row = m.rows[row].clone();
+        for (i, r) in m.rows.iter_mut().enumerate() {
+            let f = r[col];
+            if i == row || f == 0.0 {
+                continue;
+            }
+            for (x, p) in r[col + 1..].iter_mut().zip(&pivot_row[col + 1..]) {
+                *x -= f * p;
+            }
+            r[col] = 0.0;
+        }
+        pivots.push(col);
+        row += 1;
+    }
+
+    Rref {
+        matrix: m,
+        rank: pivots.len(),
+        pivots,
+    }
+}
+
+pub fn rank(a: &Matrix) -> usize {
+    rref(a, default_tol(a)).rank
+}
+
+// One basis vector per free column f among the first n columns of `m`:
+// x[f] = 1, x[pivots[i]] = -m[i][f], zero elsewhere.
+fn null_basis(m: &Matrix, pivots: &[usize], n: usize) -> (Vec<usize>, Vec<Vec<f64>>) {
+    let free: Vec<usize> = (0..n).filter(|c| !pivots.contains(c)).collect();
+    let basis = free
+        .iter()
+        .map(|&f| {
+            let mut x = vec![0.0; n];
+            x[f] = 1.0;
+            for (i, &p) in pivots.iter().enumerate().filter(|&(_, &p)| p < n) {
+                x[p] = -m.rows[i][f];
+            }
+            x
+        })
+        .collect();
+    (free, basis)
+}
+
+impl Rref {
+    // Columns without a pivot; each one is a free variable of A x = b.
+    pub fn free_columns(&self) -> Vec<usize> {
+        null_basis(&self.matrix, &self.pivots, self.matrix.ncols).0
+    }
+
+    // A basis of { x : A x = 0 }, one vector per free column.
+    pub fn null_space(&self) -> Vec<Vec<f64>> {
+        null_basis(&self.matrix, &self.pivots, self.matrix.ncols).1
+    }
+}
+
+// Every solution of A x = b: particular + sum of t[k] null_space[k], where
+// t[k] is the value of the free variable free[k].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub particular: Vec<f64>,
+    pub free: Vec<usize>,
+    pub null_space: Vec<Vec<f64>>,
+}
+
+impl Solution {
+    pub fn at(&self, t: &[f64]) -> Vec<f64> {
+        assert_eq!(t.len(), self.free.len(), "one value per free variable");
+        let mut x = self.particular.clone();
+        for (basis, &t) in self.null_space.iter().zip(t) {
+            for (x, v) in x.iter_mut().zip(basis) {
+                *x += t * v;
+            }
+        }
+        x
+    }
+}
+
+// Reduces [A | b]. A pivot in the b column means a row 0 = c with c nonzero;
+// that is reported as Inconsistent with the row of the reduced system.
+// Otherwise the particular solution sets every free variable to zero.
+pub fn solve_general(a: &Matrix, b: &[f64], tol: f64) -> Result<Solution, GaussError> {
+    assert_eq!(b.len(), a.nrows, "right hand side does not match the rows");
+    let n = a.ncols;
+    let augmented = Matrix::from_rows(
+        a.rows
+            .iter()
+            .zip(b)
+            .map(|(row, &b)| {
+                let mut row = row.clone();
+                row.push(b);
+                row
+            })
+            .collect(),
+    );
+    let r = rref(&augmented, tol);
+    if let Some(i) = r.pivots.iter().position(|&c| c == n) {
+        return Err(GaussError::Inconsistent(i));
+    }
+
+    let mut particular = vec![0.0; n];
+    for (i, &p) in r.pivots.iter().enumerate() {
+        particular[p] = r.matrix.rows[i][n];
+    }
+    let (free, null_space) = null_basis(&r.matrix, &r.pivots, n);
+    Ok(Solution {
+        particular,
+        free,
+        null_space,
+    })
+}
+
+pub fn is_consistent(a: &Matrix, b: &[f64], tol: f64) -> bool {
+    solve_general(a, b, tol).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::init;
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9)
+    }
+
+    #[test]
+    fn rank_deficient() {
+        // The third row is the sum of the first two.
+        let a = Matrix::from_rows(vec![
+            vec![1.0, 2.0, -1.0, 3.0],
+            vec![2.0, 4.0, 1.0, 0.0],
+            vec![3.0, 6.0, 0.0, 3.0],
+        ]);
+        let r = rref(&a, default_tol(&a));
+        assert_eq!(r.rank, 2);
+        assert_eq!(r.pivots, vec![0, 2]);
+        assert_eq!(r.free_columns(), vec![1, 3]);
+        assert!(r.matrix.rows[2].iter().all(|&x| x == 0.0));
+        let null = r.null_space();
+        assert_eq!(null.len(), 2);
+        for x in &null {
+            assert!(close(&a.mul_vec(x), &[0.0; 3]));
+        }
+    }
+
+    #[test]
+    fn rank_of_init() {
+        let mut data = new_data(12, 1);
+        init(&mut data);
+        let mut a = Matrix::from_data(&data);
+        assert_eq!(rank(&a), 12);
+        // compute_gauss would panic on this one.
+        a.rows[11] = a.rows[10].clone();
+        assert_eq!(rank(&a), 11);
+        assert_eq!(rref(&a, default_tol(&a)).null_space().len(), 1);
+    }
+
+    #[test]
+    fn underdetermined() {
+        let a = Matrix::from_rows(vec![vec![1.0, 1.0, 1.0, 0.0], vec![0.0, 1.0, 2.0, 1.0]]);
+        let b = [6.0, 5.0];
+        let s = solve_general(&a, &b, default_tol(&a)).unwrap();
+        assert_eq!(s.free, vec![2, 3]);
+        assert!(close(&a.mul_vec(&s.particular), &b));
+        for t in [[1.0, 0.0], [0.0, 1.0], [-2.5, 3.0]] {
+            assert!(close(&a.mul_vec(&s.at(&t)), &b));
+        }
+        assert!(is_consistent(&a, &b, default_tol(&a)));
+    }
+
+    #[test]
+    fn inconsistent() {
+        let a = Matrix::from_rows(vec![vec![1.0, 2.0], vec![2.0, 4.0], vec![0.0, 1.0]]);
+        let tol = default_tol(&a);
+        assert_eq!(solve_general(&a, &[1.0, 3.0, 0.0], tol), Err(GaussError::Inconsistent(2)));
+        assert!(!is_consistent(&a, &[1.0, 3.0, 0.0], tol));
+        assert!(is_consistent(&a, &[1.0, 2.0, 0.0], tol));
+    }
+
+    #[test]
+    fn tolerance() {
+        let a = Matrix::from_rows(vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0 + 1e-13],
+        ]);
+        assert_eq!(rref(&a, 0.0).rank, 3);
+        assert_eq!(rref(&a, 1e-10).rank, 2);
+    }
+}
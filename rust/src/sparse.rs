@@ -0,0 +1,653 @@
+use crate::error::GaussError;
+use crate::ordering::{permute, Reorder};
+use crate::Data;
+use std::mem;
+
+// Compressed sparse row storage: the entries of row i are
+// col_idx/vals[row_ptr[i]..row_ptr[i + 1]], ordered by column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Csr {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub row_ptr: Vec<usize>,
+    pub col_idx: Vec<usize>,
+    pub vals: Vec<f64>,
+}
+
+// Compressed sparse column storage, the transpose layout of Csr.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Csc {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub col_ptr: Vec<usize>,
+    pub row_idx: Vec<usize>,
+    pub vals: Vec<f64>,
+}
+
+// Regroups the entries of `nouter` compressed lines by their inner index,
+// which turns CSR into CSC and back.
+fn transpose(
+    nouter: usize,
+    ninner: usize,
+    ptr: &[usize],
+    idx: &[usize],
+    vals: &[f64],
+) -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+    let mut tptr = vec![0; ninner + 1];
+    for &j in idx {
+        tptr[j + 1] += 1;
+    }
+    for j in 0..ninner {
+        tptr[j + 1] += tptr[j];
+    }
+    let mut next = tptr.clone();
+    let mut tidx = vec![0; idx.len()];
+    let mut tvals = vec![0.0; idx.len()];
+    for i in 0..nouter {
+        for k in ptr[i]..ptr[i + 1] {
+            let p = next[idx[k]];
+            tidx[p] = i;
+            tvals[p] = vals[k];
+            next[idx[k]] += 1;
+        }
+    }
+    (tptr, tidx, tvals)
+}
+
+impl Csr {
+    // Entries given more than once are summed.
+    pub fn from_triplets(nrows: usize, ncols: usize, triplets: &[(usize, usize, f64)]) -> Csr {
+        let mut sorted = triplets.to_vec();
+        sorted.sort_by_key(|&(i, j, _)| (i, j));
+        let mut row_ptr = vec![0; nrows + 1];
+        let mut col_idx: Vec<usize> = Vec::with_capacity(sorted.len());
+        let mut vals: Vec<f64> = Vec::with_capacity(sorted.len());
+        let mut last = None;
+        for (i, j, v) in sorted {
+            assert!(i < nrows && j < ncols, "entry ({}, {}) out of bounds", i, j);
+            if last == Some((i, j)) {
+                *vals.last_mut().unwrap() += v;
+                continue;
+            }
+            last = Some((i, j));
+            row_ptr[i + 1] += 1;
+            col_idx.push(j);
+            vals.push(v);
+        }
+        for i in 0..nrows {
+            row_ptr[i + 1] += row_ptr[i];
+        }
+        Csr {
+            nrows,
+            ncols,
+            row_ptr,
+            col_idx,
+            vals,
+        }
+    }
+
+    pub fn from_dense(matrix: &[Vec<f64>]) -> Csr {
+        let ncols = matrix.first().map_or(0, |row| row.len());
+        let mut triplets = Vec::new();
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                if x != 0.0 {
+                    triplets.push((i, j, x));
+                }
+            }
+        }
+        Csr::from_triplets(matrix.len(), ncols, &triplets)
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<f64>> {
+        let mut matrix = vec![vec![0.0; self.ncols]; self.nrows];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            let (cols, vals) = self.row(i);
+            for (&j, &x) in cols.iter().zip(vals) {
+                row[j] = x;
+            }
+        }
+        matrix
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.vals.len()
+    }
+
+    pub fn row(&self, i: usize) -> (&[usize], &[f64]) {
+        let r = self.row_ptr[i]..self.row_ptr[i + 1];
+        (&self.col_idx[r.clone()], &self.vals[r])
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        let (cols, vals) = self.row(i);
+        cols.binary_search(&j).map_or(0.0, |k| vals[k])
+    }
+
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        (0..self.nrows)
+            .map(|i| {
+                let (cols, vals) = self.row(i);
+                cols.iter().zip(vals).map(|(&j, &a)| a * x[j]).sum()
+            })
+            .collect()
+    }
+
+    pub fn to_csc(&self) -> Csc {
+        let (col_ptr, row_idx, vals) = transpose(
+            self.nrows,
+            self.ncols,
+            &self.row_ptr,
+            &self.col_idx,
+            &self.vals,
+        );
+        Csc {
+            nrows: self.nrows,
+            ncols: self.ncols,
+            col_ptr,
+            row_idx,
+            vals,
+        }
+    }
+}
+
+impl Csc {
+    pub fn nnz(&self) -> usize {
+        self.vals.len()
+    }
+
+    pub fn col(&self, j: usize) -> (&[usize], &[f64]) {
+        let r = self.col_ptr[j]..self.col_ptr[j + 1];
+        (&self.row_idx[r.clone()], &self.vals[r])
+    }
+
+    pub fn to_csr(&self) -> Csr {
+        let (row_ptr, col_idx, vals) = transpose(
+            self.ncols,
+            self.nrows,
+            &self.col_ptr,
+            &self.row_idx,
+            &self.vals,
+        );
+        Csr {
+            nrows: self.nrows,
+            ncols: self.ncols,
+            row_ptr,
+            col_idx,
+            vals,
+        }
+    }
+}
+
+// The matrix of a Data that is solved sparse; Data::matrix stays empty.
+// compute_gauss and compute_gauss_with eliminate it in `order` and leave U,
+// with the same unit diagonal as the dense path, in `matrix`.
+#[derive(Clone, Debug)]
+pub struct Sparse {
+    pub matrix: Csr,
+    pub order: Reorder,
+    // The order the last elimination took the unknowns in, perm[new] = old.
+    pub perm: Vec<usize>,
+    // Entries the last elimination created that were not in the matrix.
+    pub fill: usize,
+}
+
+impl Sparse {
+    pub fn new(matrix: Csr, order: Reorder) -> Sparse {
+        Sparse {
+            perm: (0..matrix.nrows).collect(),
+            matrix,
+            order,
+            fill: 0,
+        }
+    }
+}
+
+// Width of the grid init_sparse discretizes for n points: about sqrt(n), so
+// the grid is about square.
+pub fn grid_width(n: usize) -> usize {
+    ((n as f64).sqrt().ceil() as usize).max(1)
+}
+
+// Fills in the 5-point Laplacian of a grid_width-wide grid with nsize
+// points as data's sparse matrix, to be eliminated in `order`, and b such
+// that the solution is all ones.
+pub fn init_sparse(data: &mut Data, order: Reorder) {
+    let n = data.nsize;
+    let w = grid_width(n);
+    let mut triplets = Vec::with_capacity(5 * n);
+    for i in 0..n {
+        triplets.push((i, i, 4.0));
+        if i % w != 0 {
+            triplets.push((i, i - 1, -1.0));
+        }
+        if (i + 1) % w != 0 && i + 1 < n {
+            triplets.push((i, i + 1, -1.0));
+        }
+        if i >= w {
+            triplets.push((i, i - w, -1.0));
+        }
+        if i + w < n {
+            triplets.push((i, i + w, -1.0));
+        }
+    }
+    let matrix = Csr::from_triplets(n, n, &triplets);
+    data.b = matrix.mul_vec(&vec![1.0; n]);
+    data.c = vec![0.0; n];
+    data.v = vec![0.0; n];
+    data.swap = (0..n as u64).collect();
+    data.matrix.clear();
+    data.sparse = Some(Sparse::new(matrix, order));
+}
+
+// The nonzero structure of an elimination, worked out without the values so
+// that it can be reused for every matrix with the same pattern. It assumes
+// the diagonal pivots; a row swap during compute_gauss_sparse makes it an
+// estimate. ordering::analyze_with analyzes a reordered matrix and keeps the
+// permutation in perm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbolic {
+    pub nsize: usize,
+    // Entries of every row of U, diagonal included.
+    pub row_nnz: Vec<usize>,
+    pub nnz: usize,
+    pub fill: usize,
+    pub perm: Vec<usize>,
+}
+
+// Rows that still hold an entry in each column, taken from the pattern of
+// `a`. Eliminating column i visits the rows in col_rows[i].
+fn col_rows(a: &Csr) -> Vec<Vec<usize>> {
+    let mut rows = vec![Vec::new(); a.ncols];
+    for i in 0..a.nrows {
+        for &j in a.row(i).0 {
+            rows[j].push(i);
+        }
+    }
+    rows
+}
+
+pub fn analyze(a: &Csr) -> Symbolic {
+    assert_eq!(a.nrows, a.ncols, "the matrix is not square");
+    let n = a.nrows;
+    let mut rows: Vec<Vec<usize>> = (0..n).map(|i| a.row(i).0.to_vec()).collect();
+    let mut cols = col_rows(a);
+    let mut row_nnz = vec![0; n];
+    let mut fill = 0;
+    let mut merged = Vec::new();
+
+    for i in 0..n {
+        let pivot = mem::take(&mut rows[i]);
+        for r in mem::take(&mut cols[i]) {
+            if r <= i {
+                continue;
+            }
+            merged.clear();
+            let row = &rows[r];
+            let (mut x, mut y) = (1, 1);
+            while x < row.len() || y < pivot.len() {
+                if y == pivot.len() || (x < row.len() && row[x] < pivot[y]) {
+                    merged.push(row[x]);
+                    x += 1;
+                } else if x == row.len() || pivot[y] < row[x] {
+                    merged.push(pivot[y]);
+                    cols[pivot[y]].push(r);
+                    fill += 1;
+                    y += 1;
+                } else {
+                    merged.push(row[x]);
+                    x += 1;
+                    y += 1;
+                }
+            }
+            mem::swap(&mut rows[r], &mut merged);
+        }
+        row_nnz[i] = pivot.len();
+    }
+
+    Symbolic {
+        nsize: n,
+        row_nnz,
+        nnz: a.nnz(),
+        fill,
+        perm: (0..n).collect(),
+    }
+}
+
+// Where the rows of the elimination are. Rows keep their index into `rows`
+// for the whole run; pivoting only swaps their positions.
+struct Rows {
+    rows: Vec<Vec<(usize, f64)>>,
+    b: Vec<f64>,
+    // Position -> row and row -> position.
+    at: Vec<usize>,
+    pos: Vec<usize>,
+}
+
+// Same contract as lib::pivot: keeps the diagonal unless it is zero, then
+// takes the first row below with a nonzero in the column, and scales the
+// pivot row to a leading 1.0. Returns the row now at position currow.
+fn pivot(
+    r: &mut Rows,
+    col_rows: &[usize],
+    swap: &mut [u64],
+    currow: usize,
+) -> Result<usize, GaussError> {
+    let lead = |row: &Vec<(usize, f64)>| match row.first() {
+        Some(&(j, x)) if j == currow => x,
+        _ => 0.0,
+    };
+    let mut irow = r.at[currow];
+    if lead(&r.rows[irow]) == 0.0 {
+        irow = col_rows
+            .iter()
+            .copied()
+            .filter(|&k| r.pos[k] > currow && lead(&r.rows[k]) != 0.0)
+            .min_by_key(|&k| r.pos[k])
+            .ok_or(GaussError::Singular(currow))?;
+        let other = r.pos[irow];
+        let cur = r.at[currow];
+        r.at.swap(currow, other);
+        r.pos[irow] = currow;
+        r.pos[cur] = other;
+        swap.swap(other, currow);
+    }
+
+    let row = &mut r.rows[irow];
+    let pivot_val = row[0].1;
+    if (pivot_val - 1.0).abs() > 0.0000001 {
+        row[0].1 = 1.0;
+        for e in row[1..].iter_mut() {
+            e.1 /= pivot_val;
+        }
+        r.b[irow] /= pivot_val;
+    }
+    Ok(irow)
+}
+
+// What compute_gauss and compute_gauss_with run on a Data with a sparse
+// matrix: the elimination in the matrix's order. The symbolic analysis would
+// only size the rows, and costs about as much as the elimination, so it is
+// skipped.
+pub fn solve_sparse(data: &mut Data) -> Result<(), GaussError> {
+    let sparse = data.sparse.as_ref().expect("no sparse matrix");
+    let perm = sparse.order.permutation(&sparse.matrix);
+    eliminate(data, perm, None)
+}
+
+// Sparse version of compute_gauss: the same row operations, applied only
+// where a row or the pivot row has an entry. `sym` comes from analyze on a
+// matrix with the pattern of data's sparse matrix; if it carries an
+// ordering, the matrix and b are permuted to it first and stay that way.
+pub fn compute_gauss_sparse(data: &mut Data, sym: &Symbolic) -> Result<(), GaussError> {
+    assert_eq!(sym.nsize, data.nsize, "symbolic analysis of another matrix");
+    eliminate(data, sym.perm.clone(), Some(&sym.row_nnz))
+}
+
+fn eliminate(
+    data: &mut Data,
+    perm: Vec<usize>,
+    row_nnz: Option<&[usize]>,
+) -> Result<(), GaussError> {
+    let n = data.nsize;
+    let sparse = data.sparse.as_mut().expect("no sparse matrix");
+    if perm.iter().enumerate().any(|(i, &k)| i != k) {
+        sparse.matrix = permute(&sparse.matrix, &perm);
+        let (b, swap) = (mem::take(&mut data.b), mem::take(&mut data.swap));
+        data.b = perm.iter().map(|&k| b[k]).collect();
+        data.swap = perm.iter().map(|&k| swap[k]).collect();
+    }
+    sparse.perm = perm;
+    let mut cols = col_rows(&sparse.matrix);
+    let mut r = Rows {
+        rows: (0..n)
+            .map(|i| {
+                let (c, v) = sparse.matrix.row(i);
+                let mut row = Vec::with_capacity(row_nnz.map_or(c.len(), |nnz| nnz[i]));
+                row.extend(c.iter().copied().zip(v.iter().copied()));
+                row
+            })
+            .collect(),
+        b: mem::take(&mut data.b),
+        at: (0..n).collect(),
+        pos: (0..n).collect(),
+    };
+    let mut fill = 0;
+    let mut merged = Vec::new();
+
+    for i in 0..n {
+        let irow = match pivot(&mut r, &cols[i], &mut data.swap, i) {
+            Ok(irow) => irow,
+            Err(e) => {
+                data.b = r.b;
+                return Err(e);
+            }
+        };
+        let pivot_row = mem::take(&mut r.rows[irow]);
+        let pivot_b = r.b[irow];
+
+        for k in mem::take(&mut cols[i]) {
+            if r.pos[k] <= i {
+                continue;
+            }
+            let row = &mut r.rows[k];
+            let pivot_val = row[0].1;
+            if pivot_val == 0.0 {
+                row.remove(0);
+                continue;
+            }
+            merged.clear();
+            let (mut x, mut y) = (1, 1);
+            while x < row.len() || y < pivot_row.len() {
+                if y == pivot_row.len() || (x < row.len() && row[x].0 < pivot_row[y].0) {
+                    merged.push(row[x]);
+                    x += 1;
+                } else if x == row.len() || pivot_row[y].0 < row[x].0 {
+                    let (j, p) = pivot_row[y];
+                    merged.push((j, 0.0 - pivot_val * p));
+                    cols[j].push(k);
+                    fill += 1;
+                    y += 1;
+                } else {
+                    merged.push((row[x].0, row[x].1 - pivot_val * pivot_row[y].1));
+                    x += 1;
+                    y += 1;
+                }
+            }
+            mem::swap(row, &mut merged);
+            r.b[k] -= pivot_val * pivot_b;
+        }
+        r.rows[irow] = pivot_row;
+    }
+
+    let mut triplets = Vec::new();
+    for (i, &k) in r.at.iter().enumerate() {
+        triplets.extend(r.rows[k].iter().map(|&(j, x)| (i, j, x)));
+    }
+    sparse.matrix = Csr::from_triplets(n, n, &triplets);
+    sparse.fill = fill;
+    data.b = r.at.iter().map(|&k| r.b[k]).collect();
+    Ok(())
+}
+
+// Back substitution on the U left by compute_gauss_sparse; the solution
+// goes to data.c, in the original order of the unknowns. back_substitute
+// runs it on a sparse Data.
+pub fn solve_gauss_sparse(data: &mut Data) {
+    let n = data.nsize;
+    let sparse = data.sparse.as_ref().expect("no sparse matrix");
+    let mut v = vec![0.0; n];
+    for i in (0..n).rev() {
+        let (cols, vals) = sparse.matrix.row(i);
+        let mut x = data.b[i];
+        for (&j, &u) in cols[1..].iter().zip(&vals[1..]) {
+            x -= u * v[j];
+        }
+        v[i] = x / vals[0];
+    }
+    data.c.resize(n, 0.0);
+    for (i, &k) in sparse.perm.iter().enumerate() {
+        data.c[k] = v[i];
+    }
+}
+
+// init_sparse makes the solution all ones.
+pub fn verify_sparse(data: &Data) {
+    let err: f64 = 0.000001;
+    for &x in &data.c {
+        assert!((x - 1.0).abs() < err);
+    }
+    println!("Verified");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parallel::{compute_gauss_with, Strategy};
+    use crate::test_util::new_data;
+    use crate::{back_substitute, compute_gauss};
+
+    #[test]
+    fn csr_csc_round_trip() {
+        let dense = vec![
+            vec![1.0, 0.0, 2.0, 0.0],
+            vec![0.0, 0.0, 0.0, 3.0],
+            vec![4.0, 5.0, 0.0, 0.0],
+        ];
+        let a = Csr::from_dense(&dense);
+        assert_eq!(a.nnz(), 5);
+        assert_eq!(a.row_ptr, vec![0, 2, 3, 5]);
+        assert_eq!(a.to_dense(), dense);
+        assert_eq!(a.get(2, 1), 5.0);
+        assert_eq!(a.get(1, 1), 0.0);
+        assert_eq!(a.mul_vec(&[1.0, 1.0, 1.0, 1.0]), vec![3.0, 3.0, 9.0]);
+
+        let c = a.to_csc();
+        assert_eq!(c.col(0), (&[0, 2][..], &[1.0, 4.0][..]));
+        assert_eq!(c.to_csr(), a);
+
+        let dup = Csr::from_triplets(2, 2, &[(1, 0, 1.0), (0, 1, 2.0), (1, 0, 3.0)]);
+        assert_eq!(dup.to_dense(), vec![vec![0.0, 2.0], vec![4.0, 0.0]]);
+    }
+
+    #[test]
+    fn sparse_matches_dense() {
+        // A zero diagonal in row 0 makes both paths swap rows.
+        let nsize = 6;
+        let matrix = vec![
+            vec![0.0, 2.0, 0.0, 0.0, 1.0, 0.0],
+            vec![3.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 4.0, 1.0, 0.0, 0.0],
+            vec![1.0, 0.0, 0.0, 5.0, 0.0, 2.0],
+            vec![0.0, 1.0, 2.0, 0.0, 6.0, 0.0],
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 7.0],
+        ];
+        let b: Vec<f64> = (0..nsize).map(|i| i as f64 + 1.0).collect();
+        let mut dense = new_data(nsize, 1);
+        dense.matrix = matrix.clone();
+        dense.b = b.clone();
+        dense.c = vec![0.0; nsize];
+        dense.v = vec![0.0; nsize];
+        dense.swap = (0..nsize as u64).collect();
+        compute_gauss(&mut dense);
+
+        let mut data = new_data(nsize, 1);
+        data.sparse = Some(Sparse::new(Csr::from_dense(&matrix), Reorder::Natural));
+        data.b = b;
+        data.c = vec![0.0; nsize];
+        data.swap = (0..nsize as u64).collect();
+        compute_gauss(&mut data);
+
+        assert!(data.matrix.is_empty());
+        assert_eq!(data.sparse.unwrap().matrix.to_dense(), dense.matrix);
+        assert_eq!(data.b, dense.b);
+        assert_eq!(data.swap, dense.swap);
+    }
+
+    #[test]
+    fn symbolic_predicts_fill() {
+        let mut data = new_data(200, 1);
+        init_sparse(&mut data, Reorder::Natural);
+        let sym = analyze(&data.sparse.as_ref().unwrap().matrix);
+        assert_eq!(sym.nnz, data.sparse.as_ref().unwrap().matrix.nnz());
+        compute_gauss_sparse(&mut data, &sym).unwrap();
+
+        let u = data.sparse.unwrap();
+        assert_eq!(u.fill, sym.fill);
+        let row_nnz: Vec<usize> = (0..200).map(|i| u.matrix.row(i).0.len()).collect();
+        assert_eq!(row_nnz, sym.row_nnz);
+
+        // The analysis carries over to another matrix with the same pattern.
+        let mut again = new_data(200, 1);
+        init_sparse(&mut again, Reorder::Natural);
+        let sparse = again.sparse.as_mut().unwrap();
+        sparse.matrix.vals.iter_mut().for_each(|x| *x *= 2.0);
+        again.b.iter_mut().for_each(|x| *x *= 2.0);
+        compute_gauss_sparse(&mut again, &sym).unwrap();
+        solve_gauss_sparse(&mut again);
+        verify_sparse(&again);
+    }
+
+    #[test]
+    fn solve_large_sparse() {
+        // A 317 x 317 grid: in the natural order the band would fill in.
+        let nsize = 100_000;
+        let mut data = new_data(nsize, 4);
+        init_sparse(&mut data, Reorder::Amd);
+        assert_eq!(grid_width(nsize), 317);
+        compute_gauss_with(&mut data, Strategy::Pool).unwrap();
+        back_substitute(&mut data);
+        verify_sparse(&data);
+        // AMD keeps the fill to a fraction of what the natural order's band
+        // would create, about 2 * width^3.
+        let fill = data.sparse.unwrap().fill;
+        assert!(fill < 2 * 317 * 317 * 317 / 5, "fill {}", fill);
+    }
+
+    #[test]
+    fn sparse_singular() {
+        let mut data = new_data(3, 1);
+        let matrix = Csr::from_dense(&[
+            vec![1.0, 2.0, 0.0],
+            vec![2.0, 4.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+        ]);
+        data.sparse = Some(Sparse::new(matrix, Reorder::Natural));
+        data.b = vec![1.0, 2.0, 3.0];
+        data.swap = vec![0, 1, 2];
+        assert_eq!(
+            compute_gauss_with(&mut data, Strategy::Barrier),
+            Err(GaussError::Singular(1))
+        );
+    }
+
+    #[test]
+    fn dense_solvers_reject_sparse() {
+        use crate::channel::{compute_gauss_channel, Dispatch};
+        use crate::{actor, banded, dag, exact, inverse, parallel, pipeline, symmetric};
+        use crate::{compute_gauss_growth, compute_gauss_lu};
+
+        let mut data = new_data(16, 2);
+        init_sparse(&mut data, Reorder::Natural);
+        assert_eq!(parallel::compute_gauss_p(&mut data), Err(GaussError::Sparse));
+        assert_eq!(parallel::compute_gauss_barrier(&mut data), Err(GaussError::Sparse));
+        assert_eq!(pipeline::compute_gauss_pipelined(&mut data), Err(GaussError::Sparse));
+        assert_eq!(dag::compute_gauss_dag(&mut data, 4), Err(GaussError::Sparse));
+        assert_eq!(actor::compute_gauss_actor(&mut data, 4), Err(GaussError::Sparse));
+        assert_eq!(compute_gauss_channel(&mut data, Dispatch::default()), Err(GaussError::Sparse));
+        assert_eq!(banded::compute_gauss_banded(&mut data), Err(GaussError::Sparse));
+        assert_eq!(symmetric::solve_symmetric(&mut data), Err(GaussError::Sparse));
+        assert_eq!(compute_gauss_growth(&mut data), Err(GaussError::Sparse));
+        assert!(matches!(compute_gauss_lu(&mut data), Err(GaussError::Sparse)));
+        assert!(matches!(inverse::lu(&data), Err(GaussError::Sparse)));
+        assert_eq!(inverse::inverse(&data), Err(GaussError::Sparse));
+        assert_eq!(inverse::inverse_p(&data, 4), Err(GaussError::Sparse));
+        assert_eq!(inverse::determinant(&data), Err(GaussError::Sparse));
+        assert_eq!(exact::solve_data_exact(&data), Err(GaussError::Sparse));
+
+        // Nothing was touched, so the sparse solve still goes through.
+        compute_gauss_with(&mut data, Strategy::Pool).unwrap();
+        back_substitute(&mut data);
+        verify_sparse(&data);
+    }
+}
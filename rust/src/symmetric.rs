@@ -0,0 +1,393 @@
+use crate::error::GaussError;
+use crate::parallel::{compute_gauss_p, for_rows, new_pool, pool_for};
+use crate::{back_substitute, check_dense, compute_gauss, Data};
+use threadpool_crossbeam::ThreadPool;
+
+// How solve_symmetric got its answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Cholesky,
+    Ldlt,
+    Lu,
+}
+
+// Symmetric up to rounding: entries that should match may differ in the last
+// few bits.
+pub fn is_symmetric(matrix: &[Vec<f64>]) -> bool {
+    let n = matrix.len();
+    (0..n).all(|i| {
+        (0..i).all(|j| {
+            let (a, b) = (matrix[i][j], matrix[j][i]);
+            (a - b).abs() <= 1e-12 * a.abs().max(b.abs())
+        })
+    })
+}
+
+// a[i][j] -= sum over t of w[t][i - from] * v[t][j - from] for every row
+// from `from` on, split over the pool if there is one. Columns run up to the
+// diagonal, or to the end of the row if `full`.
+fn trailing_update(
+    pool: Option<&ThreadPool>,
+    rows: &mut Vec<Vec<f64>>,
+    from: usize,
+    w: Vec<Vec<f64>>,
+    v: Vec<Vec<f64>>,
+    full: bool,
+) -> Result<(), GaussError> {
+    for_rows(pool, rows, from, move |i, row| {
+        let end = if full { row.len() } else { i + 1 };
+        for (w, v) in w.iter().zip(&v) {
+            let wi = w[i - from];
+            for (x, vj) in row[from..end].iter_mut().zip(v) {
+                *x -= wi * vj;
+            }
+        }
+    })
+}
+
+// A = L L^T, L lower triangular.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cholesky {
+    pub l: Vec<Vec<f64>>,
+}
+
+impl Cholesky {
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let l = &self.l;
+        let n = l.len();
+        let mut x = b.to_vec();
+        for i in 0..n {
+            let s: f64 = l[i][..i].iter().zip(&x[..i]).map(|(a, y)| a * y).sum();
+            x[i] = (x[i] - s) / l[i][i];
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|j| l[j][i] * x[j]).sum();
+            x[i] = (x[i] - s) / l[i][i];
+        }
+        x
+    }
+}
+
+// Right-looking: each column is scaled by the square root of its diagonal,
+// then taken out of the lower triangle of the trailing matrix. A diagonal
+// that is not positive on the way means the matrix is not positive definite.
+fn factor_cholesky(matrix: &[Vec<f64>], pool: Option<&ThreadPool>) -> Result<Cholesky, GaussError> {
+    let n = matrix.len();
+    let mut rows = matrix.to_vec();
+    for k in 0..n {
+        let akk = rows[k][k];
+        if akk <= 0.0 || akk.is_nan() {
+            return Err(GaussError::NotPositiveDefinite(k));
+        }
+        let lkk = akk.sqrt();
+        rows[k][k] = lkk;
+        let col: Vec<f64> = rows[k + 1..]
+            .iter_mut()
+            .map(|row| {
+                row[k] /= lkk;
+                row[k]
+            })
+            .collect();
+        trailing_update(pool, &mut rows, k + 1, vec![col.clone()], vec![col], false)?;
+    }
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[i + 1..].iter_mut().for_each(|x| *x = 0.0);
+    }
+    Ok(Cholesky { l: rows })
+}
+
+pub fn cholesky(matrix: &[Vec<f64>]) -> Result<Cholesky, GaussError> {
+    factor_cholesky(matrix, None)
+}
+
+// cholesky with the trailing updates of every column done by num_threads
+// pool jobs.
+pub fn cholesky_p(matrix: &[Vec<f64>], num_threads: usize) -> Result<Cholesky, GaussError> {
+    let pool = new_pool(num_threads.max(1));
+    factor_cholesky(matrix, Some(&pool))
+}
+
+// P A P^T = L D L^T with L unit lower triangular and D block diagonal with
+// 1x1 and 2x2 blocks. off[k] is D[k + 1][k], nonzero exactly where a 2x2
+// block starts at k. perm[new] = old.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ldlt {
+    pub l: Vec<Vec<f64>>,
+    pub diag: Vec<f64>,
+    pub off: Vec<f64>,
+    pub perm: Vec<usize>,
+}
+
+impl Ldlt {
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let l = &self.l;
+        let n = l.len();
+        let mut y: Vec<f64> = self.perm.iter().map(|&k| b[k]).collect();
+        for i in 0..n {
+            let s: f64 = l[i][..i].iter().zip(&y[..i]).map(|(a, y)| a * y).sum();
+            y[i] -= s;
+        }
+        let mut i = 0;
+        while i < n {
+            if self.off[i] != 0.0 {
+                let (d00, d10, d11) = (self.diag[i], self.off[i], self.diag[i + 1]);
+                let det = d00 * d11 - d10 * d10;
+                let (y0, y1) = (y[i], y[i + 1]);
+                y[i] = (d11 * y0 - d10 * y1) / det;
+                y[i + 1] = (d00 * y1 - d10 * y0) / det;
+                i += 2;
+            } else {
+                y[i] /= self.diag[i];
+                i += 1;
+            }
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|j| l[j][i] * y[j]).sum();
+            y[i] -= s;
+        }
+        let mut x = vec![0.0; n];
+        for (i, &k) in self.perm.iter().enumerate() {
+            x[k] = y[i];
+        }
+        x
+    }
+}
+
+// Swaps unknowns p and q: rows and columns of what is left of the matrix,
+// the rows of L computed so far and the permutation.
+fn swap_sym(rows: &mut [Vec<f64>], l: &mut [Vec<f64>], perm: &mut [usize], p: usize, q: usize) {
+    if p == q {
+        return;
+    }
+    rows.swap(p, q);
+    for row in rows.iter_mut() {
+        row.swap(p, q);
+    }
+    l.swap(p, q);
+    perm.swap(p, q);
+}
+
+// Bunch-Kaufman partial pivoting, as in LAPACK's dsytf2: a 1x1 pivot when the
+// diagonal is large enough next to its column, otherwise the largest entry
+// of the column is brought next to it for a 2x2 pivot, or onto the diagonal
+// if that is safe. The whole symmetric matrix is kept so rows and columns can
+// be swapped as they are.
+fn factor_ldlt(matrix: &[Vec<f64>], pool: Option<&ThreadPool>) -> Result<Ldlt, GaussError> {
+    let n = matrix.len();
+    let alpha = (1.0 + 17f64.sqrt()) / 8.0;
+    let mut rows = matrix.to_vec();
+    let mut l = vec![vec![0.0; n]; n];
+    let mut diag = vec![0.0; n];
+    let mut off = vec![0.0; n];
+    let mut perm: Vec<usize> = (0..n).collect();
+
+    let mut k = 0;
+    while k < n {
+        let absakk = rows[k][k].abs();
+        let (r, colmax) = (k + 1..n)
+            .map(|i| (i, rows[i][k].abs()))
+            .fold((k, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+        if absakk.max(colmax) == 0.0 {
+            return Err(GaussError::Singular(k));
+        }
+        let size = if absakk >= alpha * colmax {
+            1
+        } else {
+            let rowmax = (k..n)
+                .filter(|&j| j != r)
+                .map(|j| rows[r][j].abs())
+                .fold(0.0, f64::max);
+            if absakk * rowmax >= alpha * colmax * colmax {
+                1
+            } else if rows[r][r].abs() >= alpha * rowmax {
+                swap_sym(&mut rows, &mut l, &mut perm, k, r);
+                1
+            } else {
+                swap_sym(&mut rows, &mut l, &mut perm, k + 1, r);
+                2
+            }
+        };
+
+        if size == 1 {
+            let d = rows[k][k];
+            let w: Vec<f64> = (k + 1..n).map(|i| rows[i][k]).collect();
+            let v: Vec<f64> = w.iter().map(|x| x / d).collect();
+            for (row, &x) in l[k + 1..].iter_mut().zip(&v) {
+                row[k] = x;
+            }
+            diag[k] = d;
+            trailing_update(pool, &mut rows, k + 1, vec![w], vec![v], true)?;
+        } else {
+            let (d00, d10, d11) = (rows[k][k], rows[k + 1][k], rows[k + 1][k + 1]);
+            let det = d00 * d11 - d10 * d10;
+            if det == 0.0 {
+                return Err(GaussError::Singular(k));
+            }
+            let w0: Vec<f64> = (k + 2..n).map(|i| rows[i][k]).collect();
+            let w1: Vec<f64> = (k + 2..n).map(|i| rows[i][k + 1]).collect();
+            let v0: Vec<f64> = w0.iter().zip(&w1).map(|(a, b)| (a * d11 - b * d10) / det).collect();
+            let v1: Vec<f64> = w0.iter().zip(&w1).map(|(a, b)| (b * d00 - a * d10) / det).collect();
+            for (row, (&x, &y)) in l[k + 2..].iter_mut().zip(v0.iter().zip(&v1)) {
+                row[k] = x;
+                row[k + 1] = y;
+            }
+            diag[k] = d00;
+            diag[k + 1] = d11;
+            off[k] = d10;
+            trailing_update(pool, &mut rows, k + 2, vec![w0, w1], vec![v0, v1], true)?;
+        }
+        k += size;
+    }
+
+    for (i, row) in l.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    Ok(Ldlt { l, diag, off, perm })
+}
+
+pub fn ldlt(matrix: &[Vec<f64>]) -> Result<Ldlt, GaussError> {
+    factor_ldlt(matrix, None)
+}
+
+pub fn ldlt_p(matrix: &[Vec<f64>], num_threads: usize) -> Result<Ldlt, GaussError> {
+    let pool = new_pool(num_threads.max(1));
+    factor_ldlt(matrix, Some(&pool))
+}
+
+// Solves data's system into data.c with the cheapest factorization that
+// applies: Cholesky if the matrix is symmetric positive definite, LDL^T if
+// it is only symmetric, compute_gauss otherwise. The pool-parallel variants
+// are used when data.num_threads > 0. Only the LU fallback changes
+// data.matrix and data.b, as compute_gauss does.
+pub fn solve_symmetric(data: &mut Data) -> Result<Method, GaussError> {
+    check_dense(data)?;
+    let parallel = data.num_threads > 0;
+    if !is_symmetric(&data.matrix) {
+        if parallel {
+            compute_gauss_p(data)?;
+        } else {
+            compute_gauss(data);
+        }
+        back_substitute(data);
+        return Ok(Method::Lu);
+    }
+
+    let pool = if parallel { Some(pool_for(data).build()) } else { None };
+    let (method, x) = match factor_cholesky(&data.matrix, pool.as_ref()) {
+        Ok(f) => (Method::Cholesky, f.solve(&data.b)),
+        Err(GaussError::NotPositiveDefinite(_)) => {
+            let f = factor_ldlt(&data.matrix, pool.as_ref())?;
+            (Method::Ldlt, f.solve(&data.b))
+        }
+        Err(e) => return Err(e),
+    };
+    // The last update may have been dropped by a cancel.
+    if data.cancel.is_cancelled() {
+        return Err(GaussError::Cancelled);
+    }
+    data.c = x;
+    Ok(method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::new_data;
+    use crate::init;
+
+    // Symmetric with a zero diagonal, so it is indefinite and Bunch-Kaufman
+    // has to take 2x2 pivots.
+    fn indefinite(nsize: usize) -> Vec<Vec<f64>> {
+        (0..nsize)
+            .map(|i| {
+                (0..nsize)
+                    .map(|j| match (i.max(j), i.min(j)) {
+                        (hi, lo) if hi == lo => 0.0,
+                        (hi, lo) => ((hi * 7 + lo * 3) % 11) as f64 - 5.0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn residual(matrix: &[Vec<f64>], x: &[f64], b: &[f64]) -> f64 {
+        matrix
+            .iter()
+            .zip(b)
+            .map(|(row, b)| (row.iter().zip(x).map(|(a, x)| a * x).sum::<f64>() - b).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn cholesky_of_init() {
+        let mut data = new_data(30, 0);
+        init(&mut data);
+        assert!(is_symmetric(&data.matrix));
+        let f = cholesky(&data.matrix).unwrap();
+        assert_eq!(cholesky_p(&data.matrix, 3).unwrap(), f);
+        for i in 0..30 {
+            for j in 0..30 {
+                let llt: f64 = (0..30).map(|k| f.l[i][k] * f.l[j][k]).sum();
+                assert!((llt - data.matrix[i][j]).abs() < 1e-9);
+            }
+        }
+        assert!(residual(&data.matrix, &f.solve(&data.b), &data.b) < 1e-9);
+    }
+
+    #[test]
+    fn ldlt_of_indefinite() {
+        let matrix = indefinite(25);
+        assert_eq!(cholesky(&matrix), Err(GaussError::NotPositiveDefinite(0)));
+        let f = ldlt(&matrix).unwrap();
+        assert!(f.off.iter().any(|&x| x != 0.0));
+        assert_eq!(ldlt_p(&matrix, 3).unwrap(), f);
+        let b: Vec<f64> = (0..25).map(|i| i as f64).collect();
+        assert!(residual(&matrix, &f.solve(&b), &b) < 1e-9);
+
+        let swap = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        let f = ldlt(&swap).unwrap();
+        assert_eq!(f.off, vec![1.0, 0.0]);
+        assert_eq!(f.solve(&[2.0, 3.0]), vec![3.0, 2.0]);
+    }
+
+    #[test]
+    fn solve_symmetric_falls_back() {
+        for &num_threads in &[0, 2] {
+            let mut data = new_data(20, num_threads);
+            init(&mut data);
+            let matrix = data.matrix.clone();
+            assert_eq!(solve_symmetric(&mut data), Ok(Method::Cholesky));
+            assert!(residual(&matrix, &data.c, &data.b) < 1e-9);
+
+            let mut data = new_data(20, num_threads);
+            data.matrix = indefinite(20);
+            data.b = vec![1.0; 20];
+            assert_eq!(solve_symmetric(&mut data), Ok(Method::Ldlt));
+            assert!(residual(&indefinite(20), &data.c, &data.b) < 1e-9);
+
+            let mut data = new_data(20, num_threads);
+            init(&mut data);
+            data.matrix[0][19] += 1.0;
+            let matrix = data.matrix.clone();
+            let b = data.b.clone();
+            assert_eq!(solve_symmetric(&mut data), Ok(Method::Lu));
+            assert!(residual(&matrix, &data.c, &b) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn solve_symmetric_cancelled() {
+        let mut data = new_data(20, 2);
+        init(&mut data);
+        data.cancel.cancel();
+        assert_eq!(solve_symmetric(&mut data), Err(GaussError::Cancelled));
+    }
+
+    #[test]
+    fn symmetric_singular() {
+        let matrix = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        assert_eq!(cholesky(&matrix), Err(GaussError::NotPositiveDefinite(1)));
+        assert_eq!(ldlt(&matrix), Err(GaussError::Singular(1)));
+    }
+}
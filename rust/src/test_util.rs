@@ -0,0 +1,36 @@
+use crate::Data;
+use threadpool_crossbeam::cancel::CancelToken;
+
+// An empty system of nsize unknowns for init or init_sparse to fill in.
+pub fn new_data(nsize: usize, num_threads: usize) -> Data {
+    Data {
+        nsize,
+        matrix: Vec::with_capacity(nsize),
+        b: Vec::with_capacity(nsize),
+        c: Vec::with_capacity(nsize),
+        v: Vec::with_capacity(nsize),
+        swap: Vec::with_capacity(nsize),
+        num_threads,
+        affinity: None,
+        cancel: CancelToken::new(),
+        sparse: None,
+    }
+}
+
+// Knuth's 64-bit linear congruential generator, so the random test inputs
+// are the same on every run. The low bits are poor; take them from the top.
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Lcg {
+        Lcg(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0
+    }
+}
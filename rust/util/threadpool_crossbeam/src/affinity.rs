@@ -0,0 +1,210 @@
+//! Pinning pool threads to CPUs.
+//!
+//! An [`Affinity`] is turned into a list of CPUs when the pool is built; the k-th worker thread
+//! the pool starts is pinned to entry `k % len` of that list. Threads started to replace a
+//! panicked worker, or by [`ThreadPool::set_num_threads`], keep counting from there.
+//!
+//! Pinning uses `sched_setaffinity` and only takes effect on Linux. Elsewhere the threads are
+//! left to the scheduler.
+//!
+//! ```
+//! use threadpool::affinity::Affinity;
+//!
+//! let pool = threadpool::Builder::new()
+//!     .num_threads(2)
+//!     .core_affinity(Affinity::Compact)
+//!     .build();
+//! pool.execute(|| println!("pinned"));
+//! pool.join().unwrap();
+//! ```
+//!
+//! [`Affinity`]: enum.Affinity.html
+//! [`ThreadPool::set_num_threads`]: ../struct.ThreadPool.html#method.set_num_threads
+
+#[cfg(target_os = "linux")]
+use libc;
+use num_cpus;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// Which CPUs the worker threads of a pool run on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Affinity {
+    /// Fill one core after another, hyperthreads of a core before the next core.
+    Compact,
+    /// Spread the workers out: one per socket in turn, then one per core, and only then the
+    /// second hyperthread of each core.
+    Scatter,
+    /// Exactly these CPUs, in this order.
+    Cores(Vec<usize>),
+}
+
+impl Affinity {
+    /// The CPUs the workers are pinned to, in the order they are handed out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a CPU of [`Affinity::Cores`] is not one the calling thread may run on, or if
+    /// the list is empty.
+    ///
+    /// [`Affinity::Cores`]: #variant.Cores
+    pub fn cpus(&self) -> Vec<usize> {
+        let allowed = allowed();
+        match *self {
+            Affinity::Compact => {
+                let mut cpus: Vec<Cpu> = allowed.into_iter().map(Cpu::read).collect();
+                cpus.sort_by_key(|c| (c.package, c.core, c.id));
+                cpus.into_iter().map(|c| c.id).collect()
+            }
+            Affinity::Scatter => {
+                let mut cpus: Vec<Cpu> = allowed.into_iter().map(Cpu::read).collect();
+                cpus.sort_by_key(|c| (c.package, c.core, c.id));
+                // Rank every CPU among the threads of its core and the core among the cores of
+                // its package, then take first threads of first cores across all packages.
+                let mut ranked = Vec::with_capacity(cpus.len());
+                let (mut thread, mut core) = (0, 0);
+                for (i, c) in cpus.iter().enumerate() {
+                    if i > 0 && cpus[i - 1].package != c.package {
+                        core = 0;
+                        thread = 0;
+                    } else if i > 0 && cpus[i - 1].core != c.core {
+                        core += 1;
+                        thread = 0;
+                    } else if i > 0 {
+                        thread += 1;
+                    }
+                    ranked.push(((thread, core, c.package), c.id));
+                }
+                ranked.sort();
+                ranked.into_iter().map(|(_, id)| id).collect()
+            }
+            Affinity::Cores(ref cores) => {
+                assert!(!cores.is_empty(), "no cores to pin to");
+                for core in cores {
+                    assert!(allowed.contains(core), "core {} is not available", core);
+                }
+                cores.clone()
+            }
+        }
+    }
+}
+
+impl FromStr for Affinity {
+    type Err = String;
+
+    /// Parses `compact`, `scatter` or a comma-separated list of CPUs such as `0,2,4`.
+    fn from_str(s: &str) -> Result<Affinity, String> {
+        match s {
+            "compact" => Ok(Affinity::Compact),
+            "scatter" => Ok(Affinity::Scatter),
+            _ => s
+                .split(',')
+                .map(|c| c.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map(Affinity::Cores)
+                .map_err(|_| format!("unknown affinity {}", s)),
+        }
+    }
+}
+
+// Where a CPU sits in the machine, from sysfs. Without topology information
+// every CPU counts as a core of its own.
+struct Cpu {
+    id: usize,
+    package: usize,
+    core: usize,
+}
+
+impl Cpu {
+    fn read(id: usize) -> Cpu {
+        let topology = |name: &str| {
+            let path = format!("/sys/devices/system/cpu/cpu{}/topology/{}", id, name);
+            fs::read_to_string(path)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+        };
+        Cpu {
+            id,
+            package: topology("physical_package_id").unwrap_or(0),
+            core: topology("core_id").unwrap_or(id),
+        }
+    }
+}
+
+/// The CPUs the calling thread may run on.
+#[cfg(target_os = "linux")]
+pub fn allowed() -> Vec<usize> {
+    use std::mem;
+
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return (0..num_cpus::get()).collect();
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect()
+    }
+}
+
+/// The CPUs the calling thread may run on.
+#[cfg(not(target_os = "linux"))]
+pub fn allowed() -> Vec<usize> {
+    (0..num_cpus::get()).collect()
+}
+
+/// Pins the calling thread to `cpu`.
+#[cfg(target_os = "linux")]
+pub fn pin(cpu: usize) -> io::Result<()> {
+    use std::mem;
+
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such cpu"));
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Pins the calling thread to `cpu`.
+#[cfg(not(target_os = "linux"))]
+pub fn pin(cpu: usize) -> io::Result<()> {
+    let _ = cpu;
+    Err(io::Error::new(io::ErrorKind::Other, "thread affinity is only supported on Linux"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{allowed, Affinity};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("compact".parse(), Ok(Affinity::Compact));
+        assert_eq!("scatter".parse(), Ok(Affinity::Scatter));
+        assert_eq!("0, 2,4".parse(), Ok(Affinity::Cores(vec![0, 2, 4])));
+        assert!("packed".parse::<Affinity>().is_err());
+    }
+
+    #[test]
+    fn test_strategies_use_every_allowed_cpu() {
+        let mut all = allowed();
+        all.sort();
+        for affinity in &[Affinity::Compact, Affinity::Scatter] {
+            let mut cpus = affinity.cpus();
+            cpus.sort();
+            assert_eq!(cpus, all);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unavailable_core_panics() {
+        Affinity::Cores(vec![usize::MAX]).cpus();
+    }
+}
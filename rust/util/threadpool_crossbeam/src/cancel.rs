@@ -0,0 +1,65 @@
+//! Cooperative cancellation.
+//!
+//! A [`CancelToken`] is a flag shared by all of its clones. Once any clone is cancelled it stays
+//! cancelled; long jobs poll [`is_cancelled`] and return early. Every pool has one, handed out
+//! by [`ThreadPool::cancel_token`] and cancelled by [`ThreadPool::shutdown_now`].
+//!
+//! ```
+//! use threadpool::ThreadPool;
+//!
+//! let pool = ThreadPool::new(2);
+//! let token = pool.cancel_token();
+//! pool.execute(move || {
+//!     while !token.is_cancelled() {
+//!         // one slice of work
+//!         # break;
+//!     }
+//! });
+//! pool.shutdown_now();
+//! ```
+//!
+//! [`CancelToken`]: struct.CancelToken.html
+//! [`is_cancelled`]: struct.CancelToken.html#method.is_cancelled
+//! [`ThreadPool::cancel_token`]: ../struct.ThreadPool.html#method.cancel_token
+//! [`ThreadPool::shutdown_now`]: ../struct.ThreadPool.html#method.shutdown_now
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that tells jobs to stop.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels this token and every clone of it. Returns whether it was already cancelled.
+    pub fn cancel(&self) -> bool {
+        self.0.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CancelToken;
+    use std::thread;
+
+    #[test]
+    fn test_cancel_reaches_clones() {
+        let token = CancelToken::new();
+        let polled = token.clone();
+        let worker = thread::spawn(move || while !polled.is_cancelled() {
+            thread::yield_now();
+        });
+        assert!(!token.is_cancelled());
+        assert!(!token.cancel());
+        worker.join().unwrap();
+        assert!(token.cancel());
+    }
+}
@@ -0,0 +1,382 @@
+//! Interchangeable channel implementations.
+//!
+//! A [`Backend`] hands out sender/receiver pairs of one channel flavour. The
+//! pool's job queue is generic over it, and so is anything else that wants to
+//! compare channel implementations under the same workload.
+//!
+//! Closing follows the `std::sync::mpsc` model: a channel is closed once every
+//! sender has been closed or dropped, after which receivers drain what is
+//! left and then see a disconnect. Sends fail once every receiver is gone.
+//!
+//! ```
+//! use threadpool::channel::{Backend, Receiver, RingBuf, Sender};
+//!
+//! let (tx, rx) = RingBuf::bounded(2);
+//! tx.send(1).unwrap();
+//! tx.send(2).unwrap();
+//! tx.close();
+//! assert_eq!(rx.recv(), Ok(1));
+//! assert_eq!(rx.try_recv(), Ok(2));
+//! assert!(rx.recv().is_err());
+//! ```
+
+pub use lockfree::LockFree;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+pub use std::sync::mpsc::{RecvError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The sending half of a backend's channel.
+pub trait Sender<T>: Clone + Send {
+    /// Sends `x`, blocking while a bounded channel is full.
+    fn send(&self, x: T) -> Result<(), SendError<T>>;
+
+    /// Drops this handle. The channel closes when the last sender does.
+    fn close(self) {}
+}
+
+/// The receiving half of a backend's channel. Receivers can be cloned to
+/// share one channel between several consumers.
+pub trait Receiver<T>: Clone + Send {
+    /// Blocks until an element arrives or the channel is closed and empty.
+    fn recv(&self) -> Result<T, RecvError>;
+
+    fn try_recv(&self) -> Result<T, TryRecvError>;
+}
+
+/// A channel implementation.
+pub trait Backend: 'static {
+    type Sender<T: Send + 'static>: Sender<T>;
+    type Receiver<T: Send + 'static>: Receiver<T>;
+
+    /// A channel holding at most `cap` elements. A capacity of 0 makes a
+    /// rendezvous channel: a send waits until its element has been received.
+    fn bounded<T: Send + 'static>(cap: usize) -> (Self::Sender<T>, Self::Receiver<T>);
+
+    fn unbounded<T: Send + 'static>() -> (Self::Sender<T>, Self::Receiver<T>);
+}
+
+/// `std::sync::mpsc`. Its receiver is single-consumer, so clones share it
+/// behind a mutex.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Std;
+
+pub enum StdSender<T> {
+    Unbounded(mpsc::Sender<T>),
+    Bounded(mpsc::SyncSender<T>),
+}
+
+impl<T> Clone for StdSender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            StdSender::Unbounded(tx) => StdSender::Unbounded(tx.clone()),
+            StdSender::Bounded(tx) => StdSender::Bounded(tx.clone()),
+        }
+    }
+}
+
+impl<T: Send> Sender<T> for StdSender<T> {
+    fn send(&self, x: T) -> Result<(), SendError<T>> {
+        match self {
+            StdSender::Unbounded(tx) => tx.send(x),
+            StdSender::Bounded(tx) => tx.send(x),
+        }
+    }
+}
+
+pub struct StdReceiver<T>(Arc<Mutex<mpsc::Receiver<T>>>);
+
+impl<T> Clone for StdReceiver<T> {
+    fn clone(&self) -> Self {
+        StdReceiver(self.0.clone())
+    }
+}
+
+impl<T: Send> Receiver<T> for StdReceiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        self.0.lock().unwrap().recv()
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.0.lock().unwrap().try_recv()
+    }
+}
+
+impl Backend for Std {
+    type Sender<T: Send + 'static> = StdSender<T>;
+    type Receiver<T: Send + 'static> = StdReceiver<T>;
+
+    fn bounded<T: Send + 'static>(cap: usize) -> (StdSender<T>, StdReceiver<T>) {
+        let (tx, rx) = mpsc::sync_channel(cap);
+        (StdSender::Bounded(tx), StdReceiver(Arc::new(Mutex::new(rx))))
+    }
+
+    fn unbounded<T: Send + 'static>() -> (StdSender<T>, StdReceiver<T>) {
+        let (tx, rx) = mpsc::channel();
+        (StdSender::Unbounded(tx), StdReceiver(Arc::new(Mutex::new(rx))))
+    }
+}
+
+/// `crossbeam_channel`, the pool's default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Crossbeam;
+
+impl<T: Send> Sender<T> for crossbeam_channel::Sender<T> {
+    fn send(&self, x: T) -> Result<(), SendError<T>> {
+        crossbeam_channel::Sender::send(self, x).map_err(|e| SendError(e.0))
+    }
+}
+
+impl<T: Send> Receiver<T> for crossbeam_channel::Receiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        crossbeam_channel::Receiver::recv(self).map_err(|_| RecvError)
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        crossbeam_channel::Receiver::try_recv(self).map_err(|e| match e {
+            crossbeam_channel::TryRecvError::Empty => TryRecvError::Empty,
+            crossbeam_channel::TryRecvError::Disconnected => TryRecvError::Disconnected,
+        })
+    }
+}
+
+impl Backend for Crossbeam {
+    type Sender<T: Send + 'static> = crossbeam_channel::Sender<T>;
+    type Receiver<T: Send + 'static> = crossbeam_channel::Receiver<T>;
+
+    fn bounded<T: Send + 'static>(
+        cap: usize,
+    ) -> (crossbeam_channel::Sender<T>, crossbeam_channel::Receiver<T>) {
+        crossbeam_channel::bounded(cap)
+    }
+
+    fn unbounded<T: Send + 'static>() -> (crossbeam_channel::Sender<T>, crossbeam_channel::Receiver<T>)
+    {
+        crossbeam_channel::unbounded()
+    }
+}
+
+/// A ring buffer behind one mutex, with a condition variable for each
+/// direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RingBuf;
+
+struct Ring<T> {
+    buf: VecDeque<T>,
+    // None for an unbounded channel.
+    cap: Option<usize>,
+    senders: usize,
+    receivers: usize,
+    // Elements sent and received so far; a rendezvous send waits for its
+    // ticket to be received.
+    sent: u64,
+    received: u64,
+}
+
+struct RingShared<T> {
+    ring: Mutex<Ring<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+pub struct RingSender<T>(Arc<RingShared<T>>);
+pub struct RingReceiver<T>(Arc<RingShared<T>>);
+
+impl<T> Clone for RingSender<T> {
+    fn clone(&self) -> Self {
+        self.0.ring.lock().unwrap().senders += 1;
+        RingSender(self.0.clone())
+    }
+}
+
+impl<T> Drop for RingSender<T> {
+    fn drop(&mut self) {
+        let mut ring = self.0.ring.lock().unwrap();
+        ring.senders -= 1;
+        if ring.senders == 0 {
+            self.0.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Clone for RingReceiver<T> {
+    fn clone(&self) -> Self {
+        self.0.ring.lock().unwrap().receivers += 1;
+        RingReceiver(self.0.clone())
+    }
+}
+
+impl<T> Drop for RingReceiver<T> {
+    fn drop(&mut self) {
+        let mut ring = self.0.ring.lock().unwrap();
+        ring.receivers -= 1;
+        if ring.receivers == 0 {
+            self.0.not_full.notify_all();
+        }
+    }
+}
+
+impl<T: Send> Sender<T> for RingSender<T> {
+    fn send(&self, x: T) -> Result<(), SendError<T>> {
+        let shared = &*self.0;
+        let mut ring = shared.ring.lock().unwrap();
+        // A rendezvous channel still needs a slot to hand the element over in.
+        let slots = ring.cap.map(|cap| cap.max(1));
+        while ring.receivers > 0 && slots.is_some_and(|n| ring.buf.len() >= n) {
+            ring = shared.not_full.wait(ring).unwrap();
+        }
+        if ring.receivers == 0 {
+            return Err(SendError(x));
+        }
+        ring.buf.push_back(x);
+        ring.sent += 1;
+        shared.not_empty.notify_one();
+
+        if ring.cap == Some(0) {
+            let ticket = ring.sent;
+            while ring.receivers > 0 && ring.received < ticket {
+                ring = shared.not_full.wait(ring).unwrap();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Send> Receiver<T> for RingReceiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        let shared = &*self.0;
+        let mut ring = shared.ring.lock().unwrap();
+        loop {
+            if let Some(x) = ring.buf.pop_front() {
+                ring.received += 1;
+                shared.not_full.notify_all();
+                return Ok(x);
+            }
+            if ring.senders == 0 {
+                return Err(RecvError);
+            }
+            ring = shared.not_empty.wait(ring).unwrap();
+        }
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = &*self.0;
+        let mut ring = shared.ring.lock().unwrap();
+        match ring.buf.pop_front() {
+            Some(x) => {
+                ring.received += 1;
+                shared.not_full.notify_all();
+                Ok(x)
+            }
+            None if ring.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+fn ring<T>(cap: Option<usize>) -> (RingSender<T>, RingReceiver<T>) {
+    let shared = Arc::new(RingShared {
+        ring: Mutex::new(Ring {
+            buf: VecDeque::with_capacity(cap.unwrap_or(0).max(1)),
+            cap,
+            senders: 1,
+            receivers: 1,
+            sent: 0,
+            received: 0,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (RingSender(shared.clone()), RingReceiver(shared))
+}
+
+impl Backend for RingBuf {
+    type Sender<T: Send + 'static> = RingSender<T>;
+    type Receiver<T: Send + 'static> = RingReceiver<T>;
+
+    fn bounded<T: Send + 'static>(cap: usize) -> (RingSender<T>, RingReceiver<T>) {
+        ring(Some(cap))
+    }
+
+    fn unbounded<T: Send + 'static>() -> (RingSender<T>, RingReceiver<T>) {
+        ring(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Backend, Crossbeam, LockFree, Receiver, RingBuf, Sender, Std, TryRecvError};
+    use std::thread;
+
+    fn round_trip<B: Backend>() {
+        let (tx, rx) = B::bounded::<usize>(4);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for x in 0..500 {
+                        tx.send(p * 500 + x).unwrap();
+                    }
+                })
+            })
+            .collect();
+        tx.close();
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Ok(x) = rx.recv() {
+                        got.push(x);
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        let mut got: Vec<usize> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        got.sort();
+        assert_eq!(got, (0..2000).collect::<Vec<_>>());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    fn rendezvous<B: Backend>() {
+        let (tx, rx) = B::bounded::<usize>(0);
+        let consumer = thread::spawn(move || (0..100).map(|_| rx.recv().unwrap()).sum::<usize>());
+        for x in 0..100 {
+            tx.send(x).unwrap();
+        }
+        assert_eq!(consumer.join().unwrap(), 4950);
+        assert!(tx.send(0).is_err());
+    }
+
+    #[test]
+    fn test_backends() {
+        round_trip::<Std>();
+        round_trip::<Crossbeam>();
+        round_trip::<RingBuf>();
+        round_trip::<LockFree>();
+        rendezvous::<Std>();
+        rendezvous::<Crossbeam>();
+        rendezvous::<RingBuf>();
+        rendezvous::<LockFree>();
+    }
+
+    #[test]
+    fn test_ring_unbounded() {
+        let (tx, rx) = RingBuf::unbounded();
+        for x in 0..1000 {
+            tx.send(x).unwrap();
+        }
+        drop(rx.clone());
+        assert_eq!(rx.try_recv(), Ok(0));
+        drop(tx);
+        assert_eq!((1..1000).map(|_| rx.recv().unwrap()).sum::<i32>(), 499_500);
+        assert_eq!(rx.recv(), Err(super::RecvError));
+    }
+}
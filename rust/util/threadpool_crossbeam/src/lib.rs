@@ -81,13 +81,34 @@
 
 extern crate num_cpus;
 extern crate crossbeam_channel;
-
+#[cfg(target_os = "linux")]
+extern crate libc;
+
+use affinity::Affinity;
+use cancel::CancelToken;
+use panic::JobPanic;
+use scaling::{AutoScale, Monitor};
+use std::cell::Cell;
+use std::cmp;
+use std::collections::BinaryHeap;
 use std::fmt;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::mem;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 //use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Condvar, Mutex, Weak};
 use std::thread;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::time::{Duration, Instant};
+use channel::{Backend, Crossbeam, Receiver, Sender};
+
+pub mod affinity;
+pub mod cancel;
+pub mod channel;
+pub mod lockfree;
+pub mod panic;
+pub mod scaling;
+pub mod stats;
+pub mod trace;
 
 trait FnBox {
     fn call_box(self: Box<Self>);
@@ -101,13 +122,83 @@ impl<F: FnOnce()> FnBox for F {
 
 type Thunk<'a> = Box<dyn FnBox + Send + 'a>;
 
-struct Sentinel<'a> {
-    shared_data: &'a Arc<ThreadPoolSharedData>,
+// A job on its way through the queue, stamped when it was queued.
+struct Job {
+    thunk: Thunk<'static>,
+    queued: stats::Stamp,
+}
+
+// What goes through the queue: a job, word that one more job is waiting in
+// the priority queue, an order for one worker to exit, or for one worker to
+// exit if the pool has more than it should.
+enum Message {
+    Job(Job),
+    Prioritized,
+    Stop,
+    Retire,
+}
+
+type JobSender<C> = stats::CountedSender<<C as Backend>::Sender<Message>>;
+type JobReceiver<C> = stats::CountedReceiver<<C as Backend>::Receiver<Message>>;
+
+// A job in the priority queue. Higher priorities come out first, and equal
+// priorities in the order they went in.
+struct Prioritized {
+    priority: i32,
+    seq: u64,
+    job: Job,
+}
+
+impl PartialEq for Prioritized {
+    fn eq(&self, other: &Prioritized) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Prioritized {}
+
+impl PartialOrd for Prioritized {
+    fn partial_cmp(&self, other: &Prioritized) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prioritized {
+    fn cmp(&self, other: &Prioritized) -> cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct PriorityQueue {
+    jobs: BinaryHeap<Prioritized>,
+    seq: u64,
+}
+
+impl PriorityQueue {
+    fn push(&mut self, priority: i32, job: Job) {
+        self.seq += 1;
+        self.jobs.push(Prioritized {
+            priority,
+            seq: self.seq,
+            job,
+        });
+    }
+
+    fn pop(&mut self) -> Option<Job> {
+        self.jobs.pop().map(|p| p.job)
+    }
+}
+
+struct Sentinel<'a, C: Backend> {
+    shared_data: &'a Arc<ThreadPoolSharedData<C>>,
     active: bool,
 }
 
-impl<'a> Sentinel<'a> {
-    fn new(shared_data: &'a Arc<ThreadPoolSharedData>) -> Sentinel<'a> {
+impl<'a, C: Backend> Sentinel<'a, C> {
+    fn new(shared_data: &'a Arc<ThreadPoolSharedData<C>>) -> Sentinel<'a, C> {
         Sentinel {
             shared_data: shared_data,
             active: true,
@@ -120,9 +211,10 @@ impl<'a> Sentinel<'a> {
     }
 }
 
-impl<'a> Drop for Sentinel<'a> {
+impl<'a, C: Backend> Drop for Sentinel<'a, C> {
     fn drop(&mut self) {
         if self.active {
+            self.shared_data.thread_count.fetch_sub(1, Ordering::SeqCst);
             self.shared_data.active_count.fetch_sub(1, Ordering::SeqCst);
             if thread::panicking() {
                 self.shared_data.panic_count.fetch_add(1, Ordering::SeqCst);
@@ -136,15 +228,24 @@ impl<'a> Drop for Sentinel<'a> {
 /// [`ThreadPool`] factory, which can be used in order to configure the properties of the
 /// [`ThreadPool`].
 ///
-/// The three configuration options available:
+/// The eight configuration options available:
 ///
 /// * `num_threads`: maximum number of threads that will be alive at any given moment by the built
 ///   [`ThreadPool`]
 /// * `thread_name`: thread name for each of the threads spawned by the built [`ThreadPool`]
 /// * `thread_stack_size`: stack size (in bytes) for each of the threads spawned by the built
 ///   [`ThreadPool`]
+/// * `core_affinity`: which CPUs the threads spawned by the built [`ThreadPool`] are pinned to
+/// * `priority_queue`: whether jobs of the built [`ThreadPool`] are taken by priority rather than
+///   in the order they were queued
+/// * `capture_panics`: whether panics in jobs of the built [`ThreadPool`] are caught and returned
+///   by [`ThreadPool::join`]
+/// * `fail_fast`: whether the first panic in a job of the built [`ThreadPool`] drops the jobs still
+///   queued
+/// * `auto_scale`: how the built [`ThreadPool`] adds and retires threads with its load
 ///
 /// [`ThreadPool`]: struct.ThreadPool.html
+/// [`ThreadPool::join`]: struct.ThreadPool.html#method.join
 ///
 /// # Examples
 ///
@@ -162,6 +263,12 @@ pub struct Builder {
     num_threads: Option<usize>,
     thread_name: Option<String>,
     thread_stack_size: Option<usize>,
+    core_affinity: Option<Affinity>,
+    priority_queue: bool,
+    capture_panics: bool,
+    fail_fast: bool,
+    auto_scale: Option<AutoScale>,
+    cancel_token: Option<CancelToken>,
 }
 
 impl Builder {
@@ -179,6 +286,12 @@ impl Builder {
             num_threads: None,
             thread_name: None,
             thread_stack_size: None,
+            core_affinity: None,
+            priority_queue: false,
+            capture_panics: false,
+            fail_fast: false,
+            auto_scale: None,
+            cancel_token: None,
         }
     }
 
@@ -268,6 +381,154 @@ impl Builder {
         self
     }
 
+    /// Pin each of the threads spawned by the built [`ThreadPool`] to a CPU picked by `affinity`.
+    /// If not specified, threads may run on any CPU. Pinning only takes effect on Linux.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    ///
+    /// # Panics
+    ///
+    /// Building the pool will panic if `affinity` lists a CPU this process may not run on.
+    ///
+    /// # Examples
+    ///
+    /// Pin all four threads to the first CPU:
+    ///
+    /// ```
+    /// use threadpool::affinity::Affinity;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(4)
+    ///     .core_affinity(Affinity::Cores(vec![0]))
+    ///     .build();
+    /// ```
+    pub fn core_affinity(mut self, affinity: Affinity) -> Builder {
+        self.core_affinity = Some(affinity);
+        self
+    }
+
+    /// Queue the jobs of the built [`ThreadPool`] by priority, so that a job passed to
+    /// [`ThreadPool::execute_with_priority`] runs before every waiting job of lower priority.
+    /// Plain [`ThreadPool::execute`] queues at priority 0. If not specified, jobs run in the
+    /// order they were queued and priorities are ignored.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::execute`]: struct.ThreadPool.html#method.execute
+    /// [`ThreadPool::execute_with_priority`]: struct.ThreadPool.html#method.execute_with_priority
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(2)
+    ///     .priority_queue(true)
+    ///     .build();
+    /// pool.execute(|| println!("bulk"));
+    /// pool.execute_with_priority(1, || println!("urgent"));
+    /// pool.join().unwrap();
+    /// ```
+    pub fn priority_queue(mut self, enabled: bool) -> Builder {
+        self.priority_queue = enabled;
+        self
+    }
+
+    /// Catch panics in the jobs of the built [`ThreadPool`] and keep their payloads until
+    /// [`ThreadPool::join`] returns them. The worker-thread carries on with the next job. If not
+    /// specified, a panicking job takes its worker-thread down, the pool starts a new one and
+    /// only [`ThreadPool::panic_count`] records it.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::join`]: struct.ThreadPool.html#method.join
+    /// [`ThreadPool::panic_count`]: struct.ThreadPool.html#method.panic_count
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(2)
+    ///     .capture_panics(true)
+    ///     .build();
+    /// pool.execute(|| panic!("boom"));
+    /// assert_eq!(pool.join().unwrap_err()[0].message(), "boom");
+    /// ```
+    pub fn capture_panics(mut self, enabled: bool) -> Builder {
+        self.capture_panics = enabled;
+        self
+    }
+
+    /// Cancel the built [`ThreadPool`] at the first panic in one of its jobs, so that the jobs
+    /// still queued are dropped as by [`ThreadPool::shutdown_now`]. The pool drops every job it
+    /// is given from then on. Implies `capture_panics`.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .fail_fast(true)
+    ///     .build();
+    /// pool.execute(|| panic!("first"));
+    /// pool.execute(|| panic!("never runs"));
+    /// assert_eq!(pool.join().unwrap_err().len(), 1);
+    /// ```
+    pub fn fail_fast(mut self, enabled: bool) -> Builder {
+        self.fail_fast = enabled;
+        self
+    }
+
+    /// Let the built [`ThreadPool`] grow and shrink between the bounds of `policy`, see
+    /// [`scaling`]. The pool starts with `num_threads` threads, clamped to those bounds, or with
+    /// the minimum if not specified. If not specified, the pool keeps its size until
+    /// [`ThreadPool::set_num_threads`] is called.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`scaling`]: scaling/index.html
+    /// [`ThreadPool::set_num_threads`]: struct.ThreadPool.html#method.set_num_threads
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::scaling::AutoScale;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .auto_scale(AutoScale::new(2, 16).threshold(8))
+    ///     .build();
+    /// assert_eq!(pool.max_count(), 2);
+    /// ```
+    pub fn auto_scale(mut self, policy: AutoScale) -> Builder {
+        self.auto_scale = Some(policy);
+        self
+    }
+
+    /// Use `token` as the [`CancelToken`] of the built [`ThreadPool`], so whoever holds a clone
+    /// of it can cancel the pool's jobs. The pool cancels it too, on [`ThreadPool::shutdown_now`]
+    /// or a panic under [`fail_fast`]. If not specified, the pool gets a token of its own.
+    ///
+    /// [`CancelToken`]: cancel/struct.CancelToken.html
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    /// [`fail_fast`]: struct.Builder.html#method.fail_fast
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::cancel::CancelToken;
+    ///
+    /// let token = CancelToken::new();
+    /// let pool = threadpool::Builder::new()
+    ///     .cancel_token(token.clone())
+    ///     .build();
+    /// token.cancel();
+    /// assert!(pool.cancel_token().is_cancelled());
+    /// ```
+    pub fn cancel_token(mut self, token: CancelToken) -> Builder {
+        self.cancel_token = Some(token);
+        self
+    }
+
     /// Finalize the [`Builder`] and build the [`ThreadPool`].
     ///
     /// [`Builder`]: struct.Builder.html
@@ -282,9 +543,42 @@ impl Builder {
     ///     .build();
     /// ```
     pub fn build(self) -> ThreadPool {
+        self.build_with_channel()
+    }
+
+    /// Finalize the [`Builder`] and build a [`ThreadPool`] whose job queue is a channel of
+    /// backend `C`.
+    ///
+    /// [`Builder`]: struct.Builder.html
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::channel::RingBuf;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(4)
+    ///     .build_with_channel::<RingBuf>();
+    /// pool.execute(|| println!("hello"));
+    /// pool.join().unwrap();
+    /// ```
+    pub fn build_with_channel<C: Backend>(self) -> ThreadPool<C> {
         //let (tx, rx) = channel::<Thunk<'static>>();
-        let (tx, rx) = unbounded();
-        let num_threads = self.num_threads.unwrap_or_else(num_cpus::get);
+        let (tx, rx) = stats::counted("pool jobs", C::unbounded());
+        let num_threads = match self.auto_scale {
+            Some(ref policy) => cmp::min(
+                cmp::max(self.num_threads.unwrap_or(policy.min), policy.min),
+                policy.max,
+            ),
+            None => self.num_threads.unwrap_or_else(num_cpus::get),
+        };
+        let cpus = self.core_affinity.map(|a| a.cpus()).unwrap_or_default();
+        let priority_queue = if self.priority_queue {
+            Some(Mutex::new(PriorityQueue::default()))
+        } else {
+            None
+        };
 
         let shared_data = Arc::new(ThreadPoolSharedData {
             name: self.thread_name,
@@ -297,6 +591,16 @@ impl Builder {
             max_thread_count: AtomicUsize::new(num_threads),
             panic_count: AtomicUsize::new(0),
             stack_size: self.thread_stack_size,
+            cpus,
+            spawned_count: AtomicUsize::new(0),
+            priority_queue,
+            thread_count: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            cancel: self.cancel_token.unwrap_or_default(),
+            dropped_count: AtomicUsize::new(0),
+            capture_panics: self.capture_panics || self.fail_fast,
+            fail_fast: self.fail_fast,
+            panics: Mutex::new(Vec::new()),
         });
 
         // Threadpool threads
@@ -304,16 +608,27 @@ impl Builder {
             spawn_in_pool(shared_data.clone());
         }
 
+        let alive = Arc::new(());
+        if let Some(policy) = self.auto_scale {
+            spawn_monitor(
+                shared_data.clone(),
+                tx.clone(),
+                Arc::downgrade(&alive),
+                Monitor::new(policy),
+            );
+        }
+
         ThreadPool {
             jobs: tx,
             shared_data: shared_data,
+            alive,
         }
     }
 }
 
-struct ThreadPoolSharedData {
+struct ThreadPoolSharedData<C: Backend> {
     name: Option<String>,
-    job_receiver: Mutex<Receiver<Thunk<'static>>>,
+    job_receiver: Mutex<JobReceiver<C>>,
     empty_trigger: Mutex<()>,
     empty_condvar: Condvar,
     join_generation: AtomicUsize,
@@ -322,13 +637,53 @@ struct ThreadPoolSharedData {
     max_thread_count: AtomicUsize,
     panic_count: AtomicUsize,
     stack_size: Option<usize>,
+    // CPUs to pin threads to, none if they are not pinned.
+    cpus: Vec<usize>,
+    spawned_count: AtomicUsize,
+    priority_queue: Option<Mutex<PriorityQueue>>,
+    // Threads started and not yet exited.
+    thread_count: AtomicUsize,
+    // Set by shutdown; new jobs are dropped.
+    closed: AtomicBool,
+    // Once cancelled, queued jobs are dropped instead of run.
+    cancel: CancelToken,
+    dropped_count: AtomicUsize,
+    capture_panics: bool,
+    fail_fast: bool,
+    // Caught since the last join.
+    panics: Mutex<Vec<JobPanic>>,
 }
 
-impl ThreadPoolSharedData {
+impl<C: Backend> ThreadPoolSharedData<C> {
     fn has_work(&self) -> bool {
         self.queued_count.load(Ordering::SeqCst) > 0 || self.active_count.load(Ordering::SeqCst) > 0
     }
 
+    // Counts the calling worker-thread out if the pool has more threads than
+    // it should, in which case the thread has to exit.
+    fn retire(&self) -> bool {
+        let mut n = self.thread_count.load(Ordering::SeqCst);
+        while n > self.max_thread_count.load(Ordering::SeqCst) {
+            match self
+                .thread_count
+                .compare_exchange(n, n - 1, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return true,
+                Err(current) => n = current,
+            }
+        }
+        false
+    }
+
+    fn record_panic(&self, payload: Box<dyn std::any::Any + Send + 'static>) {
+        self.panic_count.fetch_add(1, Ordering::SeqCst);
+        let thread = thread::current().name().map(String::from);
+        self.panics.lock().unwrap().push(JobPanic::new(payload, thread));
+        if self.fail_fast {
+            self.cancel.cancel();
+        }
+    }
+
     /// Notify all observers joining this pool if there is no more work to do.
     fn no_work_notify_all(&self) {
         if !self.has_work() {
@@ -342,13 +697,22 @@ impl ThreadPoolSharedData {
 }
 
 /// Abstraction of a thread pool for basic parallelism.
-pub struct ThreadPool {
+///
+/// Jobs are queued on a channel of backend `C`, [`Crossbeam`] unless the pool was built with
+/// [`Builder::build_with_channel`].
+///
+/// [`Crossbeam`]: channel/struct.Crossbeam.html
+/// [`Builder::build_with_channel`]: struct.Builder.html#method.build_with_channel
+pub struct ThreadPool<C: Backend = Crossbeam> {
     // How the threadpool communicates with subthreads.
     //
     // This is the only such Sender, so when it is dropped all subthreads will
     // quit.
-    jobs: Sender<Thunk<'static>>,
-    shared_data: Arc<ThreadPoolSharedData>,
+    jobs: JobSender<C>,
+    shared_data: Arc<ThreadPoolSharedData<C>>,
+    // Shared by the handles of the pool. The auto-scaling monitor, which
+    // holds a Sender of its own, stops once the last handle is gone.
+    alive: Arc<()>,
 }
 
 impl ThreadPool {
@@ -393,7 +757,7 @@ impl ThreadPool {
     ///         );
     ///     });
     /// }
-    /// pool.join();
+    /// pool.join().unwrap();
     /// ```
     ///
     /// [thread name]: https://doc.rust-lang.org/std/thread/struct.Thread.html#method.name
@@ -410,8 +774,14 @@ impl ThreadPool {
     pub fn new_with_name(name: String, num_threads: usize) -> ThreadPool {
         Self::with_name(name, num_threads)
     }
+}
 
-    /// Executes the function `job` on a thread in the pool.
+impl<C: Backend> ThreadPool<C> {
+    /// Executes the function `job` on a thread in the pool. After [`shutdown`] or
+    /// [`shutdown_now`] the job is dropped without running.
+    ///
+    /// [`shutdown`]: #method.shutdown
+    /// [`shutdown_now`]: #method.shutdown_now
     ///
     /// # Examples
     ///
@@ -425,15 +795,65 @@ impl ThreadPool {
     /// pool.execute(|| println!("world"));
     /// pool.execute(|| println!("foo"));
     /// pool.execute(|| println!("bar"));
-    /// pool.join();
+    /// pool.join().unwrap();
     /// ```
     pub fn execute<F>(&self, job: F)
     where
         F: FnOnce() + Send + 'static,
     {
+        self.execute_with_priority(0, job)
+    }
+
+    /// Executes the function `job` on a thread in the pool, ahead of every waiting job of lower
+    /// `priority` if the pool was built with [`Builder::priority_queue`]. Otherwise the same as
+    /// [`execute`].
+    ///
+    /// [`Builder::priority_queue`]: struct.Builder.html#method.priority_queue
+    /// [`execute`]: #method.execute
+    ///
+    /// # Examples
+    ///
+    /// With one thread busy, the job queued last runs first:
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .priority_queue(true)
+    ///     .build();
+    /// let (tx, rx) = channel();
+    /// pool.execute(|| sleep(Duration::from_millis(100)));
+    /// for n in 0..3 {
+    ///     let tx = tx.clone();
+    ///     pool.execute_with_priority(n, move || tx.send(n).unwrap());
+    /// }
+    /// pool.join().unwrap();
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 1, 0]);
+    /// ```
+    pub fn execute_with_priority<F>(&self, priority: i32, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.shared_data.closed.load(Ordering::SeqCst) {
+            return;
+        }
         self.shared_data.queued_count.fetch_add(1, Ordering::SeqCst);
+        let job = Job {
+            thunk: Box::new(job),
+            queued: stats::Stamp::now(),
+        };
+        let message = match self.shared_data.priority_queue {
+            Some(ref queue) => {
+                queue.lock().unwrap().push(priority, job);
+                Message::Prioritized
+            }
+            None => Message::Job(job),
+        };
         self.jobs
-            .send(Box::new(job))
+            .send(message)
             .expect("ThreadPool::execute unable to send job into queue.");
     }
 
@@ -500,7 +920,10 @@ impl ThreadPool {
         self.shared_data.max_thread_count.load(Ordering::Relaxed)
     }
 
-    /// Returns the number of panicked threads over the lifetime of the pool.
+    /// Returns the number of panicked threads over the lifetime of the pool. Panics caught by a
+    /// pool built with [`Builder::capture_panics`] count as well.
+    ///
+    /// [`Builder::capture_panics`]: struct.Builder.html#method.capture_panics
     ///
     /// # Examples
     ///
@@ -516,7 +939,7 @@ impl ThreadPool {
     ///         }
     ///     });
     /// }
-    /// pool.join();
+    /// pool.join().unwrap();
     ///
     /// assert_eq!(5, pool.panic_count());
     /// ```
@@ -595,6 +1018,12 @@ impl ThreadPool {
     /// Calling `join` from a thread within the pool will cause a deadlock. This
     /// behavior is considered safe.
     ///
+    /// If the pool was built with [`Builder::capture_panics`], returns the panics caught since
+    /// the previous `join` as an error. Each panic is returned by one `join` only. Otherwise
+    /// always returns `Ok`.
+    ///
+    /// [`Builder::capture_panics`]: struct.Builder.html#method.capture_panics
+    ///
     /// # Examples
     ///
     /// ```
@@ -612,10 +1041,21 @@ impl ThreadPool {
     ///     });
     /// }
     ///
-    /// pool.join();
+    /// pool.join().unwrap();
     /// assert_eq!(42, test_count.load(Ordering::Relaxed));
     /// ```
-    pub fn join(&self) {
+    pub fn join(&self) -> Result<(), Vec<JobPanic>> {
+        self.wait();
+        let panics = mem::take(&mut *self.shared_data.panics.lock().unwrap());
+        if panics.is_empty() {
+            Ok(())
+        } else {
+            Err(panics)
+        }
+    }
+
+    // The waiting part of join, which leaves the caught panics in place.
+    fn wait(&self) {
         // fast path requires no mutex
         if self.shared_data.has_work() == false {
             return ();
@@ -637,9 +1077,144 @@ impl ThreadPool {
             Ordering::SeqCst,
         );
     }
+
+    /// Like [`join`], but gives up after `timeout`. Returns whether all jobs were executed in
+    /// time. Caught panics are left for the next [`join`].
+    ///
+    /// [`join`]: #method.join
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// pool.execute(|| sleep(Duration::from_millis(500)));
+    /// assert!(!pool.join_timeout(Duration::from_millis(10)));
+    /// assert!(pool.join_timeout(Duration::from_secs(60)));
+    /// ```
+    pub fn join_timeout(&self, timeout: Duration) -> bool {
+        if !self.shared_data.has_work() {
+            return true;
+        }
+
+        let deadline = Instant::now() + timeout;
+        let generation = self.shared_data.join_generation.load(Ordering::SeqCst);
+        let mut lock = self.shared_data.empty_trigger.lock().unwrap();
+
+        while generation == self.shared_data.join_generation.load(Ordering::Relaxed)
+            && self.shared_data.has_work()
+        {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            lock = self
+                .shared_data
+                .empty_condvar
+                .wait_timeout(lock, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        let _ = self.shared_data.join_generation.compare_exchange(
+            generation,
+            generation.wrapping_add(1),
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        true
+    }
+
+    /// Returns the pool's [`CancelToken`], for jobs to poll. Once it is cancelled, by
+    /// [`shutdown_now`] or through any clone, the pool drops its queued jobs instead of running
+    /// them.
+    ///
+    /// [`CancelToken`]: cancel/struct.CancelToken.html
+    /// [`shutdown_now`]: #method.shutdown_now
+    pub fn cancel_token(&self) -> CancelToken {
+        self.shared_data.cancel.clone()
+    }
+
+    /// Stops taking jobs, waits until every queued job has been executed and lets the
+    /// worker-threads exit. Affects every handle of the pool. Caught panics are left for the
+    /// next [`join`].
+    ///
+    /// Calling `shutdown` from a thread within the pool will cause a deadlock, as with [`join`].
+    ///
+    /// [`join`]: #method.join
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let count = Arc::new(AtomicUsize::new(0));
+    /// for _ in 0..8 {
+    ///     let count = count.clone();
+    ///     pool.execute(move || {
+    ///         count.fetch_add(1, Ordering::SeqCst);
+    ///     });
+    /// }
+    /// pool.shutdown();
+    /// pool.execute(|| panic!("never runs"));
+    /// assert_eq!(count.load(Ordering::SeqCst), 8);
+    /// ```
+    pub fn shutdown(&self) {
+        self.shared_data.closed.store(true, Ordering::SeqCst);
+        self.wait();
+        self.stop_threads();
+    }
+
+    /// Stops taking jobs, cancels the pool's [`CancelToken`] and drops the jobs still queued.
+    /// Waits for the running jobs, which should poll the token, and lets the worker-threads exit.
+    /// Returns how many jobs were dropped.
+    ///
+    /// [`CancelToken`]: cancel/struct.CancelToken.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let token = pool.cancel_token();
+    /// pool.execute(move || {
+    ///     while !token.is_cancelled() {
+    ///         sleep(Duration::from_millis(1));
+    ///     }
+    /// });
+    /// for _ in 0..3 {
+    ///     pool.execute(|| panic!("never runs"));
+    /// }
+    /// assert_eq!(pool.shutdown_now(), 3);
+    /// ```
+    pub fn shutdown_now(&self) -> usize {
+        self.shared_data.closed.store(true, Ordering::SeqCst);
+        let dropped = self.shared_data.dropped_count.load(Ordering::SeqCst);
+        self.shared_data.cancel.cancel();
+        self.wait();
+        self.stop_threads();
+        self.shared_data.dropped_count.load(Ordering::SeqCst) - dropped
+    }
+
+    // Sends every worker-thread an order to exit. Only called once the queue is
+    // empty, so no thread starts in the meantime.
+    fn stop_threads(&self) {
+        for _ in 0..self.shared_data.thread_count.load(Ordering::SeqCst) {
+            let _ = self.jobs.send(Message::Stop);
+        }
+    }
 }
 
-impl Clone for ThreadPool {
+impl<C: Backend> Clone for ThreadPool<C> {
     /// Cloning a pool will create a new handle to the pool.
     /// The behavior is similar to [Arc](https://doc.rust-lang.org/stable/std/sync/struct.Arc.html).
     ///
@@ -676,10 +1251,11 @@ impl Clone for ThreadPool {
     ///
     /// assert_eq!(vec![66, 39916800], results);
     /// ```
-    fn clone(&self) -> ThreadPool {
+    fn clone(&self) -> ThreadPool<C> {
         ThreadPool {
             jobs: self.jobs.clone(),
             shared_data: self.shared_data.clone(),
+            alive: self.alive.clone(),
         }
     }
 }
@@ -693,7 +1269,7 @@ impl Default for ThreadPool {
     }
 }
 
-impl fmt::Debug for ThreadPool {
+impl<C: Backend> fmt::Debug for ThreadPool<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ThreadPool")
             .field("name", &self.shared_data.name)
@@ -704,7 +1280,7 @@ impl fmt::Debug for ThreadPool {
     }
 }
 
-impl PartialEq for ThreadPool {
+impl<C: Backend> PartialEq for ThreadPool<C> {
     /// Check if you are working with the same pool
     ///
     /// ```
@@ -720,17 +1296,37 @@ impl PartialEq for ThreadPool {
     /// assert!(a != b);
     /// assert!(b != a);
     /// ```
-    fn eq(&self, other: &ThreadPool) -> bool {
-        let a: &ThreadPoolSharedData = &*self.shared_data;
-        let b: &ThreadPoolSharedData = &*other.shared_data;
-        a as *const ThreadPoolSharedData == b as *const ThreadPoolSharedData
+    fn eq(&self, other: &ThreadPool<C>) -> bool {
+        let a: &ThreadPoolSharedData<C> = &*self.shared_data;
+        let b: &ThreadPoolSharedData<C> = &*other.shared_data;
+        a as *const ThreadPoolSharedData<C> == b as *const ThreadPoolSharedData<C>
         // with rust 1.17 and late:
         // Arc::ptr_eq(&self.shared_data, &other.shared_data)
     }
 }
-impl Eq for ThreadPool {}
+impl<C: Backend> Eq for ThreadPool<C> {}
 
-fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) {
+thread_local! {
+    static WORKER: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// The index of the pool thread the caller runs on, counted from 0 in the order its pool started
+/// its threads, or `None` off a pool. Lets a job record per-worker numbers such as
+/// [`stats::busy`](stats/fn.busy.html).
+///
+/// # Examples
+///
+/// ```
+/// let pool = threadpool::ThreadPool::new(2);
+/// pool.execute(|| assert!(threadpool::current_worker().unwrap() < 2));
+/// pool.join().unwrap();
+/// assert_eq!(threadpool::current_worker(), None);
+/// ```
+pub fn current_worker() -> Option<usize> {
+    WORKER.with(|w| w.get())
+}
+
+fn spawn_in_pool<C: Backend>(shared_data: Arc<ThreadPoolSharedData<C>>) {
     let mut builder = thread::Builder::new();
     if let Some(ref name) = shared_data.name {
         builder = builder.name(name.clone());
@@ -738,16 +1334,24 @@ fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) {
     if let Some(ref stack_size) = shared_data.stack_size {
         builder = builder.stack_size(stack_size.to_owned());
     }
+    shared_data.thread_count.fetch_add(1, Ordering::SeqCst);
     builder
         .spawn(move || {
             // Will spawn a new thread on panic unless it is cancelled.
             let sentinel = Sentinel::new(&shared_data);
 
+            let k = shared_data.spawned_count.fetch_add(1, Ordering::Relaxed);
+            WORKER.with(|w| w.set(Some(k)));
+            if !shared_data.cpus.is_empty() {
+                // Pinning is best effort; an unpinned thread still does its work.
+                let _ = affinity::pin(shared_data.cpus[k % shared_data.cpus.len()]);
+            }
+
             loop {
                 // Shutdown this thread if the pool has become smaller
                 let thread_counter_val = shared_data.active_count.load(Ordering::Acquire);
                 let max_thread_count_val = shared_data.max_thread_count.load(Ordering::Relaxed);
-                if thread_counter_val >= max_thread_count_val {
+                if thread_counter_val >= max_thread_count_val && shared_data.retire() {
                     break;
                 }
                 let message = {
@@ -761,15 +1365,44 @@ fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) {
                 };
 
                 let job = match message {
-                    Ok(job) => job,
-                    // The ThreadPool was dropped.
-                    Err(..) => break,
+                    Ok(Message::Job(job)) => job,
+                    // Jobs go into the priority queue before their message is
+                    // sent, so there is one for every message.
+                    Ok(Message::Prioritized) => shared_data
+                        .priority_queue
+                        .as_ref()
+                        .and_then(|queue| queue.lock().unwrap().pop())
+                        .expect("Worker thread found the priority queue empty"),
+                    // The ThreadPool was shut down or dropped.
+                    Ok(Message::Stop) | Err(..) => {
+                        shared_data.thread_count.fetch_sub(1, Ordering::SeqCst);
+                        break;
+                    }
+                    Ok(Message::Retire) if shared_data.retire() => break,
+                    Ok(Message::Retire) => continue,
                 };
+                if shared_data.cancel.is_cancelled() {
+                    shared_data.dropped_count.fetch_add(1, Ordering::SeqCst);
+                    shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+                    shared_data.no_work_notify_all();
+                    continue;
+                }
+                stats::job_dequeued(job.queued.elapsed());
                 // Do not allow IR around the job execution
                 shared_data.active_count.fetch_add(1, Ordering::SeqCst);
                 shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
 
-                job.call_box();
+                {
+                    let _span = trace::span("job", "pool");
+                    if shared_data.capture_panics {
+                        let thunk = job.thunk;
+                        if let Err(payload) = catch_unwind(AssertUnwindSafe(move || thunk.call_box())) {
+                            shared_data.record_panic(payload);
+                        }
+                    } else {
+                        job.thunk.call_box();
+                    }
+                }
 
                 shared_data.active_count.fetch_sub(1, Ordering::SeqCst);
                 shared_data.no_work_notify_all();
@@ -780,12 +1413,64 @@ fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) {
         .unwrap();
 }
 
+// Starts the thread that resizes an auto-scaled pool. It keeps the pool's
+// size in max_thread_count, like set_num_threads, and only resizes if that
+// has not changed while it was deciding.
+fn spawn_monitor<C: Backend>(
+    shared_data: Arc<ThreadPoolSharedData<C>>,
+    jobs: JobSender<C>,
+    alive: Weak<()>,
+    mut monitor: Monitor,
+) {
+    let mut builder = thread::Builder::new();
+    if let Some(ref name) = shared_data.name {
+        builder = builder.name(format!("{} monitor", name));
+    }
+    builder
+        .spawn(move || {
+            let tick = monitor.policy().tick();
+            while alive.upgrade().is_some() && !shared_data.closed.load(Ordering::SeqCst) {
+                thread::sleep(tick);
+                let threads = shared_data.max_thread_count.load(Ordering::SeqCst);
+                let target = monitor.target(
+                    Instant::now(),
+                    threads,
+                    shared_data.active_count.load(Ordering::SeqCst),
+                    shared_data.queued_count.load(Ordering::SeqCst),
+                );
+                if target == threads
+                    || shared_data
+                        .max_thread_count
+                        .compare_exchange(threads, target, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_err()
+                {
+                    continue;
+                }
+                if target > threads {
+                    for _ in threads..target {
+                        spawn_in_pool(shared_data.clone());
+                    }
+                } else {
+                    for _ in target..threads {
+                        let _ = jobs.send(Message::Retire);
+                    }
+                }
+                monitor.policy().report(threads, target);
+            }
+        })
+        .unwrap();
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Builder, ThreadPool};
+    use super::affinity::{self, Affinity};
+    use super::cancel::CancelToken;
+    use super::channel::{Backend, Crossbeam, LockFree, RingBuf, Std};
+    use super::scaling::AutoScale;
+    use super::{current_worker, Builder, ThreadPool};
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::mpsc::{channel, sync_channel};
-    use std::sync::{Arc, Barrier};
+    use std::sync::{Arc, Barrier, Mutex};
     use std::thread::{self, sleep};
     use std::time::Duration;
 
@@ -809,7 +1494,7 @@ mod test {
         sleep(Duration::from_secs(1));
         assert_eq!(pool.active_count(), new_thread_amount);
 
-        pool.join();
+        pool.join().unwrap();
     }
 
     #[test]
@@ -828,7 +1513,7 @@ mod test {
         sleep(Duration::from_secs(1));
         assert_eq!(pool.active_count(), new_thread_amount);
 
-        pool.join();
+        pool.join().unwrap();
     }
 
     #[test]
@@ -861,6 +1546,162 @@ mod test {
         assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
     }
 
+    #[test]
+    fn test_works_on_every_channel() {
+        fn works<C: Backend>() {
+            let pool = Builder::new()
+                .num_threads(TEST_TASKS)
+                .build_with_channel::<C>();
+            let counter = Arc::new(AtomicUsize::new(0));
+            for _ in 0..1000 {
+                let counter = counter.clone();
+                pool.execute(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            pool.join().unwrap();
+            assert_eq!(counter.load(Ordering::SeqCst), 1000);
+        }
+        works::<Crossbeam>();
+        works::<Std>();
+        works::<RingBuf>();
+        works::<LockFree>();
+    }
+
+    #[test]
+    fn test_priority_queue() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .priority_queue(true)
+            .build();
+        let (tx, rx) = channel();
+        let gate = Arc::new(Barrier::new(2));
+        let worker_gate = gate.clone();
+        pool.execute(move || {
+            worker_gate.wait();
+        });
+        for n in 0..10 {
+            let tx = tx.clone();
+            pool.execute_with_priority(n % 3, move || tx.send(n).unwrap());
+        }
+        gate.wait();
+        pool.join().unwrap();
+        let order: Vec<i32> = rx.try_iter().collect();
+        assert_eq!(order, vec![2, 5, 8, 1, 4, 7, 0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..100 {
+            let count = count.clone();
+            pool.execute(move || {
+                sleep(Duration::from_millis(1));
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.clone().shutdown();
+        assert_eq!(count.load(Ordering::SeqCst), 100);
+
+        pool.execute(|| panic!("ran after shutdown"));
+        assert_eq!(pool.queued_count(), 0);
+        for _ in 0..1000 {
+            if pool.shared_data.thread_count.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.shared_data.thread_count.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.panic_count(), 0);
+    }
+
+    #[test]
+    fn test_current_worker() {
+        // Every worker holds at the barrier, so each runs exactly one job.
+        let pool = ThreadPool::new(TEST_TASKS);
+        let barrier = Arc::new(Barrier::new(TEST_TASKS));
+        let (tx, rx) = channel();
+        for _ in 0..TEST_TASKS {
+            let (barrier, tx) = (barrier.clone(), tx.clone());
+            pool.execute(move || {
+                barrier.wait();
+                tx.send(current_worker().unwrap()).unwrap();
+            });
+        }
+        pool.join().unwrap();
+        drop(tx);
+        let mut seen: Vec<usize> = rx.iter().collect();
+        seen.sort();
+        assert_eq!(seen, (0..TEST_TASKS).collect::<Vec<_>>());
+        assert_eq!(current_worker(), None);
+    }
+
+    #[test]
+    fn test_shutdown_now() {
+        fn works<C: Backend>() {
+            let pool = Builder::new().num_threads(2).build_with_channel::<C>();
+            let (tx, rx) = channel();
+            let ran = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                let token = pool.cancel_token();
+                let tx = tx.clone();
+                pool.execute(move || {
+                    tx.send(()).unwrap();
+                    while !token.is_cancelled() {
+                        sleep(Duration::from_millis(1));
+                    }
+                });
+            }
+            for _ in 0..10 {
+                let ran = ran.clone();
+                pool.execute(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            // Both workers are busy, so all ten are still queued.
+            rx.iter().take(2).count();
+            assert_eq!(pool.shutdown_now(), 10);
+            assert_eq!(ran.load(Ordering::SeqCst), 0);
+            assert_eq!(pool.active_count(), 0);
+            assert_eq!(pool.queued_count(), 0);
+        }
+        works::<Crossbeam>();
+        works::<Std>();
+        works::<RingBuf>();
+        works::<LockFree>();
+    }
+
+    #[test]
+    fn test_join_timeout() {
+        let pool = ThreadPool::new(1);
+        assert!(pool.join_timeout(Duration::from_millis(0)));
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            rx.recv().unwrap();
+        });
+        assert!(!pool.join_timeout(Duration::from_millis(50)));
+        tx.send(()).unwrap();
+        assert!(pool.join_timeout(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_core_affinity() {
+        let cpu = affinity::allowed()[0];
+        let pool = Builder::new()
+            .num_threads(TEST_TASKS)
+            .core_affinity(Affinity::Cores(vec![cpu]))
+            .build();
+        let (tx, rx) = channel();
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(affinity::allowed()).unwrap());
+        }
+        for allowed in rx.iter().take(TEST_TASKS) {
+            assert_eq!(allowed, vec![cpu]);
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_zero_tasks_panic() {
@@ -875,7 +1716,7 @@ mod test {
         for _ in 0..TEST_TASKS {
             pool.execute(move || panic!("Ignore this panic, it must!"));
         }
-        pool.join();
+        pool.join().unwrap();
 
         assert_eq!(pool.panic_count(), TEST_TASKS);
 
@@ -891,6 +1732,141 @@ mod test {
         assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
     }
 
+    #[test]
+    fn test_capture_panics() {
+        let pool = Builder::new()
+            .num_threads(TEST_TASKS)
+            .thread_name("capturing".into())
+            .capture_panics(true)
+            .build();
+        let count = Arc::new(AtomicUsize::new(0));
+        for n in 0..2 * TEST_TASKS {
+            let count = count.clone();
+            pool.execute(move || {
+                if n % 2 == 0 {
+                    panic!("Ignore this panic, job {}", n);
+                }
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        let panics = pool.join().unwrap_err();
+
+        let mut messages: Vec<_> = panics.iter().map(|p| p.message().to_string()).collect();
+        messages.sort();
+        let mut expected: Vec<_> = (0..TEST_TASKS)
+            .map(|n| format!("Ignore this panic, job {}", 2 * n))
+            .collect();
+        expected.sort();
+        assert_eq!(messages, expected);
+        assert!(panics.iter().all(|p| p.thread() == Some("capturing")));
+        assert_eq!(count.load(Ordering::SeqCst), TEST_TASKS);
+        assert_eq!(pool.panic_count(), TEST_TASKS);
+        assert!(pool.join().is_ok());
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let pool = Builder::new().num_threads(1).fail_fast(true).build();
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            rx.recv().unwrap();
+            panic!("Ignore this panic, it must!");
+        });
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..TEST_TASKS {
+            let count = count.clone();
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        tx.send(()).unwrap();
+
+        assert_eq!(pool.join().unwrap_err().len(), 1);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert!(pool.cancel_token().is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let pool = Builder::new()
+            .num_threads(1)
+            .cancel_token(token.clone())
+            .build();
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || rx.recv().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..TEST_TASKS {
+            let count = count.clone();
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert!(!token.cancel());
+        assert!(pool.cancel_token().is_cancelled());
+        tx.send(()).unwrap();
+        pool.join().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        // A pool of its own does not share it.
+        assert!(!Builder::new().build().cancel_token().is_cancelled());
+    }
+
+    #[test]
+    fn test_auto_scale() {
+        let resizes = Arc::new(Mutex::new(Vec::new()));
+        let log = resizes.clone();
+        let pool = Builder::new()
+            .auto_scale(
+                AutoScale::new(1, TEST_TASKS)
+                    .grow_after(Duration::from_millis(10))
+                    .keep_alive(Duration::from_millis(100))
+                    .on_resize(move |r| log.lock().unwrap().push(r)),
+            )
+            .build();
+        assert_eq!(pool.max_count(), 1);
+
+        // A burst grows the pool to its maximum...
+        let b = Arc::new(Barrier::new(TEST_TASKS + 1));
+        for _ in 0..TEST_TASKS {
+            let b = b.clone();
+            pool.execute(move || {
+                b.wait();
+            });
+        }
+        b.wait();
+        assert_eq!(pool.max_count(), TEST_TASKS);
+        pool.join().unwrap();
+
+        // ...and the idle workers are retired again.
+        let threads = || pool.shared_data.thread_count.load(Ordering::SeqCst);
+        for _ in 0..500 {
+            if threads() == 1 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(threads(), 1);
+        assert_eq!(pool.max_count(), 1);
+
+        let resizes = resizes.lock().unwrap();
+        assert_eq!(resizes.first().unwrap().from, 1);
+        assert_eq!(resizes.last().unwrap().to, 1);
+        assert!(resizes.iter().all(|r| r.from != r.to));
+        drop(resizes);
+
+        // Dropping the pool stops the monitor and with it the workers.
+        let shared_data = Arc::downgrade(&pool.shared_data);
+        drop(pool);
+        for _ in 0..500 {
+            if shared_data.upgrade().is_none() {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        assert!(shared_data.upgrade().is_none());
+    }
+
     #[test]
     fn test_should_not_panic_on_drop_if_subtasks_panic_after_drop() {
         let pool = ThreadPool::new(TEST_TASKS);
@@ -942,7 +1918,7 @@ mod test {
         b1.wait();
 
         assert_eq!(rx.iter().take(test_tasks).fold(0, |a, b| a + b), test_tasks);
-        pool.join();
+        pool.join().unwrap();
 
         let atomic_active_count = pool.active_count();
         assert!(
@@ -1065,7 +2041,7 @@ mod test {
         }
 
         println!("{:?}", pool);
-        pool.join();
+        pool.join().unwrap();
         assert_eq!(42, test_count.load(Ordering::Acquire));
 
         for _ in 0..42 {
@@ -1075,7 +2051,7 @@ mod test {
                 test_count.fetch_add(1, Ordering::Relaxed);
             });
         }
-        pool.join();
+        pool.join().unwrap();
         assert_eq!(84, test_count.load(Ordering::Relaxed));
     }
 
@@ -1102,7 +2078,7 @@ mod test {
             pool0.execute(move || {
                 pool1.execute(move || {
                     error(format!("p1: {} -=- {:?}\n", i, pool0_));
-                    pool0_.join();
+                    pool0_.join().unwrap();
                     error(format!("p1: send({})\n", i));
                     tx.send(i).expect("send i from pool1 -> main");
                 });
@@ -1113,9 +2089,9 @@ mod test {
 
         assert_eq!(rx.try_recv(), Err(Empty));
         error(format!("{:?}\n{:?}\n", pool0, pool1));
-        pool0.join();
+        pool0.join().unwrap();
         error(format!("pool0.join() complete =-= {:?}", pool1));
-        pool1.join();
+        pool1.join().unwrap();
         error("pool1.join() complete\n".into());
         assert_eq!(
             rx.iter().fold(0, |acc, i| acc + i),
@@ -1128,7 +2104,7 @@ mod test {
         // Joining an empty pool must return imminently
         let pool = ThreadPool::new(4);
 
-        pool.join();
+        pool.join().unwrap();
 
         assert!(true);
     }
@@ -1150,7 +2126,7 @@ mod test {
             (0..23).map(|_| p_t.execute(sleepy_function)).count();
         });
 
-        pool.join();
+        pool.join().unwrap();
     }
 
     #[test]
@@ -1169,7 +2145,7 @@ mod test {
             let pool = pool.clone();
             thread::spawn(move || {
                 // wait for the first batch of tasks to finish
-                pool.join();
+                pool.join().unwrap();
 
                 let (tx, rx) = channel();
                 for i in 0..42 {
@@ -1187,7 +2163,7 @@ mod test {
             let pool = pool.clone();
             thread::spawn(move || {
                 // wait for the first batch of tasks to finish
-                pool.join();
+                pool.join().unwrap();
 
                 let (tx, rx) = channel();
                 for i in 1..12 {
@@ -1217,13 +2193,19 @@ mod test {
     #[test]
     fn test_sync_shared_data() {
         fn assert_sync<T: Sync>() {}
-        assert_sync::<super::ThreadPoolSharedData>();
+        assert_sync::<super::ThreadPoolSharedData<Crossbeam>>();
+        assert_sync::<super::ThreadPoolSharedData<Std>>();
+        assert_sync::<super::ThreadPoolSharedData<RingBuf>>();
+        assert_sync::<super::ThreadPoolSharedData<LockFree>>();
     }
 
     #[test]
     fn test_send_shared_data() {
         fn assert_send<T: Send>() {}
-        assert_send::<super::ThreadPoolSharedData>();
+        assert_send::<super::ThreadPoolSharedData<Crossbeam>>();
+        assert_send::<super::ThreadPoolSharedData<Std>>();
+        assert_send::<super::ThreadPoolSharedData<RingBuf>>();
+        assert_send::<super::ThreadPoolSharedData<LockFree>>();
     }
 
     #[test]
@@ -1288,7 +2270,7 @@ mod test {
             let wave_clock = wave_clock.clone();
             p_waiter.execute(move || {
                 let now = wave_clock.load(Ordering::SeqCst);
-                p_clock.join();
+                p_clock.join().unwrap();
                 // submit jobs for the second wave
                 p_clock.execute(|| sleep(Duration::from_secs(1)));
                 let clock = wave_clock.load(Ordering::SeqCst);
@@ -1298,7 +2280,7 @@ mod test {
         println!("all scheduled at {}", wave_clock.load(Ordering::SeqCst));
         barrier.wait();
 
-        p_clock.join();
+        p_clock.join().unwrap();
         //p_waiter.join();
 
         drop(tx);
@@ -0,0 +1,1076 @@
+//! Lock-free ring-buffer channels.
+//!
+//! Two rings sit underneath: [`spsc`], a single-producer single-consumer ring that only needs a
+//! head and a tail index, and the multi-producer multi-consumer ring behind [`LockFree`], where
+//! every slot carries a sequence number telling producers and consumers whose turn it is (Dmitry
+//! Vyukov's bounded MPMC queue). Neither takes a lock to move an element.
+//!
+//! The blocking calls spin for a while and then park the thread on a condition variable. The
+//! other side only touches that condition variable when somebody is actually parked.
+//!
+//! A ring has a fixed size, so [`LockFree::unbounded`] links MPMC rings into a list instead. A
+//! producer that finds the last ring full closes it and appends a fresh one; consumers move on
+//! to the next ring once theirs is closed and drained. While the consumers keep up, a single ring
+//! is reused lap after lap. The pool takes its job queue from `unbounded`, and jobs that submit
+//! jobs must never block on it.
+//!
+//! [`spsc`]: fn.spsc.html
+//! [`LockFree`]: struct.LockFree.html
+//! [`LockFree::unbounded`]: struct.LockFree.html#method.unbounded
+
+use channel::{Backend, Receiver, Sender};
+use channel::{RecvError, SendError, TryRecvError};
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+// Polls before parking; the first SPIN_LIMIT of them busy-wait, the rest
+// yield the CPU.
+const SPIN_LIMIT: u32 = 64;
+const YIELD_LIMIT: u32 = 128;
+// Slots in each ring of an unbounded channel.
+const SEGMENT: usize = 256;
+// Set in an MPMC ring's enqueue index once it takes no more elements.
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+trait Ring<T>: Send + Sync {
+    /// Adds `x`, or hands it back if the ring is full or closed. On success returns the position `x` was
+    /// written at; it has been received once `popped()` has moved past it.
+    fn try_push(&self, x: T) -> Result<usize, T>;
+    fn try_pop(&self) -> Option<T>;
+    fn pushed(&self) -> usize;
+    fn popped(&self) -> usize;
+    fn capacity(&self) -> usize;
+
+    /// Whether `try_pop` would fail right now, other consumers aside.
+    fn is_empty(&self) -> bool {
+        self.popped() == self.pushed()
+    }
+
+    /// Whether `try_push` would fail right now, other producers aside.
+    fn is_full(&self) -> bool {
+        self.pushed().wrapping_sub(self.popped()) >= self.capacity()
+    }
+}
+
+// A slot's sequence number is 2 * pos while it waits for the element of
+// position pos and 2 * pos + 1 once that element is in. Counting in steps of
+// two keeps "full" and "free for the next lap" apart even with one slot.
+struct Slot<T> {
+    seq: AtomicUsize,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Mpmc<T> {
+    slots: Box<[Slot<T>]>,
+    // Carries CLOSED once the ring is closed; no push succeeds after that.
+    enqueue: AtomicUsize,
+    dequeue: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Mpmc<T> {}
+unsafe impl<T: Send> Sync for Mpmc<T> {}
+
+impl<T> Mpmc<T> {
+    fn new(cap: usize) -> Mpmc<T> {
+        assert!(cap > 0);
+        Mpmc {
+            slots: (0..cap)
+                .map(|i| Slot {
+                    seq: AtomicUsize::new(2 * i),
+                    val: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            enqueue: AtomicUsize::new(0),
+            dequeue: AtomicUsize::new(0),
+        }
+    }
+
+    fn close(&self) {
+        self.enqueue.fetch_or(CLOSED, Ordering::SeqCst);
+    }
+}
+
+impl<T: Send> Ring<T> for Mpmc<T> {
+    fn try_push(&self, x: T) -> Result<usize, T> {
+        let mut pos = self.enqueue.load(Ordering::Relaxed);
+        loop {
+            if pos & CLOSED != 0 {
+                return Err(x);
+            }
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos.wrapping_mul(2)) as isize;
+            if diff == 0 {
+                // The slot is free for lap `pos`; claim it.
+                match self.enqueue.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.val.get()).as_mut_ptr().write(x) };
+                        slot.seq
+                            .store(pos.wrapping_mul(2).wrapping_add(1), Ordering::Release);
+                        return Ok(pos);
+                    }
+                    Err(now) => pos = now,
+                }
+            } else if diff < 0 {
+                // Still holds the element from the previous lap.
+                return Err(x);
+            } else {
+                pos = self.enqueue.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let mut pos = self.dequeue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos.wrapping_mul(2).wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.dequeue.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let x = unsafe { (*slot.val.get()).as_ptr().read() };
+                        // Free the slot for the next lap.
+                        let next = pos.wrapping_add(self.slots.len());
+                        slot.seq.store(next.wrapping_mul(2), Ordering::Release);
+                        return Some(x);
+                    }
+                    Err(now) => pos = now,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.dequeue.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pushed(&self) -> usize {
+        self.enqueue.load(Ordering::SeqCst) & !CLOSED
+    }
+
+    fn popped(&self) -> usize {
+        self.dequeue.load(Ordering::SeqCst)
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // The indices move before the slot is written or freed, so these look at
+    // the sequence number of the next slot instead. Going by the indices
+    // alone would have a waiter retry in a busy loop until the thread that
+    // moved them gets to run again.
+    fn is_empty(&self) -> bool {
+        let pos = self.dequeue.load(Ordering::SeqCst);
+        let seq = self.slots[pos % self.slots.len()].seq.load(Ordering::SeqCst);
+        (seq.wrapping_sub(pos.wrapping_mul(2).wrapping_add(1)) as isize) < 0
+    }
+
+    fn is_full(&self) -> bool {
+        let pos = self.enqueue.load(Ordering::SeqCst);
+        if pos & CLOSED != 0 {
+            return true;
+        }
+        let seq = self.slots[pos % self.slots.len()].seq.load(Ordering::SeqCst);
+        (seq.wrapping_sub(pos.wrapping_mul(2)) as isize) < 0
+    }
+}
+
+impl<T> Drop for Mpmc<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.dequeue.get_mut(), *self.enqueue.get_mut() & !CLOSED);
+        let cap = self.slots.len();
+        for pos in head..tail {
+            unsafe { (*self.slots[pos % cap].val.get()).as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+struct Spsc<T> {
+    buf: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Only the consumer writes head and only the producer writes tail.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Spsc<T> {}
+unsafe impl<T: Send> Sync for Spsc<T> {}
+
+impl<T> Spsc<T> {
+    fn new(cap: usize) -> Spsc<T> {
+        assert!(cap > 0);
+        Spsc {
+            buf: (0..cap)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Send> Ring<T> for Spsc<T> {
+    fn try_push(&self, x: T) -> Result<usize, T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.buf.len() {
+            return Err(x);
+        }
+        unsafe { (*self.buf[tail % self.buf.len()].get()).as_mut_ptr().write(x) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(tail)
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let x = unsafe { (*self.buf[head % self.buf.len()].get()).as_ptr().read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(x)
+    }
+
+    fn pushed(&self) -> usize {
+        self.tail.load(Ordering::SeqCst)
+    }
+
+    fn popped(&self) -> usize {
+        self.head.load(Ordering::SeqCst)
+    }
+
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl<T> Drop for Spsc<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let cap = self.buf.len();
+        for pos in head..tail {
+            unsafe { (*self.buf[pos % cap].get()).as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+struct Segment<T> {
+    ring: Mpmc<T>,
+    // The position of the ring's first element in the whole channel.
+    base: usize,
+    next: AtomicPtr<Segment<T>>,
+    // Links the segment into List::retired once it is unlinked.
+    retired: AtomicPtr<Segment<T>>,
+}
+
+impl<T> Segment<T> {
+    fn new(cap: usize, base: usize) -> *mut Segment<T> {
+        Box::into_raw(Box::new(Segment {
+            ring: Mpmc::new(cap),
+            base,
+            next: AtomicPtr::new(ptr::null_mut()),
+            retired: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+// MPMC rings linked head to tail. Only the tail ring takes elements; once it
+// is full it is closed for good and a new one appended. The head ring is
+// unlinked when it is closed and drained.
+//
+// A thread may still be looking at a ring it loaded just before the ring was
+// unlinked, so unlinked rings wait in `retired` until a moment when no
+// operation is in progress: any operation that starts later can no longer
+// reach them.
+struct List<T> {
+    head: AtomicPtr<Segment<T>>,
+    tail: AtomicPtr<Segment<T>>,
+    segment: usize,
+    active: AtomicUsize,
+    retired: AtomicPtr<Segment<T>>,
+}
+
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Send> Sync for List<T> {}
+
+// Counts an operation on a List as in progress while it lives.
+struct Guard<'a, T: 'a>(&'a List<T>);
+
+impl<'a, T> Drop for Guard<'a, T> {
+    fn drop(&mut self) {
+        if self.0.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.collect();
+        }
+    }
+}
+
+impl<T> List<T> {
+    fn new(segment: usize) -> List<T> {
+        let first = Segment::new(segment, 0);
+        List {
+            head: AtomicPtr::new(first),
+            tail: AtomicPtr::new(first),
+            segment,
+            active: AtomicUsize::new(0),
+            retired: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn enter(&self) -> Guard<'_, T> {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        Guard(self)
+    }
+
+    // Adds the chain of segments from `first` to `last` to the retired ones.
+    fn retire(&self, first: *mut Segment<T>, last: *mut Segment<T>) {
+        let mut old = self.retired.load(Ordering::SeqCst);
+        loop {
+            unsafe { (*last).retired.store(old, Ordering::SeqCst) };
+            match self
+                .retired
+                .compare_exchange_weak(old, first, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return,
+                Err(now) => old = now,
+            }
+        }
+    }
+
+    // Frees the retired segments if nothing is in progress, or puts them back.
+    fn collect(&self) {
+        let first = self.retired.swap(ptr::null_mut(), Ordering::SeqCst);
+        if first.is_null() {
+            return;
+        }
+        if self.active.load(Ordering::SeqCst) == 0 {
+            let mut seg = first;
+            while !seg.is_null() {
+                let next = unsafe { (*seg).retired.load(Ordering::SeqCst) };
+                drop(unsafe { Box::from_raw(seg) });
+                seg = next;
+            }
+            return;
+        }
+        let mut last = first;
+        loop {
+            let next = unsafe { (*last).retired.load(Ordering::SeqCst) };
+            if next.is_null() {
+                break;
+            }
+            last = next;
+        }
+        self.retire(first, last);
+    }
+}
+
+impl<T: Send> Ring<T> for List<T> {
+    fn try_push(&self, mut x: T) -> Result<usize, T> {
+        let _guard = self.enter();
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let seg = unsafe { &*tail };
+            match seg.ring.try_push(x) {
+                Ok(pos) => return Ok(seg.base.wrapping_add(pos)),
+                Err(back) => x = back,
+            }
+            seg.ring.close();
+            let next = seg.next.load(Ordering::SeqCst);
+            if !next.is_null() {
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, next, Ordering::SeqCst, Ordering::SeqCst);
+                continue;
+            }
+            // Nobody else can see the new ring yet, so x is sure to go in.
+            let base = seg.base.wrapping_add(seg.ring.pushed());
+            let new = Segment::new(self.segment, base);
+            let _ = unsafe { (*new).ring.try_push(x) };
+            match seg
+                .next
+                .compare_exchange(ptr::null_mut(), new, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    let _ = self
+                        .tail
+                        .compare_exchange(tail, new, Ordering::SeqCst, Ordering::SeqCst);
+                    return Ok(base);
+                }
+                Err(_) => {
+                    let new = unsafe { Box::from_raw(new) };
+                    x = new.ring.try_pop().unwrap();
+                }
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let _guard = self.enter();
+        loop {
+            let head = self.head.load(Ordering::SeqCst);
+            let seg = unsafe { &*head };
+            if let Some(x) = seg.ring.try_pop() {
+                return Some(x);
+            }
+            // A ring gets a successor only after it is closed. It is drained
+            // once every element that went in has been taken out.
+            let next = seg.next.load(Ordering::SeqCst);
+            if next.is_null() || seg.ring.popped() != seg.ring.pushed() {
+                return None;
+            }
+            // The tail must not be left on a ring about to be retired.
+            let _ = self
+                .tail
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::SeqCst);
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                self.retire(head, head);
+            }
+        }
+    }
+
+    fn pushed(&self) -> usize {
+        let _guard = self.enter();
+        let seg = unsafe { &*self.tail.load(Ordering::SeqCst) };
+        seg.base.wrapping_add(seg.ring.pushed())
+    }
+
+    fn popped(&self) -> usize {
+        let _guard = self.enter();
+        let seg = unsafe { &*self.head.load(Ordering::SeqCst) };
+        seg.base.wrapping_add(seg.ring.popped())
+    }
+
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    // A drained head ring with a successor is not empty: try_pop moves on.
+    fn is_empty(&self) -> bool {
+        let _guard = self.enter();
+        let seg = unsafe { &*self.head.load(Ordering::SeqCst) };
+        seg.ring.is_empty() && seg.next.load(Ordering::SeqCst).is_null()
+    }
+
+    fn is_full(&self) -> bool {
+        false
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // The retired segments still point on into the list through `next`,
+        // so each chain is followed by its own link.
+        let mut seg = *self.head.get_mut();
+        while !seg.is_null() {
+            let mut seg_box = unsafe { Box::from_raw(seg) };
+            seg = *seg_box.next.get_mut();
+        }
+        let mut seg = *self.retired.get_mut();
+        while !seg.is_null() {
+            let mut seg_box = unsafe { Box::from_raw(seg) };
+            seg = *seg_box.retired.get_mut();
+        }
+    }
+}
+
+/// Where blocked senders or receivers park. `sleepers` lets the other side skip the mutex when
+/// nobody is parked; the fences on both sides make sure a sleeper either sees the change it is
+/// waiting for or is counted by the thread making it.
+struct Signal {
+    sleepers: AtomicUsize,
+    lock: Mutex<()>,
+    cv: Condvar,
+}
+
+impl Signal {
+    fn new() -> Signal {
+        Signal {
+            sleepers: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            cv: Condvar::new(),
+        }
+    }
+
+    fn wait_until<F: Fn() -> bool>(&self, ready: F) {
+        for i in 0..YIELD_LIMIT {
+            if ready() {
+                return;
+            }
+            if i < SPIN_LIMIT {
+                std::hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        let mut guard = self.lock.lock().unwrap();
+        loop {
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            if ready() {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                return;
+            }
+            guard = self.cv.wait(guard).unwrap();
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.cv.notify_all();
+        }
+    }
+}
+
+struct Shared<R> {
+    ring: R,
+    rendezvous: bool,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    not_empty: Signal,
+    // Also where a rendezvous send waits for its element to be taken.
+    not_full: Signal,
+}
+
+impl<R> Shared<R> {
+    fn new(ring: R, rendezvous: bool) -> Arc<Shared<R>> {
+        Arc::new(Shared {
+            ring,
+            rendezvous,
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            not_empty: Signal::new(),
+            not_full: Signal::new(),
+        })
+    }
+
+    fn send<T>(&self, mut x: T) -> Result<(), SendError<T>>
+    where
+        R: Ring<T>,
+    {
+        loop {
+            if self.receivers.load(Ordering::SeqCst) == 0 {
+                return Err(SendError(x));
+            }
+            match self.ring.try_push(x) {
+                Ok(pos) => {
+                    self.not_empty.notify();
+                    if self.rendezvous {
+                        self.not_full.wait_until(|| {
+                            self.ring.popped().wrapping_sub(pos) as isize > 0
+                                || self.receivers.load(Ordering::SeqCst) == 0
+                        });
+                    }
+                    return Ok(());
+                }
+                Err(back) => {
+                    x = back;
+                    self.not_full.wait_until(|| {
+                        !self.ring.is_full() || self.receivers.load(Ordering::SeqCst) == 0
+                    });
+                }
+            }
+        }
+    }
+
+    fn try_recv<T>(&self) -> Result<T, TryRecvError>
+    where
+        R: Ring<T>,
+    {
+        if let Some(x) = self.ring.try_pop() {
+            self.not_full.notify();
+            return Ok(x);
+        }
+        if self.senders.load(Ordering::SeqCst) > 0 {
+            return Err(TryRecvError::Empty);
+        }
+        // Something may have gone in just before the last sender left.
+        match self.ring.try_pop() {
+            Some(x) => Ok(x),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    fn recv<T>(&self) -> Result<T, RecvError>
+    where
+        R: Ring<T>,
+    {
+        loop {
+            match self.try_recv() {
+                Ok(x) => return Ok(x),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => self.not_empty.wait_until(|| {
+                    !self.ring.is_empty() || self.senders.load(Ordering::SeqCst) == 0
+                }),
+            }
+        }
+    }
+
+    fn drop_sender(&self) {
+        if self.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.not_empty.notify();
+        }
+    }
+
+    fn drop_receiver(&self) {
+        if self.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.not_full.notify();
+        }
+    }
+}
+
+/// The sending half of an [`spsc`](fn.spsc.html) channel.
+pub struct Producer<T> {
+    shared: Arc<Shared<Spsc<T>>>,
+    // Keeps the handle from being shared between threads, which would break the single
+    // producer assumption.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// The receiving half of an [`spsc`](fn.spsc.html) channel.
+pub struct Consumer<T> {
+    shared: Arc<Shared<Spsc<T>>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// Creates a single-producer single-consumer channel holding up to `cap` elements.
+///
+/// # Panics
+///
+/// This function will panic if `cap` is 0.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// let (tx, rx) = threadpool::lockfree::spsc(16);
+/// let consumer = thread::spawn(move || {
+///     let mut sum = 0;
+///     while let Ok(x) = rx.recv() {
+///         sum += x;
+///     }
+///     sum
+/// });
+/// for x in 0..100 {
+///     tx.send(x).unwrap();
+/// }
+/// drop(tx);
+/// assert_eq!(consumer.join().unwrap(), 4950);
+/// ```
+pub fn spsc<T: Send>(cap: usize) -> (Producer<T>, Consumer<T>) {
+    let shared = Shared::new(Spsc::new(cap), false);
+    (
+        Producer {
+            shared: shared.clone(),
+            _not_sync: PhantomData,
+        },
+        Consumer {
+            shared,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+impl<T: Send> Producer<T> {
+    /// Sends `x`, spinning and then parking while the ring is full.
+    pub fn send(&self, x: T) -> Result<(), SendError<T>> {
+        self.shared.send(x)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.drop_sender();
+    }
+}
+
+impl<T: Send> Consumer<T> {
+    /// Receives an element, spinning and then parking while the ring is empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.shared.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.shared.try_recv()
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.drop_receiver();
+    }
+}
+
+/// The multi-producer multi-consumer ring as a channel [`Backend`](../channel/trait.Backend.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LockFree;
+
+pub struct LockFreeSender<T>(Tx<T>);
+pub struct LockFreeReceiver<T>(Rx<T>);
+
+enum Tx<T> {
+    Ring(Arc<Shared<Mpmc<T>>>),
+    List(Arc<Shared<List<T>>>),
+}
+
+enum Rx<T> {
+    Ring(Arc<Shared<Mpmc<T>>>),
+    List(Arc<Shared<List<T>>>),
+}
+
+impl<T> Clone for LockFreeSender<T> {
+    fn clone(&self) -> Self {
+        LockFreeSender(match self.0 {
+            Tx::Ring(ref shared) => {
+                shared.senders.fetch_add(1, Ordering::SeqCst);
+                Tx::Ring(shared.clone())
+            }
+            Tx::List(ref shared) => {
+                shared.senders.fetch_add(1, Ordering::SeqCst);
+                Tx::List(shared.clone())
+            }
+        })
+    }
+}
+
+impl<T> Drop for LockFreeSender<T> {
+    fn drop(&mut self) {
+        match self.0 {
+            Tx::Ring(ref shared) => shared.drop_sender(),
+            Tx::List(ref shared) => shared.drop_sender(),
+        }
+    }
+}
+
+impl<T> Clone for LockFreeReceiver<T> {
+    fn clone(&self) -> Self {
+        LockFreeReceiver(match self.0 {
+            Rx::Ring(ref shared) => {
+                shared.receivers.fetch_add(1, Ordering::SeqCst);
+                Rx::Ring(shared.clone())
+            }
+            Rx::List(ref shared) => {
+                shared.receivers.fetch_add(1, Ordering::SeqCst);
+                Rx::List(shared.clone())
+            }
+        })
+    }
+}
+
+impl<T> Drop for LockFreeReceiver<T> {
+    fn drop(&mut self) {
+        match self.0 {
+            Rx::Ring(ref shared) => shared.drop_receiver(),
+            Rx::List(ref shared) => shared.drop_receiver(),
+        }
+    }
+}
+
+impl<T: Send> Sender<T> for LockFreeSender<T> {
+    fn send(&self, x: T) -> Result<(), SendError<T>> {
+        match self.0 {
+            Tx::Ring(ref shared) => shared.send(x),
+            Tx::List(ref shared) => shared.send(x),
+        }
+    }
+}
+
+impl<T: Send> Receiver<T> for LockFreeReceiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        match self.0 {
+            Rx::Ring(ref shared) => shared.recv(),
+            Rx::List(ref shared) => shared.recv(),
+        }
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.0 {
+            Rx::Ring(ref shared) => shared.try_recv(),
+            Rx::List(ref shared) => shared.try_recv(),
+        }
+    }
+}
+
+fn mpmc<T: Send>(cap: usize) -> (LockFreeSender<T>, LockFreeReceiver<T>) {
+    // A rendezvous channel hands its element over through a single slot.
+    let shared = Shared::new(Mpmc::new(cap.max(1)), cap == 0);
+    (
+        LockFreeSender(Tx::Ring(shared.clone())),
+        LockFreeReceiver(Rx::Ring(shared)),
+    )
+}
+
+fn list<T: Send>(segment: usize) -> (LockFreeSender<T>, LockFreeReceiver<T>) {
+    let shared = Shared::new(List::new(segment), false);
+    (
+        LockFreeSender(Tx::List(shared.clone())),
+        LockFreeReceiver(Rx::List(shared)),
+    )
+}
+
+impl Backend for LockFree {
+    type Sender<T: Send + 'static> = LockFreeSender<T>;
+    type Receiver<T: Send + 'static> = LockFreeReceiver<T>;
+
+    fn bounded<T: Send + 'static>(cap: usize) -> (LockFreeSender<T>, LockFreeReceiver<T>) {
+        mpmc(cap)
+    }
+
+    /// A list of MPMC rings of 256 slots each, which never fills up.
+    fn unbounded<T: Send + 'static>() -> (LockFreeSender<T>, LockFreeReceiver<T>) {
+        list(SEGMENT)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{list, spsc, List, LockFree, Mpmc, Ring, Spsc};
+    use channel::{Backend, Receiver, Sender, TryRecvError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use {Builder, ThreadPool};
+
+    #[test]
+    fn test_rings_wrap() {
+        let rings: Vec<Box<dyn Ring<usize>>> = vec![Box::new(Spsc::new(3)), Box::new(Mpmc::new(3))];
+        for ring in rings {
+            for lap in 0..5 {
+                for x in 0..3 {
+                    assert_eq!(ring.try_push(x), Ok(lap * 3 + x));
+                }
+                assert!(ring.is_full());
+                assert_eq!(ring.try_push(9), Err(9));
+                assert_eq!((ring.try_pop(), ring.try_pop()), (Some(0), Some(1)));
+                assert_eq!((ring.try_pop(), ring.try_pop()), (Some(2), None));
+            }
+        }
+    }
+
+    #[test]
+    fn test_spsc_stress() {
+        let (tx, rx) = spsc(7);
+        let producer = thread::spawn(move || {
+            for x in 0..200_000u64 {
+                tx.send(x).unwrap();
+            }
+        });
+        for x in 0..200_000u64 {
+            assert_eq!(rx.recv(), Ok(x));
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_mpmc_stress() {
+        for &cap in &[1, 2, 64] {
+            stress(LockFree::bounded::<usize>(cap));
+        }
+    }
+
+    #[test]
+    fn test_list_stress() {
+        // Rings this small are closed and unlinked all the time.
+        for &segment in &[1, 2, 64] {
+            stress(list::<usize>(segment));
+        }
+    }
+
+    fn stress<S, R>((tx, rx): (S, R))
+    where
+        S: Sender<usize> + 'static,
+        R: Receiver<usize> + 'static,
+    {
+        const PRODUCERS: usize = 6;
+        const PER_PRODUCER: usize = 50_000;
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for x in 0..PER_PRODUCER {
+                        tx.send(p * PER_PRODUCER + x).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    // Each producer's elements must come out in the order they went in.
+                    let mut last = [None; PRODUCERS];
+                    let mut got = Vec::new();
+                    while let Ok(x) = rx.recv() {
+                        let p = x / PER_PRODUCER;
+                        assert!(last[p].is_none_or(|l| l < x));
+                        last[p] = Some(x);
+                        got.push(x);
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        let mut got: Vec<usize> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        got.sort();
+        assert_eq!(got, (0..PRODUCERS * PER_PRODUCER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_list_reuses_its_ring() {
+        let list = List::new(4);
+        let first = list.head.load(Ordering::SeqCst);
+        // A consumer that keeps up leaves the first ring in place.
+        for x in 0..100 {
+            assert_eq!(list.try_push(x), Ok(x));
+            assert_eq!(list.try_pop(), Some(x));
+        }
+        assert_eq!(list.tail.load(Ordering::SeqCst), first);
+
+        // One that falls behind makes the list grow, and drained rings are freed on the way.
+        for x in 0..10 {
+            assert_eq!(list.try_push(x), Ok(100 + x));
+        }
+        assert!(!list.is_full());
+        assert_eq!(list.pushed() - list.popped(), 10);
+        assert!(list.tail.load(Ordering::SeqCst) != first);
+        for x in 0..10 {
+            assert_eq!(list.try_pop(), Some(x));
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.try_pop(), None);
+        assert_eq!(list.head.load(Ordering::SeqCst), list.tail.load(Ordering::SeqCst));
+        assert!(list.retired.load(Ordering::SeqCst).is_null());
+    }
+
+    #[test]
+    fn test_pool_jobs_through_the_ring() {
+        // Jobs that queue jobs, from every worker at once, on a pool whose queue is a list of
+        // rings.
+        const OUTER: usize = 16;
+        const INNER: usize = 5_000;
+        let pool: ThreadPool<LockFree> = Builder::new().num_threads(4).build_with_channel();
+        let ran = Arc::new(AtomicUsize::new(0));
+        for _ in 0..OUTER {
+            let (inner, ran) = (pool.clone(), ran.clone());
+            pool.execute(move || {
+                for _ in 0..INNER {
+                    let ran = ran.clone();
+                    inner.execute(move || {
+                        ran.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+            });
+        }
+        pool.join().unwrap();
+        assert_eq!(ran.load(Ordering::SeqCst), OUTER * INNER);
+        assert_eq!(pool.queued_count(), 0);
+    }
+
+    #[test]
+    fn test_rendezvous() {
+        let (tx, rx) = LockFree::bounded::<usize>(0);
+        let taken = Arc::new(AtomicUsize::new(0));
+        let consumer = {
+            let taken = taken.clone();
+            thread::spawn(move || {
+                for x in 0..1000 {
+                    assert_eq!(rx.recv(), Ok(x));
+                    taken.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        for x in 0..1000 {
+            tx.send(x).unwrap();
+            // The consumer has at least taken x, though it may not have counted it yet.
+            assert!(taken.load(Ordering::SeqCst) + 1 >= x);
+        }
+        consumer.join().unwrap();
+        assert!(tx.send(0).is_err());
+    }
+
+    #[test]
+    fn test_drops_leftovers() {
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = LockFree::bounded(8);
+        let (ptx, prx) = spsc(8);
+        // Spread over three rings.
+        let (ltx, lrx) = list(2);
+        for _ in 0..5 {
+            tx.send(Counted(dropped.clone())).ok().unwrap();
+            ptx.send(Counted(dropped.clone())).ok().unwrap();
+            ltx.send(Counted(dropped.clone())).ok().unwrap();
+        }
+        drop(rx.recv().unwrap());
+        drop(prx.recv().unwrap());
+        drop(lrx.recv().unwrap());
+        assert_eq!(dropped.load(Ordering::SeqCst), 3);
+        drop((tx, rx, ptx, prx, ltx, lrx));
+        assert_eq!(dropped.load(Ordering::SeqCst), 15);
+    }
+
+    #[test]
+    fn test_unbounded_never_fills() {
+        // More jobs than any ring the pool could have been given are queued while its only
+        // worker is held up; a full job queue would block execute here for good.
+        const JOBS: usize = (1 << 16) + 1000;
+        let pool = Builder::new().num_threads(1).build_with_channel::<LockFree>();
+        let (started, running) = mpsc::channel();
+        let (go, wait) = mpsc::channel::<()>();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = wait.recv();
+        });
+        running.recv().unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+        for _ in 0..JOBS {
+            let ran = ran.clone();
+            pool.execute(move || {
+                ran.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        assert_eq!(pool.queued_count(), JOBS);
+        drop(go);
+        pool.join().unwrap();
+        assert_eq!(ran.load(Ordering::SeqCst), JOBS);
+    }
+}
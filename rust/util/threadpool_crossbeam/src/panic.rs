@@ -0,0 +1,103 @@
+//! Panics caught in pool jobs.
+//!
+//! A pool built with [`Builder::capture_panics`] runs every job under `catch_unwind`. The
+//! worker-thread survives the panic and its payload is kept as a [`JobPanic`] until the next
+//! [`ThreadPool::join`] returns it. With [`Builder::fail_fast`] the first panic also cancels the
+//! pool, so the jobs still queued are dropped.
+//!
+//! ```
+//! let pool = threadpool::Builder::new()
+//!     .num_threads(2)
+//!     .capture_panics(true)
+//!     .build();
+//! pool.execute(|| panic!("row 3 is missing"));
+//! let panics = pool.join().unwrap_err();
+//! assert_eq!(panics[0].message(), "row 3 is missing");
+//! assert!(pool.join().is_ok());
+//! ```
+//!
+//! [`Builder::capture_panics`]: ../struct.Builder.html#method.capture_panics
+//! [`Builder::fail_fast`]: ../struct.Builder.html#method.fail_fast
+//! [`JobPanic`]: struct.JobPanic.html
+//! [`ThreadPool::join`]: ../struct.ThreadPool.html#method.join
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+/// The payload of a panic in a pool job and the thread it happened on.
+pub struct JobPanic {
+    payload: Box<dyn Any + Send + 'static>,
+    thread: Option<String>,
+}
+
+impl JobPanic {
+    pub(crate) fn new(payload: Box<dyn Any + Send + 'static>, thread: Option<String>) -> JobPanic {
+        JobPanic {
+            payload,
+            thread,
+        }
+    }
+
+    /// The message the job panicked with, if the payload is a string as it is for `panic!`.
+    pub fn message(&self) -> &str {
+        if let Some(s) = self.payload.downcast_ref::<&'static str>() {
+            s
+        } else if let Some(s) = self.payload.downcast_ref::<String>() {
+            s
+        } else {
+            "Box<dyn Any>"
+        }
+    }
+
+    /// The name of the worker-thread the job ran on, if the pool names its threads.
+    pub fn thread(&self) -> Option<&str> {
+        self.thread.as_ref().map(|s| &s[..])
+    }
+
+    /// The payload, to resume the panic with `std::panic::resume_unwind`.
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> {
+        self.payload
+    }
+}
+
+impl fmt::Debug for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobPanic")
+            .field("message", &self.message())
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+
+impl fmt::Display for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.thread {
+            Some(ref name) => write!(f, "job panicked on thread '{}': {}", name, self.message()),
+            None => write!(f, "job panicked: {}", self.message()),
+        }
+    }
+}
+
+impl Error for JobPanic {}
+
+#[cfg(test)]
+mod test {
+    use super::JobPanic;
+
+    #[test]
+    fn test_message() {
+        let literal = JobPanic::new(Box::new("literal"), None);
+        assert_eq!(literal.message(), "literal");
+        assert_eq!(literal.to_string(), "job panicked: literal");
+
+        let formatted = JobPanic::new(Box::new(format!("row {}", 3)), Some("worker".into()));
+        assert_eq!(formatted.message(), "row 3");
+        assert_eq!(formatted.thread(), Some("worker"));
+        assert_eq!(formatted.to_string(), "job panicked on thread 'worker': row 3");
+
+        let other = JobPanic::new(Box::new(42), None);
+        assert_eq!(other.message(), "Box<dyn Any>");
+        assert_eq!(*other.into_payload().downcast::<i32>().unwrap(), 42);
+    }
+}
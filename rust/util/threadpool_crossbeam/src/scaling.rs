@@ -0,0 +1,223 @@
+//! Growing and shrinking a pool with its load.
+//!
+//! A pool built with [`Builder::auto_scale`] starts a monitor thread next to its workers. The
+//! monitor adds workers, up to the maximum, when more than `threshold` jobs have been waiting for
+//! `grow_after`. It retires workers, down to the minimum, that have had nothing to do for
+//! `keep_alive`. Every change is reported to the `on_resize` callback.
+//!
+//! The monitor works through [`ThreadPool::max_count`], so the pool can still be resized by hand
+//! with [`ThreadPool::set_num_threads`]; the monitor carries on from the new size.
+//!
+//! ```
+//! use threadpool::scaling::AutoScale;
+//! use std::time::Duration;
+//!
+//! let pool = threadpool::Builder::new()
+//!     .auto_scale(
+//!         AutoScale::new(1, 8)
+//!             .threshold(4)
+//!             .keep_alive(Duration::from_secs(10))
+//!             .on_resize(|r| println!("{} -> {} workers", r.from, r.to)),
+//!     )
+//!     .build();
+//! assert_eq!(pool.max_count(), 1);
+//! ```
+//!
+//! [`Builder::auto_scale`]: ../struct.Builder.html#method.auto_scale
+//! [`ThreadPool::max_count`]: ../struct.ThreadPool.html#method.max_count
+//! [`ThreadPool::set_num_threads`]: ../struct.ThreadPool.html#method.set_num_threads
+
+use std::cmp;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A change in the number of workers of an auto-scaled pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resize {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// When an auto-scaled pool grows and shrinks.
+#[derive(Clone)]
+pub struct AutoScale {
+    pub(crate) min: usize,
+    pub(crate) max: usize,
+    threshold: usize,
+    grow_after: Duration,
+    keep_alive: Duration,
+    on_resize: Option<Arc<dyn Fn(Resize) + Send + Sync>>,
+}
+
+impl AutoScale {
+    /// Keeps between `min` and `max` workers. By default the pool grows as soon as a job has
+    /// been waiting for 10 ms and retires workers after a minute without work.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is 0 or greater than `max`.
+    pub fn new(min: usize, max: usize) -> AutoScale {
+        assert!(min >= 1 && min <= max, "need 1 <= min <= max");
+        AutoScale {
+            min,
+            max,
+            threshold: 0,
+            grow_after: Duration::from_millis(10),
+            keep_alive: Duration::from_secs(60),
+            on_resize: None,
+        }
+    }
+
+    /// How many jobs may wait without the pool growing.
+    pub fn threshold(mut self, jobs: usize) -> AutoScale {
+        self.threshold = jobs;
+        self
+    }
+
+    /// How long the queue has to stay above the threshold before the pool grows.
+    pub fn grow_after(mut self, wait: Duration) -> AutoScale {
+        self.grow_after = wait;
+        self
+    }
+
+    /// How long a worker has to be idle before it is retired.
+    pub fn keep_alive(mut self, idle: Duration) -> AutoScale {
+        self.keep_alive = idle;
+        self
+    }
+
+    /// Called on the monitor thread after every resize.
+    pub fn on_resize<F>(mut self, f: F) -> AutoScale
+    where
+        F: Fn(Resize) + Send + Sync + 'static,
+    {
+        self.on_resize = Some(Arc::new(f));
+        self
+    }
+
+    // How often the monitor looks at the pool.
+    pub(crate) fn tick(&self) -> Duration {
+        cmp::max(
+            cmp::min(self.grow_after, self.keep_alive) / 4,
+            Duration::from_millis(1),
+        )
+    }
+
+    pub(crate) fn report(&self, from: usize, to: usize) {
+        if let Some(ref f) = self.on_resize {
+            f(Resize { from, to });
+        }
+    }
+}
+
+impl fmt::Debug for AutoScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AutoScale")
+            .field("min", &self.min)
+            .field("max", &self.max)
+            .field("threshold", &self.threshold)
+            .field("grow_after", &self.grow_after)
+            .field("keep_alive", &self.keep_alive)
+            .finish()
+    }
+}
+
+// What the monitor has seen of the pool since its last resize.
+pub(crate) struct Monitor {
+    policy: AutoScale,
+    // Since when more than `threshold` jobs have been waiting.
+    busy_since: Option<Instant>,
+    // Since when some workers have been idle, and the fewest idle since.
+    idle_since: Option<Instant>,
+    idle: usize,
+}
+
+impl Monitor {
+    pub(crate) fn new(policy: AutoScale) -> Monitor {
+        Monitor {
+            policy,
+            busy_since: None,
+            idle_since: None,
+            idle: 0,
+        }
+    }
+
+    pub(crate) fn policy(&self) -> &AutoScale {
+        &self.policy
+    }
+
+    /// The size the pool should have, given that `threads` workers are running `active` jobs
+    /// and `queued` are waiting. Returns `threads` while nothing needs to change.
+    pub(crate) fn target(&mut self, now: Instant, threads: usize, active: usize, queued: usize) -> usize {
+        let p = &self.policy;
+        if queued > p.threshold && threads < p.max {
+            let since = *self.busy_since.get_or_insert(now);
+            self.idle_since = None;
+            if now.duration_since(since) >= p.grow_after {
+                self.busy_since = None;
+                return cmp::min(p.max, threads + queued - p.threshold);
+            }
+            return threads;
+        }
+        self.busy_since = None;
+
+        let idle = threads.saturating_sub(active + queued);
+        if idle == 0 || threads <= p.min {
+            self.idle_since = None;
+            return threads;
+        }
+        match self.idle_since {
+            Some(since) => {
+                self.idle = cmp::min(self.idle, idle);
+                if now.duration_since(since) >= p.keep_alive {
+                    self.idle_since = None;
+                    return threads - cmp::min(self.idle, threads - p.min);
+                }
+            }
+            None => {
+                self.idle_since = Some(now);
+                self.idle = idle;
+            }
+        }
+        threads
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AutoScale, Monitor};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_monitor_target() {
+        let ms = Duration::from_millis;
+        let policy = AutoScale::new(2, 6)
+            .threshold(1)
+            .grow_after(ms(10))
+            .keep_alive(ms(100));
+        let mut m = Monitor::new(policy);
+        let t = Instant::now();
+
+        // A queue has to stay long enough, and grows the pool by the jobs over the threshold.
+        assert_eq!(m.target(t, 2, 2, 3), 2);
+        assert_eq!(m.target(t + ms(5), 2, 2, 3), 2);
+        assert_eq!(m.target(t + ms(10), 2, 2, 3), 4);
+        assert_eq!(m.target(t + ms(20), 4, 4, 9), 4);
+        assert_eq!(m.target(t + ms(30), 4, 4, 9), 6);
+
+        // Only the workers idle over the whole keep-alive are retired, never below the minimum.
+        assert_eq!(m.target(t + ms(40), 6, 3, 0), 6);
+        assert_eq!(m.target(t + ms(90), 6, 5, 0), 6);
+        assert_eq!(m.target(t + ms(140), 6, 0, 0), 5);
+        assert_eq!(m.target(t + ms(150), 5, 0, 0), 5);
+        assert_eq!(m.target(t + ms(250), 5, 0, 0), 2);
+        assert_eq!(m.target(t + ms(400), 2, 0, 0), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_min_above_max_panics() {
+        AutoScale::new(3, 2);
+    }
+}
@@ -0,0 +1,494 @@
+//! Counters for where a run spends its time: messages and blocked time per channel, how long
+//! jobs wait in the pool's queue before a worker picks them up, and how busy each worker is in
+//! every phase of a phased computation.
+//!
+//! Nothing is recorded unless the crate is built with the `instrument` feature. Without it the
+//! handles in this module are empty and every call compiles to nothing, so callers use them
+//! unconditionally.
+//!
+//! Channels are counted by label: every channel wrapped with the same label adds to the same
+//! row of the report.
+//!
+//! ```
+//! use threadpool::channel::{Backend, Crossbeam, Receiver, Sender};
+//! use threadpool::stats::{self, Format};
+//!
+//! let (tx, rx) = stats::counted("numbers", Crossbeam::unbounded());
+//! tx.send(1).unwrap();
+//! assert_eq!(rx.recv(), Ok(1));
+//! println!("{}", stats::report(Format::Table));
+//! ```
+
+use channel::{Receiver, RecvError, SendError, Sender, TryRecvError};
+use trace;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+#[cfg(feature = "instrument")]
+use std::time::Instant;
+
+#[cfg(feature = "instrument")]
+mod registry {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    pub struct ChannelCounters {
+        pub sent: AtomicU64,
+        pub received: AtomicU64,
+        pub send_ns: AtomicU64,
+        pub recv_ns: AtomicU64,
+    }
+
+    #[derive(Default)]
+    pub struct Phase {
+        pub wall_ns: u64,
+        pub busy_ns: Vec<u64>,
+    }
+
+    #[derive(Default)]
+    pub struct Registry {
+        pub channels: Mutex<Vec<(&'static str, Arc<ChannelCounters>)>>,
+        pub jobs: AtomicU64,
+        pub queued_ns: AtomicU64,
+        pub max_queued_ns: AtomicU64,
+        pub phases: Mutex<Vec<Phase>>,
+    }
+
+    pub fn get() -> &'static Registry {
+        static REGISTRY: std::sync::OnceLock<Registry> = std::sync::OnceLock::new();
+        REGISTRY.get_or_init(Registry::default)
+    }
+
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn load(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the crate was built with the `instrument` feature.
+pub fn enabled() -> bool {
+    cfg!(feature = "instrument")
+}
+
+/// A point in time, taken only when instrumenting.
+#[derive(Clone, Copy, Debug)]
+pub struct Stamp {
+    #[cfg(feature = "instrument")]
+    at: Instant,
+}
+
+impl Stamp {
+    pub fn now() -> Stamp {
+        Stamp {
+            #[cfg(feature = "instrument")]
+            at: Instant::now(),
+        }
+    }
+
+    /// Time since the stamp was taken; zero when not instrumenting.
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(feature = "instrument")]
+        return self.at.elapsed();
+        #[cfg(not(feature = "instrument"))]
+        return Duration::from_secs(0);
+    }
+}
+
+/// Message counts and blocked time for every channel with one label.
+#[derive(Clone)]
+pub struct ChannelStats {
+    label: &'static str,
+    #[cfg(feature = "instrument")]
+    counters: std::sync::Arc<registry::ChannelCounters>,
+}
+
+impl ChannelStats {
+    pub fn new(label: &'static str) -> ChannelStats {
+        #[cfg(feature = "instrument")]
+        {
+            let mut channels = registry::get().channels.lock().unwrap();
+            let counters = match channels.iter().find(|(l, _)| *l == label) {
+                Some((_, counters)) => counters.clone(),
+                None => {
+                    let counters = std::sync::Arc::default();
+                    channels.push((label, std::sync::Arc::clone(&counters)));
+                    counters
+                }
+            };
+            ChannelStats { label, counters }
+        }
+        #[cfg(not(feature = "instrument"))]
+        {
+            ChannelStats { label }
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    /// Records one message sent, `blocked` being how long the send took.
+    pub fn sent(&self, blocked: Duration) {
+        #[cfg(feature = "instrument")]
+        {
+            registry::add(&self.counters.sent, 1);
+            registry::add(&self.counters.send_ns, blocked.as_nanos() as u64);
+        }
+        let _ = blocked;
+    }
+
+    /// Records one message received, `blocked` being how long the receive waited.
+    pub fn received(&self, blocked: Duration) {
+        #[cfg(feature = "instrument")]
+        {
+            registry::add(&self.counters.received, 1);
+            registry::add(&self.counters.recv_ns, blocked.as_nanos() as u64);
+        }
+        let _ = blocked;
+    }
+}
+
+/// A [`Sender`](../channel/trait.Sender.html) that records what goes through it.
+pub struct CountedSender<S> {
+    inner: S,
+    stats: ChannelStats,
+}
+
+/// A [`Receiver`](../channel/trait.Receiver.html) that records what comes out of it.
+pub struct CountedReceiver<R> {
+    inner: R,
+    stats: ChannelStats,
+}
+
+/// Wraps both ends of a channel so they are counted under `label`.
+pub fn counted<S, R>(label: &'static str, (tx, rx): (S, R)) -> (CountedSender<S>, CountedReceiver<R>) {
+    let stats = ChannelStats::new(label);
+    (
+        CountedSender {
+            inner: tx,
+            stats: stats.clone(),
+        },
+        CountedReceiver { inner: rx, stats },
+    )
+}
+
+impl<S: Clone> Clone for CountedSender<S> {
+    fn clone(&self) -> Self {
+        CountedSender {
+            inner: self.inner.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<R: Clone> Clone for CountedReceiver<R> {
+    fn clone(&self) -> Self {
+        CountedReceiver {
+            inner: self.inner.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T, S: Sender<T>> Sender<T> for CountedSender<S> {
+    fn send(&self, x: T) -> Result<(), SendError<T>> {
+        let start = Stamp::now();
+        let span = trace::span("send", self.stats.label());
+        let sent = self.inner.send(x);
+        drop(span);
+        if sent.is_ok() {
+            self.stats.sent(start.elapsed());
+        }
+        sent
+    }
+}
+
+impl<T, R: Receiver<T>> Receiver<T> for CountedReceiver<R> {
+    fn recv(&self) -> Result<T, RecvError> {
+        let start = Stamp::now();
+        let span = trace::span("recv", self.stats.label());
+        let x = self.inner.recv();
+        drop(span);
+        if x.is_ok() {
+            self.stats.received(start.elapsed());
+        }
+        x
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let x = self.inner.try_recv();
+        if x.is_ok() {
+            self.stats.received(Duration::from_secs(0));
+        }
+        x
+    }
+}
+
+/// Records that a job waited `queued` in the pool's queue before a worker took it.
+pub fn job_dequeued(queued: Duration) {
+    #[cfg(feature = "instrument")]
+    {
+        let r = registry::get();
+        let ns = queued.as_nanos() as u64;
+        registry::add(&r.jobs, 1);
+        registry::add(&r.queued_ns, ns);
+        r.max_queued_ns
+            .fetch_max(ns, std::sync::atomic::Ordering::Relaxed);
+    }
+    let _ = queued;
+}
+
+/// Records that phase `phase` took `wall` from start to finish.
+pub fn phase(phase: usize, wall: Duration) {
+    #[cfg(feature = "instrument")]
+    {
+        let mut phases = registry::get().phases.lock().unwrap();
+        if phases.len() <= phase {
+            phases.resize_with(phase + 1, Default::default);
+        }
+        phases[phase].wall_ns += wall.as_nanos() as u64;
+    }
+    let _ = (phase, wall);
+}
+
+/// Records that `worker` spent `busy` working in phase `phase`. Whatever is left of the phase's
+/// wall time counts as idle.
+pub fn busy(worker: usize, phase: usize, busy: Duration) {
+    #[cfg(feature = "instrument")]
+    {
+        let mut phases = registry::get().phases.lock().unwrap();
+        if phases.len() <= phase {
+            phases.resize_with(phase + 1, Default::default);
+        }
+        let busy_ns = &mut phases[phase].busy_ns;
+        if busy_ns.len() <= worker {
+            busy_ns.resize(worker + 1, 0);
+        }
+        busy_ns[worker] += busy.as_nanos() as u64;
+    }
+    let _ = (worker, phase, busy);
+}
+
+/// Forgets everything recorded so far.
+pub fn reset() {
+    #[cfg(feature = "instrument")]
+    {
+        let r = registry::get();
+        r.channels.lock().unwrap().clear();
+        r.phases.lock().unwrap().clear();
+        for counter in &[&r.jobs, &r.queued_ns, &r.max_queued_ns] {
+            counter.store(0, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+/// How [`report`](fn.report.html) lays out the numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown stats format {}", s)),
+        }
+    }
+}
+
+struct Snapshot {
+    // label, sent, received, send ns, recv ns
+    channels: Vec<(&'static str, u64, u64, u64, u64)>,
+    jobs: u64,
+    queued_ns: u64,
+    max_queued_ns: u64,
+    // wall ns, busy ns per worker
+    phases: Vec<(u64, Vec<u64>)>,
+}
+
+fn snapshot() -> Snapshot {
+    #[cfg(feature = "instrument")]
+    {
+        use self::registry::load;
+        let r = registry::get();
+        Snapshot {
+            channels: r
+                .channels
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(l, c)| {
+                    (
+                        *l,
+                        load(&c.sent),
+                        load(&c.received),
+                        load(&c.send_ns),
+                        load(&c.recv_ns),
+                    )
+                })
+                .collect(),
+            jobs: load(&r.jobs),
+            queued_ns: load(&r.queued_ns),
+            max_queued_ns: load(&r.max_queued_ns),
+            phases: r
+                .phases
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|p| (p.wall_ns, p.busy_ns.clone()))
+                .collect(),
+        }
+    }
+    #[cfg(not(feature = "instrument"))]
+    Snapshot {
+        channels: Vec::new(),
+        jobs: 0,
+        queued_ns: 0,
+        max_queued_ns: 0,
+        phases: Vec::new(),
+    }
+}
+
+fn secs(ns: u64) -> f64 {
+    ns as f64 / 1e9
+}
+
+/// Everything recorded so far, as a table for people or JSON for scripts.
+pub fn report(format: Format) -> String {
+    let s = snapshot();
+    let nworkers = s.phases.iter().map(|(_, b)| b.len()).max().unwrap_or(0);
+    let mut out = String::new();
+
+    match format {
+        Format::Table => {
+            if !enabled() {
+                return "no stats: built without the instrument feature\n".to_string();
+            }
+            writeln!(
+                out,
+                "{:<12} {:>10} {:>10} {:>12} {:>12}",
+                "channel", "sent", "received", "send wait s", "recv wait s"
+            )
+            .unwrap();
+            for &(label, sent, received, send_ns, recv_ns) in &s.channels {
+                writeln!(
+                    out,
+                    "{:<12} {:>10} {:>10} {:>12.6} {:>12.6}",
+                    label,
+                    sent,
+                    received,
+                    secs(send_ns),
+                    secs(recv_ns)
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "\npool jobs: {}, mean queue wait {:.3} us, max {:.3} us",
+                s.jobs,
+                s.queued_ns as f64 / s.jobs.max(1) as f64 / 1e3,
+                s.max_queued_ns as f64 / 1e3
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "\n{:<8} {:>8} {:>12} {:>12}",
+                "worker", "phases", "busy s", "idle s"
+            )
+            .unwrap();
+            for w in 0..nworkers {
+                let mut phases = 0;
+                let (mut busy, mut idle) = (0, 0);
+                for (wall, b) in &s.phases {
+                    let b = b.get(w).copied().unwrap_or(0);
+                    phases += (b > 0) as usize;
+                    busy += b;
+                    idle += wall.saturating_sub(b);
+                }
+                writeln!(
+                    out,
+                    "{:<8} {:>8} {:>12.6} {:>12.6}",
+                    w,
+                    phases,
+                    secs(busy),
+                    secs(idle)
+                )
+                .unwrap();
+            }
+        }
+        Format::Json => {
+            write!(out, "{{\"enabled\":{},\"channels\":[", enabled()).unwrap();
+            for (k, &(label, sent, received, send_ns, recv_ns)) in s.channels.iter().enumerate() {
+                write!(
+                    out,
+                    "{}{{\"label\":\"{}\",\"sent\":{},\"received\":{},\"send_wait_ns\":{},\"recv_wait_ns\":{}}}",
+                    if k > 0 { "," } else { "" },
+                    label,
+                    sent,
+                    received,
+                    send_ns,
+                    recv_ns
+                )
+                .unwrap();
+            }
+            write!(
+                out,
+                "],\"pool\":{{\"jobs\":{},\"queue_wait_ns\":{},\"max_queue_wait_ns\":{}}},\"phases\":[",
+                s.jobs, s.queued_ns, s.max_queued_ns
+            )
+            .unwrap();
+            for (k, (wall, busy)) in s.phases.iter().enumerate() {
+                let mut busy = busy.clone();
+                busy.resize(nworkers, 0);
+                let idle: Vec<u64> = busy.iter().map(|b| wall.saturating_sub(*b)).collect();
+                write!(
+                    out,
+                    "{}{{\"wall_ns\":{},\"busy_ns\":{:?},\"idle_ns\":{:?}}}",
+                    if k > 0 { "," } else { "" },
+                    wall,
+                    busy,
+                    idle
+                )
+                .unwrap();
+            }
+            out.push_str("]}\n");
+        }
+    }
+    out
+}
+
+#[cfg(all(test, feature = "instrument"))]
+mod test {
+    use super::{busy, counted, job_dequeued, phase, report, reset, Format};
+    use channel::{Backend, Crossbeam, Receiver, Sender};
+    use std::time::Duration;
+
+    #[test]
+    fn test_report() {
+        reset();
+        let (tx, rx) = counted("test", Crossbeam::unbounded());
+        for x in 0..3 {
+            tx.send(x).unwrap();
+        }
+        assert_eq!(rx.recv(), Ok(0));
+        assert_eq!(rx.try_recv(), Ok(1));
+        job_dequeued(Duration::from_micros(5));
+        phase(0, Duration::from_nanos(100));
+        busy(1, 0, Duration::from_nanos(60));
+
+        let json = report(Format::Json);
+        assert!(json.contains("{\"label\":\"test\",\"sent\":3,\"received\":2,"));
+        assert!(json.contains("\"max_queue_wait_ns\":"));
+        assert!(json.contains("{\"wall_ns\":100,\"busy_ns\":[0, 60],\"idle_ns\":[100, 40]}"));
+        assert!(report(Format::Table).contains("test"));
+    }
+}
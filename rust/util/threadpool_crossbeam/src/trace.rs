@@ -0,0 +1,244 @@
+//! A timeline of what every thread was doing, in the Chrome trace-event format.
+//!
+//! Recording starts with [`enable`] and stops with [`disable`]; while it is off [`span`] costs one
+//! atomic load. Each span becomes a complete ("X") event on the thread that opened it, and every
+//! thread that records anything gets a name in the timeline. [`write`] saves the events as a JSON
+//! file that `chrome://tracing` or Perfetto open offline.
+//!
+//! The pool records a `job` span around every job it runs, and channels wrapped with
+//! [`stats::counted`] record `send` and `recv` spans under their label.
+//!
+//! ```
+//! use threadpool::trace;
+//!
+//! trace::enable();
+//! {
+//!     let _span = trace::span("work", "example").phase(0);
+//!     // ...
+//! }
+//! assert!(trace::to_json().contains("\"name\":\"work\""));
+//! ```
+//!
+//! [`enable`]: fn.enable.html
+//! [`disable`]: fn.disable.html
+//! [`span`]: fn.span.html
+//! [`write`]: fn.write.html
+//! [`stats::counted`]: ../stats/fn.counted.html
+
+use std::cell::Cell;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_TID: AtomicUsize = AtomicUsize::new(1);
+
+struct Event {
+    name: &'static str,
+    cat: &'static str,
+    tid: usize,
+    // Microseconds since the trace was enabled.
+    ts: f64,
+    dur: f64,
+    phase: Option<usize>,
+}
+
+struct Trace {
+    epoch: Instant,
+    events: Mutex<Vec<Event>>,
+    threads: Mutex<Vec<(usize, String)>>,
+}
+
+fn trace() -> &'static Trace {
+    static TRACE: OnceLock<Trace> = OnceLock::new();
+    TRACE.get_or_init(|| Trace {
+        epoch: Instant::now(),
+        events: Mutex::new(Vec::new()),
+        threads: Mutex::new(Vec::new()),
+    })
+}
+
+thread_local! {
+    static TID: Cell<usize> = const { Cell::new(0) };
+}
+
+// The calling thread's id in the trace, naming the thread on first use.
+fn tid() -> usize {
+    TID.with(|tid| {
+        if tid.get() == 0 {
+            let id = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+            let name = match thread::current().name() {
+                Some(name) => name.to_string(),
+                None => format!("thread {}", id),
+            };
+            trace().threads.lock().unwrap().push((id, name));
+            tid.set(id);
+        }
+        tid.get()
+    })
+}
+
+/// Starts recording spans.
+pub fn enable() {
+    trace();
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Stops recording spans. Spans opened before the call are still recorded when they close.
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Drops the events recorded so far. Threads keep the names they were given.
+pub fn reset() {
+    trace().events.lock().unwrap().clear();
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// An open span; it is recorded when dropped.
+#[must_use = "a span covers the time until it is dropped"]
+pub struct Span {
+    start: Option<Instant>,
+    name: &'static str,
+    cat: &'static str,
+    phase: Option<usize>,
+}
+
+/// Opens a span called `name` in category `cat` on the calling thread.
+pub fn span(name: &'static str, cat: &'static str) -> Span {
+    Span {
+        start: if enabled() { Some(Instant::now()) } else { None },
+        name,
+        cat,
+        phase: None,
+    }
+}
+
+impl Span {
+    /// Tags the span with the phase it belongs to.
+    pub fn phase(mut self, phase: usize) -> Span {
+        self.phase = Some(phase);
+        self
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let t = trace();
+            let event = Event {
+                name: self.name,
+                cat: self.cat,
+                tid: tid(),
+                ts: start.saturating_duration_since(t.epoch).as_nanos() as f64 / 1e3,
+                dur: start.elapsed().as_nanos() as f64 / 1e3,
+                phase: self.phase,
+            };
+            t.events.lock().unwrap().push(event);
+        }
+    }
+}
+
+/// The events recorded so far as a trace-event JSON document.
+pub fn to_json() -> String {
+    let t = trace();
+    let mut out = String::from("{\"traceEvents\":[\n");
+    for (id, name) in t.threads.lock().unwrap().iter() {
+        writeln!(
+            out,
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":{:?}}}}},",
+            id, name
+        )
+        .unwrap();
+    }
+    for e in t.events.lock().unwrap().iter() {
+        write!(
+            out,
+            "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}",
+            e.name, e.cat, e.tid, e.ts, e.dur
+        )
+        .unwrap();
+        if let Some(phase) = e.phase {
+            write!(out, ",\"args\":{{\"phase\":{}}}", phase).unwrap();
+        }
+        out.push_str("},\n");
+    }
+    // Trailing commas are not allowed, so close with an empty metadata event.
+    out.push_str("{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{\"name\":\"gauss\"}}\n]}\n");
+    out
+}
+
+/// Writes the events recorded so far to `path`.
+pub fn write<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    File::create(path)?.write_all(to_json().as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disable, enable, reset, span, to_json};
+    use std::sync::{Mutex, MutexGuard};
+    use std::thread;
+
+    static RECORDING: Mutex<()> = Mutex::new(());
+
+    // Records for one test at a time and turns recording off again when the test is done with
+    // it, even if the test fails, so the tests that follow do not record into the global buffer.
+    struct Recording {
+        _guard: MutexGuard<'static, ()>,
+    }
+
+    impl Recording {
+        fn start() -> Recording {
+            let _guard = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+            enable();
+            Recording { _guard }
+        }
+    }
+
+    impl Drop for Recording {
+        fn drop(&mut self) {
+            disable();
+            reset();
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let _recording = Recording::start();
+        thread::Builder::new()
+            .name("traced".into())
+            .spawn(|| {
+                let _outer = span("outer", "test").phase(3);
+                drop(span("inner", "test"));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let json = to_json();
+        assert!(json.contains("\"args\":{\"name\":\"traced\"}"));
+        assert!(json.contains("\"name\":\"outer\",\"cat\":\"test\",\"ph\":\"X\""));
+        assert!(json.contains(",\"args\":{\"phase\":3}}"));
+        assert!(json.contains("\"name\":\"inner\""));
+    }
+
+    #[test]
+    fn test_disable_and_reset() {
+        let _recording = Recording::start();
+        drop(span("kept", "reset"));
+        disable();
+        drop(span("skipped", "reset"));
+        assert!(to_json().contains("\"name\":\"kept\",\"cat\":\"reset\""));
+        assert!(!to_json().contains("\"name\":\"skipped\""));
+        reset();
+        assert!(!to_json().contains("\"cat\":\"reset\""));
+    }
+}